@@ -0,0 +1,80 @@
+//! Heap allocations per search with move lists reused and, for comparison,
+//! allocated afresh at every node, each next to its node rate. Run with
+//! `cargo bench --bench search_allocations`.
+
+use brainfish_rust::board::Board;
+use brainfish_rust::search::{self, SearchConfig, SearchEvent, SearchLimits, SearchResult};
+use brainfish_rust::tt::TranspositionTable;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Instant;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const POSITIONS: [(&str, u32); 3] = [
+    ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 9),
+    ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 7),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 12),
+];
+
+type SearchFn = fn(
+    &Board,
+    &SearchLimits,
+    &SearchConfig,
+    &TranspositionTable,
+    &AtomicBool,
+    fn(SearchEvent<'_>),
+) -> SearchResult;
+
+// Nodes, allocations and node rate of one search from a cleared table.
+fn measure(run: SearchFn, board: &Board, depth: u32, tt: &TranspositionTable) -> (u64, u64, u64) {
+    let limits = SearchLimits {
+        depth: Some(depth),
+        ..Default::default()
+    };
+    tt.clear();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let result = run(board, &limits, &SearchConfig::default(), tt, &AtomicBool::new(false), |_| {});
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    (result.nodes, allocations, search::nodes_per_second(result.nodes, elapsed))
+}
+
+fn main() {
+    let tt = TranspositionTable::new(16);
+    println!(
+        "{:<72} {:>10} {:>9} {:>10} {:>9} {:>10}",
+        "position", "nodes", "allocs", "nps", "fresh", "fresh nps"
+    );
+    for (fen, depth) in POSITIONS {
+        let board = Board::from_fen(fen).unwrap();
+        let (nodes, allocations, nps) = measure(search::search, &board, depth, &tt);
+        let (_, fresh, fresh_nps) = measure(search::search_allocating, &board, depth, &tt);
+        println!(
+            "{:<72} {:>10} {:>9} {:>10} {:>9} {:>10}",
+            fen, nodes, allocations, nps, fresh, fresh_nps
+        );
+    }
+}
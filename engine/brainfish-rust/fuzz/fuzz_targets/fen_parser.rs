@@ -0,0 +1,24 @@
+//! Feeds arbitrary input to `Board::from_fen`: it must return an error or a
+//! board whose own FEN reads back the same, and never panic.
+//!
+//! Run with `cargo fuzz run fen_parser` from the crate directory.
+#![no_main]
+
+use brainfish_rust::board::Board;
+use brainfish_rust::movegen;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(board) = Board::from_fen(text) else {
+        return;
+    };
+    let fen = board.to_fen();
+    let again = Board::from_fen(&fen).expect("a parsed board's FEN is valid");
+    assert_eq!(again.to_fen(), fen);
+    // Whatever parses must be safe to play from.
+    movegen::generate_legal_moves(&board);
+    board.canonical_fen();
+});
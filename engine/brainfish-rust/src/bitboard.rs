@@ -0,0 +1,246 @@
+use crate::types::{Color, Square};
+
+pub type Bitboard = u64;
+
+pub const FILE_A: Bitboard = 0x0101_0101_0101_0101;
+pub const FILE_H: Bitboard = FILE_A << 7;
+pub const RANK_1: Bitboard = 0xFF;
+pub const RANK_2: Bitboard = RANK_1 << 8;
+pub const RANK_4: Bitboard = RANK_1 << 24;
+pub const RANK_5: Bitboard = RANK_1 << 32;
+pub const RANK_7: Bitboard = RANK_1 << 48;
+pub const RANK_8: Bitboard = RANK_1 << 56;
+pub const DARK_SQUARES: Bitboard = 0xAA55_AA55_AA55_AA55;
+
+// Ray directions as (file delta, rank delta). The first four point towards
+// higher square indices, which matters for picking the nearest blocker.
+const DIRECTIONS: [(i8, i8); 8] = [
+    (0, 1),
+    (1, 0),
+    (1, 1),
+    (-1, 1),
+    (0, -1),
+    (-1, 0),
+    (1, -1),
+    (-1, -1),
+];
+const ORTHOGONAL: [usize; 4] = [0, 1, 4, 5];
+const DIAGONAL: [usize; 4] = [2, 3, 6, 7];
+
+const fn step_attacks(sq: usize, deltas: &[(i8, i8)]) -> Bitboard {
+    let file = (sq % 8) as i8;
+    let rank = (sq / 8) as i8;
+    let mut bb = 0;
+    let mut i = 0;
+    while i < deltas.len() {
+        let f = file + deltas[i].0;
+        let r = rank + deltas[i].1;
+        if f >= 0 && f < 8 && r >= 0 && r < 8 {
+            bb |= 1u64 << (r * 8 + f);
+        }
+        i += 1;
+    }
+    bb
+}
+
+const fn build_step_table(deltas: &[(i8, i8)]) -> [Bitboard; 64] {
+    let mut table = [0; 64];
+    let mut sq = 0;
+    while sq < 64 {
+        table[sq] = step_attacks(sq, deltas);
+        sq += 1;
+    }
+    table
+}
+
+const fn build_rays() -> [[Bitboard; 64]; 8] {
+    let mut rays = [[0; 64]; 8];
+    let mut dir = 0;
+    while dir < 8 {
+        let mut sq = 0;
+        while sq < 64 {
+            let mut f = (sq % 8) as i8 + DIRECTIONS[dir].0;
+            let mut r = (sq / 8) as i8 + DIRECTIONS[dir].1;
+            let mut bb = 0;
+            while f >= 0 && f < 8 && r >= 0 && r < 8 {
+                bb |= 1u64 << (r * 8 + f);
+                f += DIRECTIONS[dir].0;
+                r += DIRECTIONS[dir].1;
+            }
+            rays[dir][sq] = bb;
+            sq += 1;
+        }
+        dir += 1;
+    }
+    rays
+}
+
+const KNIGHT_ATTACKS: [Bitboard; 64] = build_step_table(&[
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+]);
+const KING_ATTACKS: [Bitboard; 64] = build_step_table(&[
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+]);
+const WHITE_PAWN_ATTACKS: [Bitboard; 64] = build_step_table(&[(-1, 1), (1, 1)]);
+const BLACK_PAWN_ATTACKS: [Bitboard; 64] = build_step_table(&[(-1, -1), (1, -1)]);
+const RAYS: [[Bitboard; 64]; 8] = build_rays();
+
+pub fn knight_attacks(sq: Square) -> Bitboard {
+    KNIGHT_ATTACKS[sq.index()]
+}
+
+pub fn king_attacks(sq: Square) -> Bitboard {
+    KING_ATTACKS[sq.index()]
+}
+
+/// Squares attacked by a pawn of `color` standing on `sq`.
+pub fn pawn_attacks(color: Color, sq: Square) -> Bitboard {
+    match color {
+        Color::White => WHITE_PAWN_ATTACKS[sq.index()],
+        Color::Black => BLACK_PAWN_ATTACKS[sq.index()],
+    }
+}
+
+fn ray_attacks(dir: usize, sq: Square, occupancy: Bitboard) -> Bitboard {
+    let ray = RAYS[dir][sq.index()];
+    let blockers = ray & occupancy;
+    if blockers == 0 {
+        return ray;
+    }
+    let nearest = if dir < 4 {
+        blockers.trailing_zeros()
+    } else {
+        63 - blockers.leading_zeros()
+    };
+    ray ^ RAYS[dir][nearest as usize]
+}
+
+pub fn rook_attacks(sq: Square, occupancy: Bitboard) -> Bitboard {
+    ORTHOGONAL
+        .iter()
+        .fold(0, |bb, &dir| bb | ray_attacks(dir, sq, occupancy))
+}
+
+pub fn bishop_attacks(sq: Square, occupancy: Bitboard) -> Bitboard {
+    DIAGONAL
+        .iter()
+        .fold(0, |bb, &dir| bb | ray_attacks(dir, sq, occupancy))
+}
+
+pub fn queen_attacks(sq: Square, occupancy: Bitboard) -> Bitboard {
+    rook_attacks(sq, occupancy) | bishop_attacks(sq, occupancy)
+}
+
+/// Squares ahead of a `color` pawn on `sq`, on its own file and the files
+/// beside it. The pawn is passed if no enemy pawn stands on any of them.
+pub fn passed_pawn_span(color: Color, sq: Square) -> Bitboard {
+    let north_or_south = match color {
+        Color::White => 0,
+        Color::Black => 4,
+    };
+    let ahead = RAYS[north_or_south][sq.index()];
+    ahead | ((ahead << 1) & !FILE_A) | ((ahead >> 1) & !FILE_H)
+}
+
+/// Squares strictly between `a` and `b` when they share a line, else empty.
+pub fn between(a: Square, b: Square) -> Bitboard {
+    (0..8)
+        .find(|&dir| RAYS[dir][a.index()] & b.bitboard() != 0)
+        .map_or(0, |dir| (RAYS[dir][a.index()] ^ RAYS[dir][b.index()]) & !b.bitboard())
+}
+
+/// The whole line through `a` and `b` when they share one, else empty.
+pub fn line(a: Square, b: Square) -> Bitboard {
+    let through = |from: Square, to: Square| {
+        (0..8)
+            .find(|&dir| RAYS[dir][from.index()] & to.bitboard() != 0)
+            .map_or(0, |dir| RAYS[dir][from.index()])
+    };
+    match through(a, b) {
+        0 => 0,
+        ray => ray | through(b, a) | a.bitboard() | b.bitboard(),
+    }
+}
+
+/// Iterates over the squares set in a bitboard, lowest index first.
+pub struct Squares(Bitboard);
+
+impl Iterator for Squares {
+    type Item = Square;
+
+    fn next(&mut self) -> Option<Square> {
+        if self.0 == 0 {
+            return None;
+        }
+        let index = self.0.trailing_zeros() as usize;
+        self.0 &= self.0 - 1;
+        Some(Square::from_index_unchecked(index))
+    }
+}
+
+pub fn squares(bb: Bitboard) -> Squares {
+    Squares(bb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sq(s: &str) -> Square {
+        Square::from_algebraic(s).unwrap()
+    }
+
+    #[test]
+    fn test_step_attacks() {
+        assert_eq!(knight_attacks(sq("a1")).count_ones(), 2);
+        assert_eq!(knight_attacks(sq("d4")).count_ones(), 8);
+        assert_eq!(king_attacks(sq("h8")).count_ones(), 3);
+        assert_eq!(pawn_attacks(Color::White, sq("a2")), sq("b3").bitboard());
+        assert_eq!(pawn_attacks(Color::Black, sq("e5")).count_ones(), 2);
+    }
+
+    #[test]
+    fn test_slider_attacks_stop_at_blockers() {
+        let occupancy = sq("d6").bitboard() | sq("f4").bitboard();
+        let attacks = rook_attacks(sq("d4"), occupancy);
+        assert!(attacks & sq("d6").bitboard() != 0);
+        assert!(attacks & sq("d7").bitboard() == 0);
+        assert!(attacks & sq("f4").bitboard() != 0);
+        assert!(attacks & sq("g4").bitboard() == 0);
+        assert_eq!(bishop_attacks(sq("a1"), 0).count_ones(), 7);
+    }
+
+    #[test]
+    fn test_between() {
+        assert_eq!(between(sq("a1"), sq("a4")), sq("a2").bitboard() | sq("a3").bitboard());
+        assert_eq!(between(sq("h8"), sq("e5")), sq("g7").bitboard() | sq("f6").bitboard());
+        assert_eq!(between(sq("a1"), sq("b3")), 0);
+        assert_eq!(between(sq("d4"), sq("d5")), 0);
+        assert_eq!(line(sq("c3"), sq("e5")).count_ones(), 8);
+        assert_eq!(line(sq("b1"), sq("b7")), FILE_A << 1);
+        assert_eq!(line(sq("a1"), sq("b3")), 0);
+    }
+
+    #[test]
+    fn test_passed_pawn_span() {
+        let ahead = ["a7", "a8", "b7", "b8"].iter().fold(0, |bb, s| bb | sq(s).bitboard());
+        assert_eq!(passed_pawn_span(Color::White, sq("a6")), ahead);
+        assert_eq!(passed_pawn_span(Color::Black, sq("e7")).count_ones(), 18);
+        assert_eq!(passed_pawn_span(Color::Black, sq("h2")).count_ones(), 2);
+        assert_eq!(passed_pawn_span(Color::White, sq("d8")), 0);
+    }
+}
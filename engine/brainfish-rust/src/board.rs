@@ -0,0 +1,1174 @@
+use crate::bitboard::{self, Bitboard};
+use crate::eval::PIECE_VALUES;
+use crate::moves::Move;
+use crate::types::{Color, Piece, PieceKind, Square};
+use crate::zobrist;
+use thiserror::Error;
+
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    #[error("expected 4 or 6 fields, found {0}")]
+    FieldCount(usize),
+    #[error("expected 8 ranks, found {0}")]
+    RankCount(usize),
+    #[error("rank {0} does not describe exactly 8 squares")]
+    RankLength(u8),
+    #[error("invalid piece character '{0}'")]
+    InvalidPiece(char),
+    #[error("invalid side to move '{0}'")]
+    InvalidSideToMove(String),
+    #[error("invalid castling field '{0}'")]
+    InvalidCastling(String),
+    #[error("invalid en passant square '{0}'")]
+    InvalidEnPassant(String),
+    #[error("invalid move counter '{0}'")]
+    InvalidCounter(String),
+    #[error("each side must have exactly one king")]
+    KingCount,
+    #[error("pawns cannot stand on the first or last rank")]
+    PawnOnBackRank,
+    #[error("the side not to move is in check")]
+    OpponentInCheck,
+    #[error("inconsistent board state: {0}")]
+    Inconsistent(&'static str),
+}
+
+// Rook files for each right, indexed by the right's bit position.
+const STANDARD_ROOK_FILES: [u8; 4] = [7, 0, 7, 0];
+
+/// Which castling moves are still allowed, and the file of the rook each one
+/// uses (the a- and h-files in standard chess; anywhere in Chess960).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CastlingRights {
+    flags: u8,
+    rook_files: [u8; 4],
+}
+
+impl Default for CastlingRights {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+impl CastlingRights {
+    pub const WHITE_KINGSIDE: CastlingRights = CastlingRights::from_flags(1);
+    pub const WHITE_QUEENSIDE: CastlingRights = CastlingRights::from_flags(2);
+    pub const BLACK_KINGSIDE: CastlingRights = CastlingRights::from_flags(4);
+    pub const BLACK_QUEENSIDE: CastlingRights = CastlingRights::from_flags(8);
+    pub const NONE: CastlingRights = CastlingRights::from_flags(0);
+    pub const ALL: CastlingRights = CastlingRights::from_flags(15);
+
+    const fn from_flags(flags: u8) -> CastlingRights {
+        CastlingRights {
+            flags,
+            rook_files: STANDARD_ROOK_FILES,
+        }
+    }
+
+    /// Whether every right in `other` is held. Rook files are ignored.
+    pub fn contains(self, other: CastlingRights) -> bool {
+        self.flags & other.flags == other.flags
+    }
+
+    /// Adds the rights in `other`, keeping the rook files already recorded.
+    pub fn insert(&mut self, other: CastlingRights) {
+        self.flags |= other.flags;
+    }
+
+    pub fn remove(&mut self, other: CastlingRights) {
+        self.flags &= !other.flags;
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.flags == 0
+    }
+
+    pub fn bits(self) -> u8 {
+        self.flags
+    }
+
+    /// File of the rook used by `right`, which must be a single right.
+    pub fn rook_file(self, right: CastlingRights) -> u8 {
+        self.rook_files[right.flags.trailing_zeros() as usize]
+    }
+
+    /// Records the rook file for `right`, which must be a single right.
+    pub fn set_rook_file(&mut self, right: CastlingRights, file: u8) {
+        self.rook_files[right.flags.trailing_zeros() as usize] = file;
+    }
+
+    /// Whether `right` uses the rook from the corner, as in standard chess.
+    pub fn is_standard(self, right: CastlingRights) -> bool {
+        self.rook_file(right) == STANDARD_ROOK_FILES[right.flags.trailing_zeros() as usize]
+    }
+
+    pub fn kingside(color: Color) -> CastlingRights {
+        match color {
+            Color::White => Self::WHITE_KINGSIDE,
+            Color::Black => Self::BLACK_KINGSIDE,
+        }
+    }
+
+    pub fn queenside(color: Color) -> CastlingRights {
+        match color {
+            Color::White => Self::WHITE_QUEENSIDE,
+            Color::Black => Self::BLACK_QUEENSIDE,
+        }
+    }
+
+    /// Rights that survive a move touching `sq` (as origin or destination).
+    /// Kings castle from the e-file; rooks from their recorded files.
+    fn after_touching(mut self, sq: Square) -> CastlingRights {
+        for color in Color::ALL {
+            let back = match color {
+                Color::White => 0,
+                Color::Black => 7,
+            };
+            if sq.rank() != back {
+                continue;
+            }
+            for right in [Self::kingside(color), Self::queenside(color)] {
+                if sq.file() == 4 || sq.file() == self.rook_file(right) {
+                    self.remove(right);
+                }
+            }
+        }
+        self
+    }
+}
+
+/// Everything `make_move` destroys, so `unmake_move` can restore it.
+#[derive(Clone, Copy, Debug)]
+pub struct Undo {
+    captured: Option<Piece>,
+    castling: CastlingRights,
+    en_passant: Option<Square>,
+    halfmove_clock: u32,
+    hash: u64,
+}
+
+/// How a move checks the enemy king; see `Board::check_type`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckType {
+    None,
+    /// Only the moved piece gives check; when castling, the rook.
+    Direct,
+    /// Moving uncovered a check by a piece that stayed put.
+    Discovered,
+    /// Two pieces give check at once, so only a king move answers it.
+    Double,
+}
+
+/// Piece counts for both sides.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MaterialCount {
+    counts: [[u32; 6]; 2],
+}
+
+impl MaterialCount {
+    pub fn count(&self, color: Color, kind: PieceKind) -> u32 {
+        self.counts[color.index()][kind.index()]
+    }
+
+    /// Knights, bishops, rooks and queens of `color`, in centipawns.
+    pub fn non_pawn_material(&self, color: Color) -> i32 {
+        [PieceKind::Knight, PieceKind::Bishop, PieceKind::Rook, PieceKind::Queen]
+            .iter()
+            .map(|&kind| self.count(color, kind) as i32 * PIECE_VALUES[kind.index()])
+            .sum()
+    }
+
+    pub fn total_non_pawn_material(&self) -> i32 {
+        self.non_pawn_material(Color::White) + self.non_pawn_material(Color::Black)
+    }
+
+    /// True when neither side can possibly mate: bare kings, or a single
+    /// knight or bishop against a bare king.
+    pub fn is_insufficient(&self) -> bool {
+        let heavy_or_pawns = Color::ALL.iter().any(|&color| {
+            self.count(color, PieceKind::Pawn)
+                + self.count(color, PieceKind::Rook)
+                + self.count(color, PieceKind::Queen)
+                > 0
+        });
+        let minors: u32 = Color::ALL
+            .iter()
+            .map(|&c| self.count(c, PieceKind::Knight) + self.count(c, PieceKind::Bishop))
+            .sum();
+        !heavy_or_pawns && minors <= 1
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Board {
+    by_color: [Bitboard; 2],
+    by_kind: [Bitboard; 6],
+    squares: [Option<Piece>; 64],
+    side_to_move: Color,
+    castling: CastlingRights,
+    en_passant: Option<Square>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    hash: u64,
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::startpos()
+    }
+}
+
+impl Board {
+    fn empty() -> Self {
+        Self {
+            by_color: [0; 2],
+            by_kind: [0; 6],
+            squares: [None; 64],
+            side_to_move: Color::White,
+            castling: CastlingRights::NONE,
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash: 0,
+        }
+    }
+
+    pub fn startpos() -> Self {
+        Self::from_fen(START_FEN).expect("start position FEN is valid")
+    }
+
+    pub fn from_fen(fen: &str) -> Result<Board, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() != 4 && fields.len() != 6 {
+            return Err(FenError::FieldCount(fields.len()));
+        }
+
+        let mut board = Board::empty();
+
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::RankCount(ranks.len()));
+        }
+        for (i, rank_str) in ranks.iter().enumerate() {
+            let rank = 7 - i as u8;
+            let mut file: u8 = 0;
+            for c in rank_str.chars() {
+                if let Some(skip) = c.to_digit(10) {
+                    if skip == 0 || file as u32 + skip > 8 {
+                        return Err(FenError::RankLength(rank + 1));
+                    }
+                    file += skip as u8;
+                } else {
+                    let piece = Piece::from_fen_char(c).ok_or(FenError::InvalidPiece(c))?;
+                    let sq = Square::from_coords(file, rank)
+                        .ok_or(FenError::RankLength(rank + 1))?;
+                    board.put_piece(piece, sq);
+                    file += 1;
+                }
+            }
+            if file != 8 {
+                return Err(FenError::RankLength(rank + 1));
+            }
+        }
+
+        board.side_to_move = match fields[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            other => return Err(FenError::InvalidSideToMove(other.to_string())),
+        };
+
+        if fields[2] != "-" {
+            for c in fields[2].chars() {
+                let (right, file) = board
+                    .parse_castling_char(c)
+                    .ok_or_else(|| FenError::InvalidCastling(fields[2].to_string()))?;
+                board.castling.insert(right);
+                board.castling.set_rook_file(right, file);
+            }
+        }
+        board.castling = board.sanitized_castling();
+
+        if fields[3] != "-" {
+            let sq = Square::from_algebraic(fields[3])
+                .ok_or_else(|| FenError::InvalidEnPassant(fields[3].to_string()))?;
+            let expected_rank = match board.side_to_move {
+                Color::White => 5,
+                Color::Black => 2,
+            };
+            if sq.rank() != expected_rank {
+                return Err(FenError::InvalidEnPassant(fields[3].to_string()));
+            }
+            board.en_passant = Some(sq);
+        }
+
+        if fields.len() == 6 {
+            board.halfmove_clock = fields[4]
+                .parse()
+                .map_err(|_| FenError::InvalidCounter(fields[4].to_string()))?;
+            board.fullmove_number = fields[5]
+                .parse()
+                .map_err(|_| FenError::InvalidCounter(fields[5].to_string()))?;
+            if board.fullmove_number == 0 {
+                return Err(FenError::InvalidCounter(fields[5].to_string()));
+            }
+        }
+
+        board.validate()?;
+        board.hash = board.compute_hash();
+        Ok(board)
+    }
+
+    fn compute_hash(&self) -> u64 {
+        let keys = zobrist::keys();
+        let mut hash = keys.castling(self.castling.bits())
+            ^ keys.side(self.side_to_move)
+            ^ self.en_passant_key();
+        for (index, piece) in self.squares.iter().enumerate() {
+            if let Some(piece) = piece {
+                hash ^= keys.piece(*piece, Square::from_index_unchecked(index));
+            }
+        }
+        hash
+    }
+
+    /// The en passant square only contributes to the hash when a pawn can
+    /// actually capture there, so transpositions with a dead EP square match.
+    fn en_passant_key(&self) -> u64 {
+        self.capturable_en_passant()
+            .map_or(0, |ep| zobrist::keys().en_passant(ep))
+    }
+
+    /// The en passant square if a pawn of the side to move attacks it.
+    fn capturable_en_passant(&self) -> Option<Square> {
+        self.en_passant.filter(|&ep| {
+            bitboard::pawn_attacks(self.side_to_move.opposite(), ep)
+                & self.pieces(self.side_to_move, PieceKind::Pawn)
+                != 0
+        })
+    }
+
+    /// The position with colors swapped and ranks flipped, so each side's
+    /// pieces stand where the other's did and the other side is to move.
+    /// Castling rights and the en passant square follow the pieces.
+    pub fn mirror(&self) -> Board {
+        let mut board = Board::empty();
+        for (index, piece) in self.squares.iter().enumerate() {
+            if let Some(piece) = piece {
+                let sq = Square::from_index_unchecked(index).flip();
+                board.put_piece(Piece::new(piece.color().opposite(), piece.kind()), sq);
+            }
+        }
+        board.side_to_move = self.side_to_move.opposite();
+        for color in Color::ALL {
+            let other = color.opposite();
+            for (right, mirrored) in [
+                (CastlingRights::kingside(color), CastlingRights::kingside(other)),
+                (CastlingRights::queenside(color), CastlingRights::queenside(other)),
+            ] {
+                if self.castling.contains(right) {
+                    board.castling.insert(mirrored);
+                    board.castling.set_rook_file(mirrored, self.castling.rook_file(right));
+                }
+            }
+        }
+        board.en_passant = self.en_passant.map(Square::flip);
+        board.halfmove_clock = self.halfmove_clock;
+        board.fullmove_number = self.fullmove_number;
+        board.hash = board.compute_hash();
+        board
+    }
+
+    /// Zobrist key of the position.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    fn validate(&self) -> Result<(), FenError> {
+        for color in Color::ALL {
+            if self.pieces(color, PieceKind::King).count_ones() != 1 {
+                return Err(FenError::KingCount);
+            }
+        }
+        if self.by_kind[PieceKind::Pawn.index()] & (bitboard::RANK_1 | bitboard::RANK_8) != 0 {
+            return Err(FenError::PawnOnBackRank);
+        }
+        let them = self.side_to_move.opposite();
+        if self.is_square_attacked(self.king_square(them), self.side_to_move) {
+            return Err(FenError::OpponentInCheck);
+        }
+        Ok(())
+    }
+
+    /// Checks what every position reached from a valid FEN by legal moves
+    /// keeps: the FEN rules, bitboards that agree with the square list and an
+    /// up-to-date hash. A failure means a bug somewhere, not bad input.
+    pub fn check_consistency(&self) -> Result<(), FenError> {
+        let mut by_color = [0; 2];
+        let mut by_kind = [0; 6];
+        for (index, piece) in self.squares.iter().enumerate() {
+            if let Some(piece) = piece {
+                let bb = Square::from_index_unchecked(index).bitboard();
+                by_color[piece.color().index()] |= bb;
+                by_kind[piece.kind().index()] |= bb;
+            }
+        }
+        if by_color != self.by_color || by_kind != self.by_kind {
+            return Err(FenError::Inconsistent("bitboards disagree with the squares"));
+        }
+        self.validate()?;
+        if self.hash != self.compute_hash() {
+            return Err(FenError::Inconsistent("stale hash"));
+        }
+        Ok(())
+    }
+
+    /// `self` with the piece on `sq` gone and nothing else updated, giving
+    /// tests a board that no legal play can reach.
+    #[cfg(test)]
+    pub(crate) fn corrupted(mut self, sq: Square) -> Board {
+        self.squares[sq.index()] = None;
+        for bb in self.by_color.iter_mut().chain(self.by_kind.iter_mut()) {
+            *bb &= !sq.bitboard();
+        }
+        self
+    }
+
+    /// A right and its rook file from one castling character: `KQkq` take the
+    /// outermost rook on that side (X-FEN), `A`-`H` and `a`-`h` name the
+    /// rook's file directly (Shredder-FEN).
+    fn parse_castling_char(&self, c: char) -> Option<(CastlingRights, u8)> {
+        let color = if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+        let back = match color {
+            Color::White => 0,
+            Color::Black => 7,
+        };
+        let holds = |file: u8, kind: PieceKind| {
+            self.piece_at(Square::from_coords(file, back).unwrap()) == Some(Piece::new(color, kind))
+        };
+        let is_rook = |file: u8| holds(file, PieceKind::Rook);
+        let king_file = (0..8).find(|&file| holds(file, PieceKind::King)).unwrap_or(4);
+        match c.to_ascii_lowercase() {
+            'k' => {
+                let file = (king_file + 1..8).rev().find(|&f| is_rook(f)).unwrap_or(7);
+                Some((CastlingRights::kingside(color), file))
+            }
+            'q' => {
+                let file = (0..king_file).find(|&f| is_rook(f)).unwrap_or(0);
+                Some((CastlingRights::queenside(color), file))
+            }
+            letter @ 'a'..='h' => {
+                let file = letter as u8 - b'a';
+                let right = if file > king_file {
+                    CastlingRights::kingside(color)
+                } else {
+                    CastlingRights::queenside(color)
+                };
+                Some((right, file))
+            }
+            _ => None,
+        }
+    }
+
+    /// Drops castling rights whose king or rook is no longer on its home square.
+    fn sanitized_castling(&self) -> CastlingRights {
+        let mut rights = self.castling;
+        for color in Color::ALL {
+            let back = match color {
+                Color::White => 0,
+                Color::Black => 7,
+            };
+            let home = |file: u8, kind: PieceKind| {
+                self.piece_at(Square::from_coords(file, back).unwrap())
+                    == Some(Piece::new(color, kind))
+            };
+            for right in [CastlingRights::kingside(color), CastlingRights::queenside(color)] {
+                if !home(4, PieceKind::King) || !home(rights.rook_file(right), PieceKind::Rook) {
+                    rights.remove(right);
+                    // A dropped right keeps no rook file, so boards that read
+                    // the same compare equal.
+                    let standard = STANDARD_ROOK_FILES[right.bits().trailing_zeros() as usize];
+                    rights.set_rook_file(right, standard);
+                }
+            }
+        }
+        rights
+    }
+
+    /// FEN with the en passant square exactly as stored: set after every
+    /// double push, whether or not a capture is possible.
+    pub fn to_fen(&self) -> String {
+        self.fen_with_en_passant(self.en_passant)
+    }
+
+    /// FEN that names the en passant square only when a pawn can capture
+    /// there, so equal positions always print the same way.
+    pub fn canonical_fen(&self) -> String {
+        self.fen_with_en_passant(self.capturable_en_passant())
+    }
+
+    fn fen_with_en_passant(&self, en_passant: Option<Square>) -> String {
+        let mut fen = String::with_capacity(90);
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                match self.piece_at(Square::from_coords(file, rank).unwrap()) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            fen.push(char::from(b'0' + empty));
+                            empty = 0;
+                        }
+                        fen.push(piece.to_fen_char());
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen.push(char::from(b'0' + empty));
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push(' ');
+        fen.push(match self.side_to_move {
+            Color::White => 'w',
+            Color::Black => 'b',
+        });
+
+        fen.push(' ');
+        if self.castling.is_empty() {
+            fen.push('-');
+        } else {
+            for (right, c) in [
+                (CastlingRights::WHITE_KINGSIDE, 'K'),
+                (CastlingRights::WHITE_QUEENSIDE, 'Q'),
+                (CastlingRights::BLACK_KINGSIDE, 'k'),
+                (CastlingRights::BLACK_QUEENSIDE, 'q'),
+            ] {
+                if !self.castling.contains(right) {
+                    continue;
+                }
+                if self.castling.is_standard(right) {
+                    fen.push(c);
+                } else {
+                    // Shredder-style file letter, cased like the side.
+                    let letter = char::from(b'a' + self.castling.rook_file(right));
+                    if c.is_ascii_uppercase() {
+                        fen.push(letter.to_ascii_uppercase());
+                    } else {
+                        fen.push(letter);
+                    }
+                }
+            }
+        }
+
+        fen.push(' ');
+        match en_passant {
+            Some(sq) => fen.push_str(&sq.to_algebraic()),
+            None => fen.push('-'),
+        }
+
+        fen.push_str(&format!(" {} {}", self.halfmove_clock, self.fullmove_number));
+        fen
+    }
+
+    pub fn side_to_move(&self) -> Color {
+        self.side_to_move
+    }
+
+    pub fn castling_rights(&self) -> CastlingRights {
+        self.castling
+    }
+
+    pub fn en_passant(&self) -> Option<Square> {
+        self.en_passant
+    }
+
+    pub fn halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    pub fn fullmove_number(&self) -> u32 {
+        self.fullmove_number
+    }
+
+    /// What stands on `sq`, if anything.
+    pub fn piece_at(&self, sq: Square) -> Option<Piece> {
+        self.squares[sq.index()]
+    }
+
+    /// The color of the piece on `sq`, if there is one.
+    pub fn color_at(&self, sq: Square) -> Option<Color> {
+        self.piece_at(sq).map(|piece| piece.color())
+    }
+
+    pub fn pieces(&self, color: Color, kind: PieceKind) -> Bitboard {
+        self.by_color[color.index()] & self.by_kind[kind.index()]
+    }
+
+    pub fn material_count(&self) -> MaterialCount {
+        let mut material = MaterialCount::default();
+        for color in Color::ALL {
+            for kind in PieceKind::ALL {
+                material.counts[color.index()][kind.index()] =
+                    self.pieces(color, kind).count_ones();
+            }
+        }
+        material
+    }
+
+    pub fn color_occupancy(&self, color: Color) -> Bitboard {
+        self.by_color[color.index()]
+    }
+
+    pub fn occupancy(&self) -> Bitboard {
+        self.by_color[0] | self.by_color[1]
+    }
+
+    pub fn king_square(&self, color: Color) -> Square {
+        let kings = self.pieces(color, PieceKind::King);
+        debug_assert!(kings != 0, "no {:?} king on the board", color);
+        Square::from_index_unchecked(kings.trailing_zeros() as usize)
+    }
+
+    pub fn is_square_attacked(&self, sq: Square, by: Color) -> bool {
+        self.attackers_to(sq, self.occupancy()) & self.by_color[by.index()] != 0
+    }
+
+    /// Whether the side to move's king is attacked. Cheaper than generating
+    /// moves to find out.
+    pub fn in_check(&self) -> bool {
+        let us = self.side_to_move;
+        self.is_square_attacked(self.king_square(us), us.opposite())
+    }
+
+    /// Pieces of both colors attacking `sq`, with sliders blocked only by
+    /// `occupancy`. Clearing squares from the mask reveals x-ray attackers.
+    pub fn attackers_to(&self, sq: Square, occupancy: Bitboard) -> Bitboard {
+        let queens = self.by_kind[PieceKind::Queen.index()];
+        let diagonal = self.by_kind[PieceKind::Bishop.index()] | queens;
+        let orthogonal = self.by_kind[PieceKind::Rook.index()] | queens;
+        bitboard::pawn_attacks(Color::Black, sq) & self.pieces(Color::White, PieceKind::Pawn)
+            | bitboard::pawn_attacks(Color::White, sq) & self.pieces(Color::Black, PieceKind::Pawn)
+            | bitboard::knight_attacks(sq) & self.by_kind[PieceKind::Knight.index()]
+            | bitboard::king_attacks(sq) & self.by_kind[PieceKind::King.index()]
+            | bitboard::bishop_attacks(sq, occupancy) & diagonal
+            | bitboard::rook_attacks(sq, occupancy) & orthogonal
+    }
+
+    /// Enemy pieces giving check to the side to move.
+    pub(crate) fn checkers(&self) -> Bitboard {
+        let us = self.side_to_move;
+        self.attackers_to(self.king_square(us), self.occupancy())
+            & self.by_color[us.opposite().index()]
+    }
+
+    fn put_piece(&mut self, piece: Piece, sq: Square) {
+        let bb = sq.bitboard();
+        self.by_color[piece.color().index()] |= bb;
+        self.by_kind[piece.kind().index()] |= bb;
+        self.squares[sq.index()] = Some(piece);
+        self.hash ^= zobrist::keys().piece(piece, sq);
+    }
+
+    fn remove_piece(&mut self, sq: Square) -> Option<Piece> {
+        let piece = self.squares[sq.index()].take()?;
+        let bb = sq.bitboard();
+        self.by_color[piece.color().index()] &= !bb;
+        self.by_kind[piece.kind().index()] &= !bb;
+        self.hash ^= zobrist::keys().piece(piece, sq);
+        Some(piece)
+    }
+
+    /// Rook origin and destination for a castling king move, if `mv` is one.
+    fn castling_rook_squares(&self, mv: Move, piece: Piece) -> Option<(Square, Square)> {
+        if piece.kind() != PieceKind::King || mv.from.file() != 4 {
+            return None;
+        }
+        let rank = mv.from.rank();
+        match mv.to.file() {
+            6 => Some((Square::from_coords(7, rank)?, Square::from_coords(5, rank)?)),
+            2 => Some((Square::from_coords(0, rank)?, Square::from_coords(3, rank)?)),
+            _ => None,
+        }
+    }
+
+    /// Plays `mv`, which must be pseudo-legal in this position.
+    pub fn make_move(&mut self, mv: Move) -> Undo {
+        let us = self.side_to_move;
+        let piece = self.piece_at(mv.from).expect("make_move from an empty square");
+        let undo = Undo {
+            captured: None,
+            castling: self.castling,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+        };
+        let keys = zobrist::keys();
+        self.hash ^= keys.castling(self.castling.bits()) ^ self.en_passant_key();
+
+        let mut captured = self.remove_piece(mv.to);
+        self.remove_piece(mv.from);
+
+        if piece.kind() == PieceKind::Pawn && Some(mv.to) == self.en_passant {
+            let victim = Square::from_coords(mv.to.file(), mv.from.rank()).unwrap();
+            captured = self.remove_piece(victim);
+        }
+
+        if let Some((rook_from, rook_to)) = self.castling_rook_squares(mv, piece) {
+            if let Some(rook) = self.remove_piece(rook_from) {
+                self.put_piece(rook, rook_to);
+            }
+        }
+
+        let placed = match mv.promotion {
+            Some(kind) => Piece::new(us, kind),
+            None => piece,
+        };
+        self.put_piece(placed, mv.to);
+
+        self.en_passant = None;
+        if piece.kind() == PieceKind::Pawn && mv.from.rank().abs_diff(mv.to.rank()) == 2 {
+            self.en_passant = mv.from.forward(us);
+        }
+
+        self.castling = self
+            .castling
+            .after_touching(mv.from)
+            .after_touching(mv.to);
+
+        if piece.kind() == PieceKind::Pawn || captured.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if us == Color::Black {
+            self.fullmove_number += 1;
+        }
+        self.side_to_move = us.opposite();
+        self.hash ^= keys.castling(self.castling.bits())
+            ^ keys.side(Color::Black)
+            ^ self.en_passant_key();
+
+        Undo { captured, ..undo }
+    }
+
+    /// Reverts `mv`, which must be the last move made with `make_move`.
+    pub fn unmake_move(&mut self, mv: Move, undo: &Undo) {
+        let us = self.side_to_move.opposite();
+        self.side_to_move = us;
+        if us == Color::Black {
+            self.fullmove_number -= 1;
+        }
+        self.castling = undo.castling;
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+
+        let placed = self.remove_piece(mv.to).expect("unmake_move to an empty square");
+        let piece = match mv.promotion {
+            Some(_) => Piece::new(us, PieceKind::Pawn),
+            None => placed,
+        };
+        self.put_piece(piece, mv.from);
+
+        if let Some((rook_from, rook_to)) = self.castling_rook_squares(mv, piece) {
+            if let Some(rook) = self.remove_piece(rook_to) {
+                self.put_piece(rook, rook_from);
+            }
+        }
+
+        if let Some(captured) = undo.captured {
+            let en_passant = piece.kind() == PieceKind::Pawn && Some(mv.to) == undo.en_passant;
+            let sq = if en_passant {
+                Square::from_coords(mv.to.file(), mv.from.rank()).unwrap()
+            } else {
+                mv.to
+            };
+            self.put_piece(captured, sq);
+        }
+        self.hash = undo.hash;
+    }
+
+    /// What kind of check `mv`, which must be pseudo-legal, gives.
+    pub fn check_type(&self, mv: Move) -> CheckType {
+        let piece = self.piece_at(mv.from).expect("check_type from an empty square");
+        let mut moved = mv.to.bitboard();
+        if let Some((_, rook_to)) = self.castling_rook_squares(mv, piece) {
+            moved |= rook_to.bitboard();
+        }
+        let mut after = self.clone();
+        after.make_move(mv);
+        let checkers = after.checkers();
+        match checkers.count_ones() {
+            0 => CheckType::None,
+            1 if checkers & moved != 0 => CheckType::Direct,
+            1 => CheckType::Discovered,
+            _ => CheckType::Double,
+        }
+    }
+
+    /// Whether the side that just moved left its own king in check.
+    pub(crate) fn left_king_in_check(&self) -> bool {
+        let mover = self.side_to_move.opposite();
+        self.is_square_attacked(self.king_square(mover), self.side_to_move)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sq(s: &str) -> Square {
+        Square::from_algebraic(s).unwrap()
+    }
+
+    #[test]
+    fn test_fen_round_trip() {
+        for fen in [
+            START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3",
+            "8/8/8/8/8/8/8/K6k b - - 12 40",
+        ] {
+            assert_eq!(Board::from_fen(fen).unwrap().to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn test_mirror() {
+        let board =
+            Board::from_fen("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBN1 w Qkq d6 0 3").unwrap();
+        let mirrored = board.mirror();
+        assert_eq!(
+            mirrored.to_fen(),
+            "rnbqkbn1/pppp1ppp/8/8/3Pp3/8/PPP1PPPP/RNBQKBNR b KQq d3 0 3"
+        );
+        assert_eq!(mirrored, Board::from_fen(&mirrored.to_fen()).unwrap());
+        assert_eq!(mirrored.mirror(), board);
+        assert_eq!(
+            Board::startpos().mirror().to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1"
+        );
+    }
+
+    #[test]
+    fn test_invalid_fens() {
+        assert_eq!(Board::from_fen(""), Err(FenError::FieldCount(0)));
+        assert_eq!(
+            Board::from_fen("8/8/8/8/8/8/8 w - - 0 1"),
+            Err(FenError::RankCount(7))
+        );
+        assert_eq!(
+            Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1"),
+            Err(FenError::InvalidPiece('X'))
+        );
+        assert_eq!(
+            Board::from_fen("8/8/8/8/8/8/8/K7 w - - 0 1"),
+            Err(FenError::KingCount)
+        );
+        assert_eq!(
+            Board::from_fen("4k3/8/8/8/8/8/8/4K2r b - - 0 1"),
+            Err(FenError::OpponentInCheck)
+        );
+    }
+
+    #[test]
+    fn test_oversized_placements_are_errors() {
+        let k = "4k3/8/8/8/8/8/8/4K3";
+        for (placement, error) in [
+            ("9/8/8/8/8/8/8/8", FenError::RankLength(8)),
+            ("4k3/8/8/8/8/8/8/4K4", FenError::RankLength(1)),
+            ("4k3/8/8/8/8/8/8/4K3p", FenError::RankLength(1)),
+            ("4k3/8/8/8/8/8/8/4K2p1", FenError::RankLength(1)),
+            ("4k3/8/8/8/8/8/8/pppppppppppppppp", FenError::RankLength(1)),
+            ("4k3/8/8/8/8/8/8/4K3/8", FenError::RankCount(9)),
+            ("4k3/8/8/8/8/8/8/4K3/", FenError::RankCount(9)),
+            ("4k3/8/8/8/8/8/8/", FenError::RankLength(1)),
+            ("4k3/8/8/8/8/8/8/4K3/8/8/8/8/8/8/8/8", FenError::RankCount(16)),
+            ("4k3/8/8/8/8/8/8/0000K3", FenError::RankLength(1)),
+            ("4k3/8/8/8/8/8/8/4K\u{0663}", FenError::InvalidPiece('\u{0663}')),
+        ] {
+            let fen = format!("{} w - - 0 1", placement);
+            assert_eq!(Board::from_fen(&fen), Err(error), "{}", fen);
+        }
+        assert!(Board::from_fen(&format!("{} w - - 0 1", k)).is_ok());
+    }
+
+    #[test]
+    fn test_mangled_fens_never_panic() {
+        // Random edits of valid FENs, as FFI callers might pass them.
+        let mut rng = crate::rng::Rng::new(7);
+        let alphabet: Vec<char> = "0123456789/pnbrqkPNBRQK wb-KQkqa3e6 ".chars().collect();
+        for fen in [START_FEN, "r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1"] {
+            for _ in 0..2_000 {
+                let mut chars: Vec<char> = fen.chars().collect();
+                for _ in 0..1 + rng.below(4) {
+                    let at = rng.below(chars.len() as u64) as usize;
+                    let c = alphabet[rng.below(alphabet.len() as u64) as usize];
+                    match rng.below(3) {
+                        0 => chars[at] = c,
+                        1 => chars.insert(at, c),
+                        _ => drop(chars.remove(at)),
+                    }
+                }
+                let mangled: String = chars.into_iter().collect();
+                if let Ok(board) = Board::from_fen(&mangled) {
+                    assert_eq!(Board::from_fen(&board.to_fen()), Ok(board), "{}", mangled);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_make_unmake_restores_position() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        for mv in [
+            Move::new(sq("e1"), sq("g1")),
+            Move::new(sq("e5"), sq("f7")),
+            Move::new(sq("d5"), sq("e6")),
+        ] {
+            let undo = board.make_move(mv);
+            board.unmake_move(mv, &undo);
+            assert_eq!(board.to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn test_make_move_updates_state() {
+        let mut board = Board::startpos();
+        board.make_move(Move::new(sq("e2"), sq("e4")));
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        board.make_move(Move::new(sq("g8"), sq("f6")));
+        board.make_move(Move::new(sq("e1"), sq("e2")));
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 2 2"
+        );
+    }
+
+    #[test]
+    fn test_rook_moves_clear_their_own_right() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let cases = [
+            ("h1h4", "Qkq"),
+            ("a1a4", "Kkq"),
+            ("a1a8", "Kk"),
+            ("e1f1", "kq"),
+        ];
+        for (uci, expected) in cases {
+            let mut board = Board::from_fen(fen).unwrap();
+            let from = sq(&uci[..2]);
+            board.make_move(Move::new(from, sq(&uci[2..])));
+            let rights = board.castling_rights();
+            assert_eq!(board.to_fen().split(' ').nth(2), Some(expected), "{}", uci);
+            assert_eq!(rights.contains(CastlingRights::WHITE_KINGSIDE), expected.contains('K'));
+            assert_eq!(rights.contains(CastlingRights::BLACK_QUEENSIDE), expected.contains('q'));
+        }
+    }
+
+    #[test]
+    fn test_castling_rook_files() {
+        // Shredder letters for corner rooks read and print as KQkq.
+        let shredder = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1").unwrap();
+        assert_eq!(shredder.castling_rights().bits(), CastlingRights::ALL.bits());
+        assert_eq!(shredder.to_fen(), "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+
+        // X-FEN `K` takes the outermost kingside rook, here on g1.
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/R3K1R1 w KQ - 0 1").unwrap();
+        let rights = board.castling_rights();
+        assert_eq!(rights.rook_file(CastlingRights::WHITE_KINGSIDE), 6);
+        assert!(rights.is_standard(CastlingRights::WHITE_QUEENSIDE));
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/R3K1R1 w GQ - 0 1");
+        assert_eq!(Board::from_fen(&board.to_fen()).unwrap(), board);
+
+        board.make_move(Move::new(sq("g1"), sq("g5")));
+        assert_eq!(board.to_fen().split(' ').nth(2), Some("Q"));
+        assert_eq!(board.hash(), board.compute_hash());
+    }
+
+    #[test]
+    fn test_incremental_hash_matches_full_recompute() {
+        let mut board = Board::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        )
+        .unwrap();
+        for mv in [
+            Move::new(sq("a2"), sq("a4")),
+            Move::new(sq("b4"), sq("a3")),
+            Move::new(sq("e1"), sq("c1")),
+            Move::new(sq("h3"), sq("g2")),
+        ] {
+            board.make_move(mv);
+            assert_eq!(board.hash(), board.compute_hash());
+            let reparsed = Board::from_fen(&board.to_fen()).unwrap();
+            assert_eq!(board.hash(), reparsed.hash());
+        }
+    }
+
+    #[test]
+    fn test_transpositions_share_a_hash() {
+        let mut a = Board::startpos();
+        let mut b = Board::startpos();
+        for mv in ["g1f3", "g8f6", "b1c3"] {
+            a.make_move(Move::new(sq(&mv[..2]), sq(&mv[2..])));
+        }
+        for mv in ["b1c3", "g8f6", "g1f3"] {
+            b.make_move(Move::new(sq(&mv[..2]), sq(&mv[2..])));
+        }
+        assert_eq!(a.hash(), b.hash());
+        assert_ne!(a.hash(), Board::startpos().hash());
+    }
+
+    #[test]
+    fn test_material_count() {
+        let mut board = Board::startpos();
+        let start = board.material_count();
+        for color in Color::ALL {
+            assert_eq!(start.count(color, PieceKind::Pawn), 8);
+            assert_eq!(start.count(color, PieceKind::Knight), 2);
+            assert_eq!(start.count(color, PieceKind::Bishop), 2);
+            assert_eq!(start.count(color, PieceKind::Rook), 2);
+            assert_eq!(start.count(color, PieceKind::Queen), 1);
+            assert_eq!(start.count(color, PieceKind::King), 1);
+            assert_eq!(start.non_pawn_material(color), 2 * 320 + 2 * 330 + 2 * 500 + 900);
+        }
+        assert!(!start.is_insufficient());
+
+        // 1. e4 d5 2. exd5 Qxd5: one pawn each comes off.
+        let mut undos = Vec::new();
+        for (from, to) in [("e2", "e4"), ("d7", "d5"), ("e4", "d5"), ("d8", "d5")] {
+            let mv = Move::new(sq(from), sq(to));
+            undos.push((mv, board.make_move(mv)));
+        }
+        let after = board.material_count();
+        assert_eq!(after.count(Color::White, PieceKind::Pawn), 7);
+        assert_eq!(after.count(Color::Black, PieceKind::Pawn), 7);
+        assert_eq!(after.total_non_pawn_material(), start.total_non_pawn_material());
+
+        for (mv, undo) in undos.iter().rev() {
+            board.unmake_move(*mv, undo);
+        }
+        assert_eq!(board.material_count(), start);
+
+        let minor_endgame = Board::from_fen("8/8/4k3/8/8/2B5/8/4K3 w - - 0 1").unwrap();
+        assert!(minor_endgame.material_count().is_insufficient());
+    }
+
+    #[test]
+    fn test_raw_and_canonical_en_passant() {
+        // No black pawn next to e4: the raw field keeps e3, the canonical form drops it.
+        let mut board = Board::startpos();
+        board.make_move(Move::new(sq("e2"), sq("e4")));
+        let raw = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1";
+        assert_eq!(board.to_fen(), raw);
+        assert_eq!(
+            board.canonical_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        );
+        assert_eq!(Board::from_fen(raw).unwrap().to_fen(), raw);
+        assert_eq!(Board::from_fen(raw).unwrap(), board);
+
+        // A black pawn on d4 can take on e3, so both forms keep it.
+        let mut board =
+            Board::from_fen("rnbqkbnr/ppp1pppp/8/8/3p4/8/PPPPPPPP/RNBQKBNR w KQkq - 0 3").unwrap();
+        board.make_move(Move::new(sq("e2"), sq("e4")));
+        let fen = "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3";
+        assert_eq!(board.to_fen(), fen);
+        assert_eq!(board.canonical_fen(), fen);
+    }
+
+    #[test]
+    fn test_attackers_to() {
+        // d4 is hit by a pawn, knight, king and rooks from both sides.
+        let board = Board::from_fen("3r4/8/8/1Np1k3/8/8/8/3RK3 w - - 0 1").unwrap();
+        let attackers = board.attackers_to(sq("d4"), board.occupancy());
+        let expected = [sq("c5"), sq("b5"), sq("e5"), sq("d1"), sq("d8")]
+            .iter()
+            .fold(0, |bb, s| bb | s.bitboard());
+        assert_eq!(attackers, expected);
+        assert_eq!(
+            attackers & board.color_occupancy(Color::White),
+            sq("b5").bitboard() | sq("d1").bitboard()
+        );
+
+        // Doubled rooks: the back rook x-rays through once the front one is removed.
+        let board = Board::from_fen("4k3/8/8/8/8/8/3R4/3RK3 w - - 0 1").unwrap();
+        let target = sq("d6");
+        assert_eq!(board.attackers_to(target, board.occupancy()), sq("d2").bitboard());
+        let without_front = board.occupancy() & !sq("d2").bitboard();
+        assert_eq!(
+            board.attackers_to(target, without_front),
+            sq("d2").bitboard() | sq("d1").bitboard()
+        );
+    }
+
+    #[test]
+    fn test_piece_and_color_at() {
+        let board = Board::startpos();
+        assert_eq!(board.piece_at(sq("e1")), Some(Piece::new(Color::White, PieceKind::King)));
+        assert_eq!(board.piece_at(sq("d8")), Some(Piece::new(Color::Black, PieceKind::Queen)));
+        assert_eq!(board.piece_at(sq("g1")), Some(Piece::new(Color::White, PieceKind::Knight)));
+        assert_eq!(board.piece_at(sq("c7")), Some(Piece::new(Color::Black, PieceKind::Pawn)));
+        assert_eq!(board.color_at(sq("a1")), Some(Color::White));
+        assert_eq!(board.color_at(sq("h8")), Some(Color::Black));
+        for empty in ["e4", "a3", "h6", "d5"] {
+            assert_eq!(board.piece_at(sq(empty)), None, "{}", empty);
+            assert_eq!(board.color_at(sq(empty)), None, "{}", empty);
+        }
+    }
+
+    #[test]
+    fn test_in_check() {
+        assert!(!Board::startpos().in_check());
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/4RK2 b - - 0 1").unwrap();
+        assert!(board.in_check());
+        // The rook's line to the king is blocked.
+        assert!(!Board::from_fen("4k3/4p3/8/8/8/8/8/4RK2 b - - 0 1").unwrap().in_check());
+        // A knight check.
+        assert!(Board::from_fen("4k3/8/3N4/8/8/8/8/5K2 b - - 0 1").unwrap().in_check());
+    }
+
+    #[test]
+    fn test_check_type() {
+        let check_type = |fen: &str, from: &str, to: &str| {
+            Board::from_fen(fen).unwrap().check_type(Move::new(sq(from), sq(to)))
+        };
+        // The knight stands between the rook and the king.
+        let battery = "4k3/8/8/8/4N3/8/8/4R1K1 w - - 0 1";
+        assert_eq!(check_type(battery, "e4", "c3"), CheckType::Discovered);
+        assert_eq!(check_type(battery, "e4", "f6"), CheckType::Double);
+        assert_eq!(check_type(battery, "g1", "g2"), CheckType::None);
+        assert_eq!(check_type("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", "a1", "a8"), CheckType::Direct);
+        // Castling checks with the rook.
+        let castle = "5k2/8/8/8/8/8/8/4K2R w K - 0 1";
+        assert_eq!(check_type(castle, "e1", "g1"), CheckType::Direct);
+        // Taking en passant opens the bishop's diagonal.
+        let en_passant = "7k/8/8/3pP3/8/8/8/B3K3 w - d6 0 1";
+        assert_eq!(check_type(en_passant, "e5", "d6"), CheckType::Discovered);
+    }
+
+    #[test]
+    fn test_check_consistency() {
+        let mut board = Board::startpos();
+        board.make_move(Move::new(sq("e2"), sq("e4")));
+        assert_eq!(board.check_consistency(), Ok(()));
+        assert_eq!(board.clone().corrupted(sq("e1")).check_consistency(), Err(FenError::KingCount));
+        // A pawn gone but still hashed.
+        assert_eq!(
+            board.clone().corrupted(sq("a2")).check_consistency(),
+            Err(FenError::Inconsistent("stale hash"))
+        );
+        board.squares[sq("a2").index()] = None;
+        assert_eq!(
+            board.check_consistency(),
+            Err(FenError::Inconsistent("bitboards disagree with the squares"))
+        );
+    }
+}
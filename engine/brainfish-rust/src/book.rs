@@ -0,0 +1,154 @@
+use crate::board::Board;
+use crate::movegen;
+use crate::moves::Move;
+use crate::rng::Rng;
+use log::warn;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum BookError {
+    #[error("cannot read {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("malformed book: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+// On-disk layout, shared with the Python `OpeningBook`:
+// `{ "<fen>": { "moves": { "<uci>": { "frequency": n, ... } }, ... } }`.
+#[derive(Deserialize)]
+struct PositionRecord {
+    #[serde(default)]
+    moves: HashMap<String, MoveRecord>,
+}
+
+#[derive(Deserialize)]
+struct MoveRecord {
+    #[serde(default)]
+    frequency: u32,
+}
+
+/// Opening moves by position, each weighted by how often it was played.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OpeningBook {
+    // Keyed by the first four FEN fields, so move counters don't matter.
+    positions: HashMap<String, Vec<(String, u32)>>,
+}
+
+// Placement, side to move, castling and an en passant square only if a pawn
+// can use it, so the same position always has the same key.
+fn position_key(board: &Board) -> String {
+    let fen = board.canonical_fen();
+    fen.split_whitespace().take(4).collect::<Vec<_>>().join(" ")
+}
+
+impl OpeningBook {
+    pub fn from_json(text: &str) -> Result<Self, BookError> {
+        let records: HashMap<String, PositionRecord> = serde_json::from_str(text)?;
+        let positions = records
+            .into_iter()
+            .filter_map(|(fen, record)| {
+                let Ok(board) = Board::from_fen(&fen) else {
+                    warn!("Skipping book entry with invalid FEN '{}'", fen);
+                    return None;
+                };
+                let mut moves: Vec<(String, u32)> = record
+                    .moves
+                    .into_iter()
+                    .map(|(mv, data)| (mv, data.frequency))
+                    .collect();
+                // Map order is arbitrary; a fixed order keeps seeded picks reproducible.
+                moves.sort();
+                Some((position_key(&board), moves))
+            })
+            .collect();
+        Ok(Self { positions })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, BookError> {
+        let text = std::fs::read_to_string(path).map_err(|source| BookError::Io {
+            path: path.display().to_string(),
+            source,
+        })?;
+        Self::from_json(&text)
+    }
+
+    /// Number of positions in the book.
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Legal book moves for `board` with their weights.
+    pub fn moves(&self, board: &Board) -> Vec<(Move, u32)> {
+        let Some(entries) = self.positions.get(&position_key(board)) else {
+            return Vec::new();
+        };
+        let legal = movegen::generate_legal_moves(board);
+        entries
+            .iter()
+            .filter_map(|(text, weight)| {
+                let mv = text.parse::<Move>().ok().filter(|mv| legal.contains(mv))?;
+                Some((mv, *weight))
+            })
+            .collect()
+    }
+
+    /// Whether the book has a legal move for `board`.
+    pub fn contains(&self, board: &Board) -> bool {
+        !self.moves(board).is_empty()
+    }
+
+    /// A book move for `board`, picked with probability proportional to its
+    /// frequency. `None` when the position is not in the book.
+    pub fn probe(&self, board: &Board, rng: &mut Rng) -> Option<Move> {
+        let moves = self.moves(board);
+        let weights: Vec<u32> = moves.iter().map(|&(_, weight)| weight).collect();
+        rng.choose_weighted(&weights).map(|i| moves[i].0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::START_FEN;
+
+    #[test]
+    fn test_probe_reads_python_book_layout() {
+        let json = format!(
+            r#"{{"not a fen": {{"moves": {{}}}}, "{}": {{"moves": {{
+                "e2e4": {{"frequency": 3, "evaluation": 0.3, "last_updated": "2024-01-01"}},
+                "e2e5": {{"frequency": 9}}
+            }}, "total_games": 12}}}}"#,
+            START_FEN
+        );
+        let book = OpeningBook::from_json(&json).unwrap();
+        assert_eq!(book.len(), 1);
+        // The illegal entry is dropped; counters in the FEN don't matter.
+        let board = Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 5 9");
+        let mut rng = Rng::default();
+        assert_eq!(
+            book.probe(&board.unwrap(), &mut rng).map(|mv| mv.to_uci()).as_deref(),
+            Some("e2e4")
+        );
+        let after = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(book.probe(&after, &mut rng), None);
+    }
+
+    #[test]
+    fn test_malformed_book_is_an_error() {
+        assert!(matches!(OpeningBook::from_json("[1, 2]"), Err(BookError::Json(_))));
+        assert!(matches!(
+            OpeningBook::load(Path::new("/no/such/book.json")),
+            Err(BookError::Io { .. })
+        ));
+    }
+}
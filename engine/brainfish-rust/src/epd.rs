@@ -0,0 +1,346 @@
+use crate::board::{Board, FenError};
+use crate::movegen;
+use crate::moves::Move;
+use crate::san;
+use crate::search::{self, SearchConfig, SearchLimits};
+use crate::tt::TranspositionTable;
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum EpdError {
+    #[error("cannot read {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("line {line}: {source}")]
+    Fen { line: usize, source: FenError },
+    #[error("line {line}: malformed operation '{operation}'")]
+    Operation { line: usize, operation: String },
+    #[error("line {line}: '{san}' is not a legal move")]
+    IllegalMove { line: usize, san: String },
+}
+
+/// A position with the perft counts it should produce, from a line such as
+/// `<fen> ;D1 20 ;D2 400`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PerftCase {
+    pub fen: String,
+    /// `(depth, leaf nodes)` in file order.
+    pub expected: Vec<(u32, u64)>,
+}
+
+/// A depth whose count came out wrong.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PerftMismatch {
+    pub depth: u32,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PerftOutcome {
+    pub fen: String,
+    pub mismatches: Vec<PerftMismatch>,
+}
+
+impl PerftOutcome {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for PerftOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.passed() {
+            return write!(f, "PASS {}", self.fen);
+        }
+        write!(f, "FAIL {}", self.fen)?;
+        for m in &self.mismatches {
+            write!(f, "; depth {} expected {} actual {}", m.depth, m.expected, m.actual)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a perft suite. Blank lines and lines starting with `#` are skipped.
+pub fn parse_perft_suite(text: &str) -> Result<Vec<PerftCase>, EpdError> {
+    let mut cases = Vec::new();
+    for (index, raw) in text.lines().enumerate() {
+        let line = index + 1;
+        let raw = raw.trim();
+        if raw.is_empty() || raw.starts_with('#') {
+            continue;
+        }
+        let mut fields = raw.split(';');
+        let fen = fields.next().unwrap_or_default().trim();
+        Board::from_fen(fen).map_err(|source| EpdError::Fen { line, source })?;
+        let expected = fields
+            .map(|operation| {
+                parse_depth_count(operation).ok_or_else(|| EpdError::Operation {
+                    line,
+                    operation: operation.trim().to_string(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        cases.push(PerftCase {
+            fen: fen.to_string(),
+            expected,
+        });
+    }
+    Ok(cases)
+}
+
+// `D3 8902` -> (3, 8902).
+fn parse_depth_count(operation: &str) -> Option<(u32, u64)> {
+    let mut parts = operation.split_whitespace();
+    let depth = parts.next()?.strip_prefix('D')?.parse().ok()?;
+    let count = parts.next()?.parse().ok()?;
+    parts.next().is_none().then_some((depth, count))
+}
+
+/// Reads and parses a perft suite file.
+pub fn load_perft_suite(path: &Path) -> Result<Vec<PerftCase>, EpdError> {
+    parse_perft_suite(&read_file(path)?)
+}
+
+/// Runs perft for every expected depth of every case.
+pub fn run_perft_suite(cases: &[PerftCase]) -> Vec<PerftOutcome> {
+    cases
+        .iter()
+        .map(|case| {
+            // The FEN was validated when the suite was parsed.
+            let mut board = Board::from_fen(&case.fen).expect("suite FEN is valid");
+            let mismatches = case
+                .expected
+                .iter()
+                .filter_map(|&(depth, expected)| {
+                    let actual = movegen::perft(&mut board, depth);
+                    (actual != expected).then_some(PerftMismatch {
+                        depth,
+                        expected,
+                        actual,
+                    })
+                })
+                .collect();
+            PerftOutcome {
+                fen: case.fen.clone(),
+                mismatches,
+            }
+        })
+        .collect()
+}
+
+fn read_file(path: &Path) -> Result<String, EpdError> {
+    std::fs::read_to_string(path).map_err(|source| EpdError::Io {
+        path: path.display().to_string(),
+        source,
+    })
+}
+
+/// A position with best (`bm`) and/or avoid (`am`) moves, from a line such
+/// as `<4-field fen> bm Qg6; id "WAC.001";`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TacticCase {
+    /// The `id` operation, or the FEN when there is none.
+    pub id: String,
+    pub fen: String,
+    pub best: Vec<Move>,
+    pub avoid: Vec<Move>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TacticOutcome {
+    pub id: String,
+    /// The search's choice in SAN.
+    pub played: String,
+    pub solved: bool,
+}
+
+impl fmt::Display for TacticOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verdict = if self.solved { "SOLVED" } else { "FAILED" };
+        write!(f, "{} {} played {}", verdict, self.id, self.played)
+    }
+}
+
+/// Parses a `bm`/`am` suite. Other operations are ignored apart from `id`;
+/// move counters may be given or left out.
+pub fn parse_tactic_suite(text: &str) -> Result<Vec<TacticCase>, EpdError> {
+    let mut cases = Vec::new();
+    for (index, raw) in text.lines().enumerate() {
+        let line = index + 1;
+        let raw = raw.trim();
+        if raw.is_empty() || raw.starts_with('#') {
+            continue;
+        }
+        let tokens: Vec<&str> = raw.split_whitespace().collect();
+        let mut fen_len = tokens.len().min(4);
+        let counters = tokens.get(4..6).unwrap_or_default();
+        if counters.len() == 2 && counters.iter().all(|t| t.parse::<u32>().is_ok()) {
+            fen_len = 6;
+        }
+        let position = tokens[..fen_len.min(4)].join(" ");
+        let fen = match fen_len {
+            6 => tokens[..6].join(" "),
+            _ => format!("{} 0 1", position),
+        };
+        let board = Board::from_fen(&fen).map_err(|source| EpdError::Fen { line, source })?;
+        let mut case = TacticCase {
+            id: position,
+            fen,
+            best: Vec::new(),
+            avoid: Vec::new(),
+        };
+        let operations = tokens[fen_len..].join(" ");
+        for operation in operations.split(';').map(str::trim).filter(|op| !op.is_empty()) {
+            let (opcode, operands) = operation.split_once(' ').unwrap_or((operation, ""));
+            let moves = match opcode {
+                "bm" => &mut case.best,
+                "am" => &mut case.avoid,
+                "id" => {
+                    case.id = operands.trim().trim_matches('"').to_string();
+                    continue;
+                }
+                _ => continue,
+            };
+            if operands.trim().is_empty() {
+                return Err(EpdError::Operation {
+                    line,
+                    operation: operation.to_string(),
+                });
+            }
+            for text in operands.split_whitespace() {
+                let mv = san::parse_san(&board, text).ok_or_else(|| EpdError::IllegalMove {
+                    line,
+                    san: text.to_string(),
+                })?;
+                moves.push(mv);
+            }
+        }
+        cases.push(case);
+    }
+    Ok(cases)
+}
+
+/// Reads and parses a `bm`/`am` suite file.
+pub fn load_tactic_suite(path: &Path) -> Result<Vec<TacticCase>, EpdError> {
+    parse_tactic_suite(&read_file(path)?)
+}
+
+/// Searches each case within `limits`. A case is solved when the chosen
+/// move is one of its best moves (if it has any) and none of its avoid moves.
+pub fn run_tactic_suite(
+    cases: &[TacticCase],
+    limits: &SearchLimits,
+    config: &SearchConfig,
+    tt: &TranspositionTable,
+) -> Vec<TacticOutcome> {
+    let stop = AtomicBool::new(false);
+    cases
+        .iter()
+        .map(|case| {
+            let board = Board::from_fen(&case.fen).expect("suite FEN is valid");
+            tt.clear();
+            let result = search::search(&board, limits, config, tt, &stop, |_| {});
+            let (played, solved) = match result.best_move {
+                Some(mv) => (
+                    san::to_san(&board, mv),
+                    (case.best.is_empty() || case.best.contains(&mv)) && !case.avoid.contains(&mv),
+                ),
+                None => (String::from("(none)"), false),
+            };
+            TacticOutcome {
+                id: case.id.clone(),
+                played,
+                solved,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_perft_suite_passes() {
+        let cases = parse_perft_suite(include_str!("../data/perftsuite.epd")).unwrap();
+        assert_eq!(cases.len(), 6);
+        for outcome in run_perft_suite(&cases) {
+            assert!(outcome.passed(), "{}", outcome);
+        }
+    }
+
+    #[test]
+    fn test_mismatch_names_fen_depth_and_counts() {
+        let fen = "4k3/8/8/8/8/8/8/4K3 w - - 0 1";
+        let cases = parse_perft_suite(&format!("{} ;D1 5 ;D2 26", fen)).unwrap();
+        let outcome = &run_perft_suite(&cases)[0];
+        assert_eq!(
+            outcome.mismatches,
+            [PerftMismatch {
+                depth: 2,
+                expected: 26,
+                actual: 25
+            }]
+        );
+        assert_eq!(
+            outcome.to_string(),
+            format!("FAIL {}; depth 2 expected 26 actual 25", fen)
+        );
+    }
+
+    #[test]
+    fn test_malformed_lines_are_rejected() {
+        assert!(matches!(
+            parse_perft_suite("\n8/8/8 w - - ;D1 1"),
+            Err(EpdError::Fen { line: 2, .. })
+        ));
+        assert!(matches!(
+            parse_perft_suite("4k3/8/8/8/8/8/8/4K3 w - - 0 1 ;D1 five"),
+            Err(EpdError::Operation { line: 1, .. })
+        ));
+    }
+
+    const MATES: &str = "\
+6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; id \"back rank\";
+r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - bm Qxf7#; am Qxe5+;
+r1b2k1r/ppp1bppp/8/1B1Q4/5q2/2P5/PPP2PPP/R3R1K1 w - - 0 1 bm Qd8+; id \"mate in 2\";
+1k6/8/2K5/8/8/8/8/7R w - - am Rh7 Kb6;
+";
+
+    #[test]
+    fn test_tactic_suite_parses_operations() {
+        let cases = parse_tactic_suite(MATES).unwrap();
+        assert_eq!(cases.len(), 4);
+        assert_eq!(cases[0].id, "back rank");
+        assert_eq!(cases[0].best[0].to_uci(), "a1a8");
+        assert_eq!(cases[1].avoid[0].to_uci(), "h5e5");
+        assert_eq!(cases[2].fen, "r1b2k1r/ppp1bppp/8/1B1Q4/5q2/2P5/PPP2PPP/R3R1K1 w - - 0 1");
+        assert_eq!(cases[2].best[0].to_uci(), "d5d8");
+        assert_eq!(cases[3].avoid.len(), 2);
+        assert!(matches!(
+            parse_tactic_suite("6k1/8/8/8/8/8/8/R5K1 w - - bm Rb9;"),
+            Err(EpdError::IllegalMove { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_tactic_suite_solves_short_mates() {
+        let cases = parse_tactic_suite(MATES).unwrap();
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..Default::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let outcomes = run_tactic_suite(&cases, &limits, &SearchConfig::default(), &tt);
+        for outcome in &outcomes {
+            assert!(outcome.solved, "{}", outcome);
+        }
+        assert_eq!(outcomes[0].to_string(), "SOLVED back rank played Ra8#");
+    }
+}
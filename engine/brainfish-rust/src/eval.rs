@@ -0,0 +1,453 @@
+use crate::bitboard::{self, squares, DARK_SQUARES};
+use crate::board::Board;
+use crate::types::{Color, PieceKind, Square};
+
+pub const PIECE_VALUES: [i32; 6] = [100, 320, 330, 500, 900, 0];
+
+// Game phase contribution per piece kind; a full board sums to MAX_PHASE.
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+const MAX_PHASE: i32 = 24;
+
+/// Static evaluations never go beyond this many centipawns either way, which
+/// keeps them clear of the search's mate scores.
+pub const EVAL_LIMIT: i32 = 30_000;
+
+/// Endgame scale factors are out of this, so 64 leaves the score unchanged.
+pub const SCALE_NORMAL: i32 = 64;
+// Bishops of opposite colors and nothing else: a base plus a bit per pawn.
+const SCALE_PURE_OCB: i32 = 16;
+const SCALE_OCB_PER_PAWN: i32 = 8;
+// Opposite-colored bishops alongside other, balanced, pieces.
+const SCALE_OCB_WITH_PIECES: i32 = 46;
+// No pawns and at most a minor piece up, with a rook or more on the board.
+const SCALE_PAWNLESS_MINOR_UP: i32 = 4;
+
+// Passed pawn bonuses by rank, counted from the pawn's own side.
+const PASSED_MIDGAME: [i32; 8] = [0, 5, 10, 15, 25, 40, 60, 0];
+const PASSED_ENDGAME: [i32; 8] = [0, 10, 15, 25, 45, 75, 110, 0];
+// Per rank past the fourth, per king step from the square in front of the
+// pawn: the enemy king further away is worth more than ours closer.
+const PASSER_ENEMY_KING: i32 = 5;
+const PASSER_OWN_KING: i32 = 2;
+// Our king on a key square of the pawn, or the enemy king right in front.
+const PASSER_KEY_SQUARE: i32 = 30;
+const PASSER_BLOCKADE: i32 = 20;
+// A pawn the enemy king cannot catch will queen if no piece stops it.
+const UNSTOPPABLE_PASSER: i32 = 400;
+// Against a bare king: per step of the weak king from the centre, and per
+// step closer of the strong king.
+const MOP_UP_EDGE: i32 = 30;
+const MOP_UP_KINGS: i32 = 15;
+
+// Piece-square tables from White's point of view, rank 8 first, so they read
+// like a diagram. Index with `relative_index`.
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const PAWN_ENDGAME_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    90, 90, 90, 90, 90, 90, 90, 90,
+    60, 60, 60, 60, 60, 60, 60, 60,
+    35, 35, 35, 35, 35, 35, 35, 35,
+    20, 20, 20, 20, 20, 20, 20, 20,
+    10, 10, 10, 10, 10, 10, 10, 10,
+     0,  0,  0,  0,  0,  0,  0,  0,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+   -50,-40,-30,-30,-30,-30,-40,-50,
+   -40,-20,  0,  0,  0,  0,-20,-40,
+   -30,  0, 10, 15, 15, 10,  0,-30,
+   -30,  5, 15, 20, 20, 15,  5,-30,
+   -30,  0, 15, 20, 20, 15,  0,-30,
+   -30,  5, 10, 15, 15, 10,  5,-30,
+   -40,-20,  0,  5,  5,  0,-20,-40,
+   -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+   -20,-10,-10,-10,-10,-10,-10,-20,
+   -10,  0,  0,  0,  0,  0,  0,-10,
+   -10,  0,  5, 10, 10,  5,  0,-10,
+   -10,  5,  5, 10, 10,  5,  5,-10,
+   -10,  0, 10, 10, 10, 10,  0,-10,
+   -10, 10, 10, 10, 10, 10, 10,-10,
+   -10,  5,  0,  0,  0,  0,  5,-10,
+   -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+   -20,-10,-10, -5, -5,-10,-10,-20,
+   -10,  0,  0,  0,  0,  0,  0,-10,
+   -10,  0,  5,  5,  5,  5,  0,-10,
+    -5,  0,  5,  5,  5,  5,  0, -5,
+     0,  0,  5,  5,  5,  5,  0, -5,
+   -10,  5,  5,  5,  5,  5,  0,-10,
+   -10,  0,  5,  0,  0,  0,  0,-10,
+   -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const KING_TABLE: [i32; 64] = [
+   -30,-40,-40,-50,-50,-40,-40,-30,
+   -30,-40,-40,-50,-50,-40,-40,-30,
+   -30,-40,-40,-50,-50,-40,-40,-30,
+   -30,-40,-40,-50,-50,-40,-40,-30,
+   -20,-30,-30,-40,-40,-30,-30,-20,
+   -10,-20,-20,-20,-20,-20,-20,-10,
+    20, 20,  0,  0,  0,  0, 20, 20,
+    20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+#[rustfmt::skip]
+const KING_ENDGAME_TABLE: [i32; 64] = [
+   -50,-40,-30,-20,-20,-30,-40,-50,
+   -30,-20,-10,  0,  0,-10,-20,-30,
+   -30,-10, 20, 30, 30, 20,-10,-30,
+   -30,-10, 30, 40, 40, 30,-10,-30,
+   -30,-10, 30, 40, 40, 30,-10,-30,
+   -30,-10, 20, 30, 30, 20,-10,-30,
+   -30,-30,  0,  0,  0,  0,-30,-30,
+   -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+fn relative_index(color: Color, sq: Square) -> usize {
+    match color {
+        Color::White => sq.flip().index(),
+        Color::Black => sq.index(),
+    }
+}
+
+fn tables(kind: PieceKind) -> (&'static [i32; 64], &'static [i32; 64]) {
+    match kind {
+        PieceKind::Pawn => (&PAWN_TABLE, &PAWN_ENDGAME_TABLE),
+        PieceKind::Knight => (&KNIGHT_TABLE, &KNIGHT_TABLE),
+        PieceKind::Bishop => (&BISHOP_TABLE, &BISHOP_TABLE),
+        PieceKind::Rook => (&ROOK_TABLE, &ROOK_TABLE),
+        PieceKind::Queen => (&QUEEN_TABLE, &QUEEN_TABLE),
+        PieceKind::King => (&KING_TABLE, &KING_ENDGAME_TABLE),
+    }
+}
+
+/// Remaining non-pawn material on a 0 (bare kings) ..= 24 (opening) scale.
+pub fn game_phase(board: &Board) -> i32 {
+    let material = board.material_count();
+    let phase: i32 = PieceKind::ALL
+        .iter()
+        .map(|&kind| {
+            let count = Color::ALL.iter().map(|&c| material.count(c, kind)).sum::<u32>();
+            count as i32 * PHASE_WEIGHTS[kind.index()]
+        })
+        .sum();
+    phase.min(MAX_PHASE)
+}
+
+/// How much of the endgame score `strong` can hope to convert, out of
+/// `SCALE_NORMAL`. Drawish material gets a smaller factor.
+pub fn endgame_scale(board: &Board, strong: Color) -> i32 {
+    let weak = strong.opposite();
+    let material = board.material_count();
+    let strong_npm = material.non_pawn_material(strong);
+    let weak_npm = material.non_pawn_material(weak);
+    let bishop = PIECE_VALUES[PieceKind::Bishop.index()];
+
+    if material.count(strong, PieceKind::Pawn) == 0 && strong_npm - weak_npm <= bishop {
+        return if strong_npm < PIECE_VALUES[PieceKind::Rook.index()] {
+            0
+        } else {
+            SCALE_PAWNLESS_MINOR_UP
+        };
+    }
+
+    let strong_bishops = board.pieces(strong, PieceKind::Bishop);
+    let weak_bishops = board.pieces(weak, PieceKind::Bishop);
+    let opposite_bishops = strong_bishops.count_ones() == 1
+        && weak_bishops.count_ones() == 1
+        && (strong_bishops & DARK_SQUARES == 0) != (weak_bishops & DARK_SQUARES == 0);
+    if opposite_bishops {
+        if strong_npm == bishop && weak_npm == bishop {
+            let pawns = material.count(strong, PieceKind::Pawn) as i32;
+            return (SCALE_PURE_OCB + SCALE_OCB_PER_PAWN * pawns).min(SCALE_NORMAL);
+        }
+        if strong_npm == weak_npm {
+            return SCALE_OCB_WITH_PIECES;
+        }
+    }
+    SCALE_NORMAL
+}
+
+// Rank, 0 to 7, counted from `color`'s side of the board.
+fn relative_rank(color: Color, sq: Square) -> usize {
+    match color {
+        Color::White => sq.rank() as usize,
+        Color::Black => 7 - sq.rank() as usize,
+    }
+}
+
+/// Midgame and endgame bonuses for `color`'s passed pawns. Beyond the bonus
+/// for how far a pawn has come, the endgame part counts the kings: close
+/// support and a distant defender help, our king on the pawn's key squares
+/// (two ranks ahead, or one once it is past halfway) wins the opposition
+/// fight, and the enemy king in front stops it. When the enemy has only
+/// pawns, a pawn outside their king's square races home.
+pub fn passed_pawns(board: &Board, color: Color) -> (i32, i32) {
+    let them = color.opposite();
+    let enemy_pawns = board.pieces(them, PieceKind::Pawn);
+    let our_king = board.king_square(color);
+    let their_king = board.king_square(them);
+    let bare = board.material_count().non_pawn_material(them) == 0;
+    let (mut midgame, mut endgame) = (0, 0);
+    for sq in squares(board.pieces(color, PieceKind::Pawn)) {
+        if bitboard::passed_pawn_span(color, sq) & enemy_pawns != 0 {
+            continue;
+        }
+        let rank = relative_rank(color, sq);
+        midgame += PASSED_MIDGAME[rank];
+        endgame += PASSED_ENDGAME[rank];
+        let Some(stop) = sq.forward(color) else {
+            continue;
+        };
+        let weight = rank.saturating_sub(3) as i32;
+        let distance = |king: Square| king.distance(stop).min(5) as i32;
+        endgame += weight
+            * (PASSER_ENEMY_KING * distance(their_king) - PASSER_OWN_KING * distance(our_king));
+
+        let key_steps = if rank >= 4 { 1 } else { 2 };
+        if relative_rank(color, our_king) == rank + key_steps
+            && our_king.file().abs_diff(sq.file()) <= 1
+        {
+            endgame += PASSER_KEY_SQUARE;
+        }
+        if their_king == stop {
+            endgame -= PASSER_BLOCKADE;
+        }
+
+        let file = bitboard::FILE_A << sq.file();
+        let path = bitboard::passed_pawn_span(color, sq) & file;
+        if bare && path & board.occupancy() == 0 {
+            let last_rank = if color == Color::White { 7 } else { 0 };
+            let queening = Square::from_coords(sq.file(), last_rank).expect("on the board");
+            // A pawn on its first move steps two squares.
+            let pawn_moves = (7 - rank.max(2)) as i32;
+            let tempo = (board.side_to_move() == them) as i32;
+            if their_king.distance(queening) as i32 - tempo > pawn_moves {
+                endgame += UNSTOPPABLE_PASSER;
+            }
+        }
+    }
+    (midgame, endgame)
+}
+
+/// From White's point of view, a bonus for driving a bare king to the edge
+/// and bringing the other king up, so that mating material actually mates.
+/// Zero unless one side has only its king and the other at least a rook's
+/// worth of pieces.
+pub fn mop_up(board: &Board) -> i32 {
+    let material = board.material_count();
+    let rook = PIECE_VALUES[PieceKind::Rook.index()];
+    for (strong, sign) in [(Color::White, 1), (Color::Black, -1)] {
+        let weak = strong.opposite();
+        let bare =
+            material.non_pawn_material(weak) == 0 && material.count(weak, PieceKind::Pawn) == 0;
+        if bare && material.non_pawn_material(strong) >= rook {
+            let weak_king = board.king_square(weak);
+            let from_centre = |c: u8| if c < 4 { 3 - c as i32 } else { c as i32 - 4 };
+            let edge = from_centre(weak_king.file()) + from_centre(weak_king.rank());
+            let kings = weak_king.distance(board.king_square(strong)) as i32;
+            return sign * (MOP_UP_EDGE * edge + MOP_UP_KINGS * (7 - kings));
+        }
+    }
+    0
+}
+
+/// Brings a raw evaluation within `EVAL_LIMIT`.
+pub fn clamp_eval(score: i32) -> i32 {
+    score.clamp(-EVAL_LIMIT, EVAL_LIMIT)
+}
+
+/// Static evaluation in centipawns from the side to move's point of view,
+/// clamped with `clamp_eval`.
+pub fn evaluate(board: &Board) -> i32 {
+    let mut midgame = 0;
+    let mut endgame = 0;
+
+    for color in Color::ALL {
+        let sign = if color == Color::White { 1 } else { -1 };
+        for kind in PieceKind::ALL {
+            let (mg_table, eg_table) = tables(kind);
+            for sq in squares(board.pieces(color, kind)) {
+                let index = relative_index(color, sq);
+                let value = PIECE_VALUES[kind.index()];
+                midgame += sign * (value + mg_table[index]);
+                endgame += sign * (value + eg_table[index]);
+            }
+        }
+        if board.pieces(color, PieceKind::Bishop).count_ones() >= 2 {
+            midgame += sign * 30;
+            endgame += sign * 50;
+        }
+        let (passed_midgame, passed_endgame) = passed_pawns(board, color);
+        midgame += sign * passed_midgame;
+        endgame += sign * passed_endgame;
+    }
+    endgame += mop_up(board);
+
+    let strong = if endgame > 0 { Color::White } else { Color::Black };
+    endgame = endgame * endgame_scale(board, strong) / SCALE_NORMAL;
+
+    let phase = game_phase(board);
+    let score = clamp_eval((midgame * phase + endgame * (MAX_PHASE - phase)) / MAX_PHASE);
+    match board.side_to_move() {
+        Color::White => score,
+        Color::Black => -score,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_position_is_balanced() {
+        assert_eq!(evaluate(&Board::startpos()), 0);
+        assert_eq!(game_phase(&Board::startpos()), MAX_PHASE);
+    }
+
+    #[test]
+    fn test_evaluation_is_side_relative() {
+        let white = Board::from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+        let black = Board::from_fen("4k3/8/8/8/8/8/8/3QK3 b - - 0 1").unwrap();
+        assert!(evaluate(&white) > 800);
+        assert_eq!(evaluate(&white), -evaluate(&black));
+    }
+
+    #[test]
+    fn test_opposite_colored_bishops_scale_down() {
+        // A pawn up with bishops on c4 (light) against c5 (dark) or d5 (light).
+        let opposite = Board::from_fen("8/6k1/8/2b5/2B5/4P3/8/4K3 w - - 0 1").unwrap();
+        let same = Board::from_fen("8/6k1/8/3b4/2B5/4P3/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(endgame_scale(&same, Color::White), SCALE_NORMAL);
+        assert!(endgame_scale(&opposite, Color::White) < SCALE_NORMAL / 2);
+        assert!(evaluate(&same) > 0);
+        assert!(evaluate(&opposite) > 0);
+        assert!(evaluate(&opposite) < evaluate(&same) / 2);
+    }
+
+    #[test]
+    fn test_pawnless_minor_advantage_is_drawish() {
+        let rook_vs_bishop = Board::from_fen("8/6k1/8/2b5/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(endgame_scale(&rook_vs_bishop, Color::White), SCALE_PAWNLESS_MINOR_UP);
+        let minor_vs_minor = Board::from_fen("8/6k1/8/2n5/8/8/8/1B2K3 w - - 0 1").unwrap();
+        assert_eq!(endgame_scale(&minor_vs_minor, Color::White), 0);
+        assert_eq!(endgame_scale(&Board::startpos(), Color::White), SCALE_NORMAL);
+    }
+
+    #[test]
+    fn test_extreme_scores_are_clamped_below_mate() {
+        use crate::search::{is_mate_score, MATE_BOUND};
+        for raw in [i32::MAX, 1_000_000, -1_000_000, i32::MIN + 1] {
+            let score = clamp_eval(raw);
+            assert_eq!(score.abs(), EVAL_LIMIT);
+            assert!(score.abs() < MATE_BOUND && !is_mate_score(score));
+        }
+        assert_eq!(clamp_eval(-250), -250);
+        // Nine queens against two pawns is nowhere near the limit.
+        let queens = Board::from_fen("7k/6pp/8/8/8/8/QQQQQQQQ/QK6 w - - 0 1").unwrap();
+        assert!(evaluate(&queens) < EVAL_LIMIT);
+    }
+
+    #[test]
+    fn test_passed_pawns() {
+        let passed = |fen: &str| passed_pawns(&Board::from_fen(fen).unwrap(), Color::White);
+        // On the b file a7 stops the pawn; on the e file nothing does.
+        assert_eq!(passed("4k3/p7/8/1P6/8/8/8/4K3 w - - 0 1"), (0, 0));
+        let (midgame, endgame) = passed("4k3/p7/8/4P3/8/8/8/4K3 w - - 0 1");
+        assert_eq!(midgame, PASSED_MIDGAME[4]);
+        // The nearer enemy king makes up for ours being further back.
+        assert_eq!(endgame, PASSED_ENDGAME[4]);
+        assert!(passed("k7/p7/8/4P3/8/8/8/4K3 w - - 0 1").1 > endgame);
+        // Outside the bare king's square the pawn runs home; inside it doesn't.
+        let (_, racing) = passed("8/8/8/8/P7/8/7k/4K3 w - - 0 1");
+        let (_, caught) = passed("8/8/2k5/8/P7/8/8/4K3 w - - 0 1");
+        assert!(racing > caught + UNSTOPPABLE_PASSER / 2);
+        // Our king on a key square beats the enemy king in front.
+        let (_, supported) = passed("8/8/4k3/8/3K4/4P3/8/8 w - - 0 1");
+        let (_, blockaded) = passed("8/8/8/3K4/8/4k3/4P3/8 w - - 0 1");
+        assert!(supported > blockaded);
+    }
+
+    #[test]
+    fn test_mop_up_drives_the_bare_king_to_the_edge() {
+        let mop_up = |fen: &str| mop_up(&Board::from_fen(fen).unwrap());
+        let centre = mop_up("8/8/8/3k4/8/8/8/2Q1K3 w - - 0 1");
+        let edge = mop_up("8/8/8/k7/8/8/8/2Q1K3 w - - 0 1");
+        let corner = mop_up("k7/8/8/8/8/8/8/2Q1K3 w - - 0 1");
+        assert!(0 < centre && centre < edge && edge < corner);
+        // Kings close together count too, and for either side.
+        assert!(mop_up("k7/8/1K6/8/8/8/8/2Q5 w - - 0 1") > corner);
+        let board = Board::from_fen("k7/8/8/8/8/8/8/2Q1K3 w - - 0 1").unwrap();
+        assert_eq!(super::mop_up(&board.mirror()), -corner);
+        // Nothing for too little material, or with the weak side not bare.
+        assert_eq!(mop_up("k7/8/8/8/8/8/8/N3K3 w - - 0 1"), 0);
+        assert_eq!(mop_up("k7/p7/8/8/8/8/8/Q3K3 w - - 0 1"), 0);
+    }
+
+    /// Mirrors `fen` and checks that White's view of the one is Black's view
+    /// of the other, to the centipawn.
+    fn assert_eval_symmetric(fen: &str) {
+        let white_pov = |board: &Board| match board.side_to_move() {
+            Color::White => evaluate(board),
+            Color::Black => -evaluate(board),
+        };
+        let board = Board::from_fen(fen).unwrap();
+        let mirrored = board.mirror();
+        assert_eq!(white_pov(&mirrored), -white_pov(&board), "{}", fen);
+    }
+
+    #[test]
+    fn test_evaluation_is_color_symmetric() {
+        for fen in [
+            crate::board::START_FEN,
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            // Opposite-colored bishops and a pawnless ending, which scale.
+            "4k3/5p2/8/3b4/8/2B5/5PP1/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/2RBK3 b - - 0 1",
+            // Lopsided material and a bishop pair on one side only.
+            "4k3/8/8/8/8/8/PPPPPPPP/RBB1K3 w - - 0 1",
+            // Passed pawns, and a bare king to mate.
+            "8/8/4k3/8/3K4/4P3/1p6/8 b - - 0 1",
+            "8/8/8/3k4/8/8/8/Q3K3 w - - 0 1",
+        ] {
+            assert_eval_symmetric(fen);
+        }
+    }
+}
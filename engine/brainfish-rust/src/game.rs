@@ -0,0 +1,463 @@
+use crate::board::{Board, START_FEN};
+use crate::movegen;
+use crate::moves::Move;
+use crate::san;
+use crate::types::Color;
+use crate::EngineError;
+use serde::Serialize;
+use std::fmt;
+
+/// Whether the game is over, and why.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameStatus {
+    Ongoing,
+    Checkmate { winner: Color },
+    Stalemate,
+    ThreefoldRepetition,
+    FiftyMoveRule,
+    InsufficientMaterial,
+}
+
+impl GameStatus {
+    pub fn is_draw(self) -> bool {
+        !matches!(self, GameStatus::Ongoing | GameStatus::Checkmate { .. })
+    }
+
+    /// The PGN result token: `1-0`, `0-1`, `1/2-1/2` or `*` while ongoing.
+    pub fn result(self) -> &'static str {
+        match self {
+            GameStatus::Ongoing => "*",
+            GameStatus::Checkmate { winner: Color::White } => "1-0",
+            GameStatus::Checkmate { winner: Color::Black } => "0-1",
+            _ => "1/2-1/2",
+        }
+    }
+}
+
+impl fmt::Display for GameStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameStatus::Ongoing => write!(f, "ongoing"),
+            GameStatus::Checkmate { winner: Color::White } => write!(f, "white wins by checkmate"),
+            GameStatus::Checkmate { winner: Color::Black } => write!(f, "black wins by checkmate"),
+            GameStatus::Stalemate => write!(f, "draw by stalemate"),
+            GameStatus::ThreefoldRepetition => write!(f, "draw by threefold repetition"),
+            GameStatus::FiftyMoveRule => write!(f, "draw by the fifty-move rule"),
+            GameStatus::InsufficientMaterial => write!(f, "draw by insufficient material"),
+        }
+    }
+}
+
+/// A game as the engine knows it: the position it started from and the moves
+/// played since, as given by `position ... moves ...`.
+#[derive(Clone, Debug)]
+pub struct Game {
+    start: Board,
+    board: Board,
+    moves: Vec<Move>,
+    // Zobrist key of every position reached, starting with `start`.
+    keys: Vec<u64>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new(Board::default())
+    }
+}
+
+impl Game {
+    pub fn new(start: Board) -> Self {
+        Self {
+            board: start.clone(),
+            keys: vec![start.hash()],
+            start,
+            moves: Vec::new(),
+        }
+    }
+
+    /// Reads a game from PGN: optional tag pairs, of which only `FEN` is
+    /// used, then SAN movetext. Move numbers, comments, variations, NAGs and
+    /// the result are skipped.
+    pub fn from_pgn(text: &str) -> Result<Self, EngineError> {
+        let mut start = Board::startpos();
+        let mut movetext = String::new();
+        let mut rest = text;
+        while let Some(open) = rest.find('[') {
+            let Some(close) = rest[open..].find(']').map(|i| open + i) else {
+                break;
+            };
+            movetext.push_str(&rest[..open]);
+            let tag = rest[open + 1..close].trim();
+            if let Some(fen) = tag.strip_prefix("FEN") {
+                start = Board::from_fen(fen.trim().trim_matches('"'))?;
+            }
+            rest = &rest[close + 1..];
+        }
+        movetext.push_str(rest);
+
+        let mut game = Game::new(start);
+        for token in movetext_tokens(&movetext) {
+            let Some(mv) = san::parse_san(&game.board, token) else {
+                return Err(EngineError::InvalidSan {
+                    number: game.board.fullmove_number(),
+                    text: token.to_string(),
+                });
+            };
+            game.play(mv);
+        }
+        Ok(game)
+    }
+
+    pub fn start(&self) -> &Board {
+        &self.start
+    }
+
+    /// The current position.
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    /// Plays a move given in UCI notation, rejecting anything illegal. A
+    /// promotion suffix is required on a pawn reaching the last rank and
+    /// refused everywhere else.
+    pub fn play_uci(&mut self, text: &str) -> Result<Move, EngineError> {
+        let legal = movegen::generate_legal_moves(&self.board);
+        let parsed = text.parse::<Move>().ok();
+        let Some(mv) = parsed.filter(|mv| legal.contains(mv)) else {
+            // Same squares as a legal move, but the wrong promotion part?
+            let same_squares =
+                |mv: &&Move| parsed.is_some_and(|p| (p.from, p.to) == (mv.from, mv.to));
+            let wanted = parsed.and_then(|p| p.promotion);
+            let reason = match legal.iter().find(same_squares) {
+                Some(mv) if mv.promotion.is_some() && wanted.is_none() => {
+                    " (promotion piece required)"
+                }
+                Some(mv) if mv.promotion.is_none() && wanted.is_some() => " (not a promotion)",
+                _ => "",
+            };
+            return Err(EngineError::IllegalMove(format!("{}{}", text, reason)));
+        };
+        self.play(mv);
+        Ok(mv)
+    }
+
+    /// Plays `mv`, which must be legal in the current position.
+    pub fn play(&mut self, mv: Move) {
+        self.board.make_move(mv);
+        self.moves.push(mv);
+        self.keys.push(self.board.hash());
+    }
+
+    /// How many times the current position has occurred in this game,
+    /// counting the current occurrence. Only positions since the last capture
+    /// or pawn move can match.
+    pub fn repetitions(&self) -> usize {
+        let current = self.board.hash();
+        let reversible = self.board.halfmove_clock() as usize;
+        self.keys
+            .iter()
+            .rev()
+            .take(reversible + 1)
+            .step_by(2)
+            .filter(|&&key| key == current)
+            .count()
+    }
+
+    pub fn status(&self) -> GameStatus {
+        let board = &self.board;
+        let us = board.side_to_move();
+        let in_check = board.in_check();
+        if movegen::generate_legal_moves(board).is_empty() {
+            return if in_check {
+                GameStatus::Checkmate { winner: us.opposite() }
+            } else {
+                GameStatus::Stalemate
+            };
+        }
+        if self.repetitions() >= 3 {
+            GameStatus::ThreefoldRepetition
+        } else if board.halfmove_clock() >= 100 {
+            GameStatus::FiftyMoveRule
+        } else if board.material_count().is_insufficient() {
+            GameStatus::InsufficientMaterial
+        } else {
+            GameStatus::Ongoing
+        }
+    }
+
+    pub fn san_moves(&self) -> Vec<String> {
+        let mut board = self.start.clone();
+        self.moves
+            .iter()
+            .map(|&mv| {
+                let san = san::to_san(&board, mv);
+                board.make_move(mv);
+                san
+            })
+            .collect()
+    }
+
+    /// The FEN of every position in the game, the start first and the current
+    /// position last, so one more than there are moves.
+    pub fn position_fens(&self) -> Vec<String> {
+        let mut board = self.start.clone();
+        let mut fens = vec![board.to_fen()];
+        for &mv in &self.moves {
+            board.make_move(mv);
+            fens.push(board.to_fen());
+        }
+        fens
+    }
+
+    /// PGN-style movetext, e.g. `1. e4 e5 2. Nf3`. A game starting with Black
+    /// to move opens with `1... `.
+    pub fn movetext(&self) -> String {
+        let mut parts = Vec::new();
+        let mut number = self.start.fullmove_number();
+        let mut color = self.start.side_to_move();
+        for (i, san) in self.san_moves().into_iter().enumerate() {
+            match color {
+                Color::White => parts.push(format!("{}. {}", number, san)),
+                Color::Black if i == 0 => parts.push(format!("{}... {}", number, san)),
+                Color::Black => parts.push(san),
+            }
+            if color == Color::Black {
+                number += 1;
+            }
+            color = color.opposite();
+        }
+        parts.join(" ")
+    }
+
+    /// The game as PGN: the seven standard tags, `SetUp` and `FEN` when it
+    /// did not start from the initial position, then the movetext ending in
+    /// the result.
+    pub fn pgn(&self, white: &str, black: &str) -> String {
+        let result = self.status().result();
+        let mut tags = vec![
+            ("Event", "?"),
+            ("Site", "?"),
+            ("Date", "????.??.??"),
+            ("Round", "?"),
+            ("White", white),
+            ("Black", black),
+            ("Result", result),
+        ];
+        let fen = self.start.to_fen();
+        if fen != START_FEN {
+            tags.push(("SetUp", "1"));
+            tags.push(("FEN", &fen));
+        }
+        let mut lines: Vec<String> = tags
+            .iter()
+            .map(|(name, value)| format!("[{} \"{}\"]", name, value.replace('"', "'")))
+            .collect();
+        lines.push(String::new());
+        let movetext = self.movetext();
+        lines.push(if movetext.is_empty() {
+            result.to_string()
+        } else {
+            format!("{} {}", movetext, result)
+        });
+        lines.join("\n")
+    }
+}
+
+// The SAN moves of PGN movetext, without move numbers (`12.`, `12...`, or
+// fused as in `12.e4`), `{...}` and `;` comments, `(...)` variations, NAGs
+// like `$1` and the result.
+fn movetext_tokens(movetext: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut depth = 0;
+    for line in movetext.lines() {
+        let line = line.split(';').next().unwrap_or("");
+        let mut word_start = None;
+        for (i, c) in line.char_indices().chain(std::iter::once((line.len(), ' '))) {
+            let separator = c.is_whitespace() || "{}()".contains(c);
+            if separator {
+                if let Some(start) = word_start.take() {
+                    if depth == 0 {
+                        tokens.push(&line[start..i]);
+                    }
+                }
+                match c {
+                    '{' | '(' => depth += 1,
+                    '}' | ')' => depth = (depth - 1).max(0),
+                    _ => {}
+                }
+            } else if word_start.is_none() {
+                word_start = Some(i);
+            }
+        }
+    }
+    tokens
+        .into_iter()
+        .filter(|token| !token.starts_with('$') && !["1-0", "0-1", "1/2-1/2", "*"].contains(token))
+        .map(|token| match token.rfind('.') {
+            Some(dot) if token[..dot].chars().all(|c| c.is_ascii_digit() || c == '.') => {
+                &token[dot + 1..]
+            }
+            _ => token,
+        })
+        .filter(|token| !token.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(game: &mut Game, moves: &str) {
+        for mv in moves.split_whitespace() {
+            game.play_uci(mv).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_movetext_for_short_game() {
+        let mut game = Game::default();
+        play(&mut game, "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5c6 d7c6 e1g1");
+        assert_eq!(
+            game.movetext(),
+            "1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Bxc6 dxc6 5. O-O"
+        );
+        assert_eq!(game.moves().len(), 9);
+    }
+
+    #[test]
+    fn test_movetext_with_checks_and_black_to_move() {
+        let start = Board::from_fen(
+            "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2",
+        )
+        .unwrap();
+        let mut game = Game::new(start);
+        play(&mut game, "d8h4");
+        assert_eq!(game.movetext(), "2... Qh4#");
+    }
+
+    #[test]
+    fn test_pgn_tags_and_result() {
+        let mut game = Game::default();
+        play(&mut game, "f2f3 e7e5 g2g4 d8h4");
+        let pgn = game.pgn("W", "B");
+        assert!(pgn.starts_with("[Event \"?\"]\n"), "{}", pgn);
+        assert!(pgn.contains("[White \"W\"]\n[Black \"B\"]\n[Result \"0-1\"]\n\n"), "{}", pgn);
+        assert!(!pgn.contains("FEN"));
+        assert!(pgn.ends_with("\n1. f3 e5 2. g4 Qh4# 0-1"), "{}", pgn);
+
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        let game = Game::new(Board::from_fen(fen).unwrap());
+        let pgn = game.pgn("W", "B");
+        assert!(pgn.contains(&format!("[SetUp \"1\"]\n[FEN \"{}\"]", fen)), "{}", pgn);
+        assert!(pgn.ends_with("\n\n*"), "{}", pgn);
+    }
+
+    #[test]
+    fn test_from_pgn_skips_everything_but_moves() {
+        let pgn = "[Event \"Test\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 w - - 0 1\"]\n\n\
+                   1.e4 {a comment (with parens)} Kd7 $1 (1... Ke7 2. e5) 2. Kd2 ; to the end\n\
+                   2... Kc6 *";
+        let game = Game::from_pgn(pgn).unwrap();
+        assert_eq!(game.movetext(), "1. e4 Kd7 2. Kd2 Kc6");
+        assert_eq!(game.board().to_fen(), "8/8/2k5/8/4P3/8/3K4/8 w - - 3 3");
+        // The result alone is an empty game.
+        assert!(Game::from_pgn("1/2-1/2").unwrap().moves().is_empty());
+        assert!(matches!(
+            Game::from_pgn("1. e4 e5 2. Ke3"),
+            Err(EngineError::InvalidSan { number: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_position_fens_step_one_move_at_a_time() {
+        let mut game = Game::default();
+        play(&mut game, "e2e4 c7c5 g1f3 d7d6");
+        let fens = game.position_fens();
+        assert_eq!(fens.len(), 5);
+        assert_eq!(fens[0], START_FEN);
+        assert_eq!(fens[4], game.board().to_fen());
+        for (pair, &mv) in fens.windows(2).zip(game.moves()) {
+            let mut board = Board::from_fen(&pair[0]).unwrap();
+            board.make_move(mv);
+            assert_ne!(pair[0], pair[1]);
+            assert_eq!(board.to_fen(), pair[1]);
+        }
+        assert_eq!(Game::default().position_fens(), [START_FEN]);
+    }
+
+    #[test]
+    fn test_threefold_repetition_is_a_draw() {
+        let mut game = Game::default();
+        play(&mut game, "g1f3 g8f6 f3g1 f6g8");
+        assert_eq!(game.repetitions(), 2);
+        assert_eq!(game.status(), GameStatus::Ongoing);
+        play(&mut game, "g1f3 g8f6 f3g1");
+        assert_eq!(game.repetitions(), 2);
+        play(&mut game, "f6g8");
+        assert_eq!(game.repetitions(), 3);
+        assert_eq!(game.status(), GameStatus::ThreefoldRepetition);
+        assert!(game.status().is_draw());
+    }
+
+    #[test]
+    fn test_repetition_ignores_positions_before_a_pawn_move() {
+        let mut game = Game::default();
+        play(&mut game, "g1f3 g8f6 f3g1 f6g8 e2e4 e7e5 g1f3 g8f6 f3g1 f6g8");
+        assert_eq!(game.repetitions(), 2);
+        assert_eq!(game.status(), GameStatus::Ongoing);
+    }
+
+    #[test]
+    fn test_mate_and_stalemate_status() {
+        let mut game = Game::default();
+        play(&mut game, "f2f3 e7e5 g2g4 d8h4");
+        assert_eq!(game.status(), GameStatus::Checkmate { winner: Color::Black });
+        let stalemate = Game::new(Board::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap());
+        assert_eq!(stalemate.status(), GameStatus::Stalemate);
+    }
+
+    #[test]
+    fn test_mate_on_the_hundredth_halfmove_is_not_a_draw() {
+        let start = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 99 80").unwrap();
+        let mut game = Game::new(start.clone());
+        play(&mut game, "a1a8");
+        assert_eq!(game.board().halfmove_clock(), 100);
+        assert_eq!(game.status(), GameStatus::Checkmate { winner: Color::White });
+
+        let mut game = Game::new(start);
+        play(&mut game, "a1b1");
+        assert_eq!(game.status(), GameStatus::FiftyMoveRule);
+    }
+
+    #[test]
+    fn test_illegal_move_leaves_game_unchanged() {
+        let mut game = Game::default();
+        assert!(game.play_uci("e2e5").is_err());
+        assert!(game.moves().is_empty());
+        assert_eq!(game.board(), game.start());
+    }
+
+    #[test]
+    fn test_promotion_suffix_is_validated() {
+        let start = Board::from_fen("4k3/P7/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        let mut game = Game::new(start.clone());
+        let knight = Some(crate::types::PieceKind::Knight);
+        assert_eq!(game.play_uci("a7a8n").unwrap().promotion, knight);
+
+        let mut game = Game::new(start);
+        let reason = |result: Result<Move, EngineError>| match result {
+            Err(EngineError::IllegalMove(text)) => text,
+            other => panic!("expected an illegal move, got {:?}", other),
+        };
+        assert_eq!(reason(game.play_uci("e2e4q")), "e2e4q (not a promotion)");
+        assert_eq!(reason(game.play_uci("a7a8")), "a7a8 (promotion piece required)");
+        assert_eq!(reason(game.play_uci("a7a8k")), "a7a8k");
+        assert_eq!(reason(game.play_uci("a7b8q")), "a7b8q");
+        assert!(game.moves().is_empty());
+    }
+}
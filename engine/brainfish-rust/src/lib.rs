@@ -1,35 +1,290 @@
+use std::borrow::Cow;
 use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
-use std::sync::Once;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, Once};
+use std::thread::{self, JoinHandle};
 use log::{error, info, warn};
 use thiserror::Error;
 
+pub mod bitboard;
+pub mod board;
+pub mod book;
+pub mod epd;
+pub mod eval;
+pub mod game;
+pub mod movecache;
+pub mod movegen;
+pub mod movepick;
+pub mod moves;
+pub mod options;
+pub mod report;
+pub mod rng;
+pub mod san;
+pub mod search;
+pub mod stats;
+pub mod strength;
+pub mod tablebase;
+pub mod time;
+pub mod tt;
+pub mod types;
+pub mod wdl;
+pub mod zobrist;
+
+use board::{Board, FenError};
+use book::OpeningBook;
+use game::{Game, GameStatus};
+use movecache::MoveCache;
+use moves::Move;
+use options::Options;
+use report::PositionReport;
+use rng::Rng;
+use search::{Score, SearchConfig, SearchEvent, SearchLimits, SearchResult};
+use stats::StatsLog;
+use strength::Strength;
+use tablebase::{TableResult, Tablebases};
+use tt::TranspositionTable;
+use std::time::{Duration, Instant};
+use types::Color;
+
 static INIT: Once = Once::new();
 
+/// Name and version sent in `id name`. Builds can tag themselves by setting
+/// `BRAINFISH_NAME_SUFFIX` at compile time, e.g. to get `BrainFish 0.2.0 NNUE`.
+pub fn engine_name() -> String {
+    let name = format!("BrainFish {}", env!("CARGO_PKG_VERSION"));
+    match option_env!("BRAINFISH_NAME_SUFFIX") {
+        Some(suffix) if !suffix.is_empty() => format!("{} {}", name, suffix),
+        _ => name,
+    }
+}
+
+/// The FEN after playing `moves` (UCI notation) from `fen`. Needs no engine;
+/// an illegal move is reported with its index in `moves`.
+pub fn apply_moves(fen: &str, moves: &[&str]) -> Result<String, EngineError> {
+    let mut game = Game::new(Board::from_fen(fen)?);
+    for (index, &text) in moves.iter().enumerate() {
+        game.play_uci(text).map_err(|e| match e {
+            EngineError::IllegalMove(text) => EngineError::IllegalMoveAt { index, text },
+            other => other,
+        })?;
+    }
+    Ok(game.board().to_fen())
+}
+
+/// A command line as received, made text: invalid UTF-8 is replaced rather
+/// than rejected and control characters are dropped, since GUIs now and then
+/// send stray bytes.
+pub fn decode_command(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    if let Cow::Owned(_) = text {
+        warn!("Command is not valid UTF-8, replacing the invalid bytes");
+    }
+    strip_control(&text).into_owned()
+}
+
+// Control characters other than whitespace, which still separates tokens.
+fn strip_control(text: &str) -> Cow<'_, str> {
+    let stray = |c: char| c.is_control() && !c.is_whitespace();
+    if !text.contains(stray) {
+        return Cow::Borrowed(text);
+    }
+    warn!("Dropping control characters from command {:?}", text);
+    Cow::Owned(text.chars().filter(|&c| !stray(c)).collect())
+}
+
 #[derive(Error, Debug)]
 pub enum EngineError {
     #[error("Failed to initialize engine")]
     InitializationError,
-    #[error("Invalid FEN string")]
-    InvalidFen,
+    #[error("Invalid FEN string: {0}")]
+    InvalidFen(#[from] FenError),
+    #[error("Illegal move: {0}")]
+    IllegalMove(String),
+    #[error("Illegal move {text} at index {index}")]
+    IllegalMoveAt { index: usize, text: String },
+    #[error("Invalid SAN move {text} at move {number}")]
+    InvalidSan { number: u32, text: String },
+    #[error("Invalid command: {0}")]
+    InvalidCommand(String),
+    #[error("Invalid square: {0}")]
+    InvalidSquare(String),
+    #[error("Unknown option: {0}")]
+    UnknownOption(String),
+    #[error("Invalid value '{value}' for option {name}")]
+    InvalidOptionValue { name: String, value: String },
     #[error("Engine not initialized")]
     NotInitialized,
+    #[error("EPD error: {0}")]
+    Epd(#[from] epd::EpdError),
     #[error("FFI error: {0}")]
     FfiError(String),
 }
 
 pub struct Engine {
     initialized: bool,
+    game: Game,
+    // False until the GUI sends `position`; `go` then falls back to the start position.
+    position_set: bool,
+    // Set by `ucinewgame`: the next `go` reduces less, as nothing is learned yet.
+    warmup: bool,
+    options: Options,
+    tt: Arc<TranspositionTable>,
+    // A table of the new Hash size being allocated; replaces `tt` once ready.
+    pending_tt: Option<JoinHandle<TranspositionTable>>,
+    background: Option<BackgroundSearch>,
+    output: Option<OutputSink>,
+    // Reseeded from the Seed option on every new game.
+    rng: Rng,
+    stats_log: Option<Arc<Mutex<StatsLog>>>,
+    tablebases: Tablebases,
+    tablebase_probe: Option<tablebase::Probe>,
+    // The most recent finished search and the position it started from.
+    last_search: Option<(Board, SearchResult)>,
+    move_cache: MoveCache,
+    // In BookFile order; the first to know the position answers.
+    books: Vec<OpeningBook>,
+}
+
+/// Receives search output line by line as it is produced.
+pub type OutputSink = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Commands for an engine started with `Engine::spawn`, one per message.
+pub type CommandSender = Sender<String>;
+/// Everything a spawned engine prints, one line per message.
+pub type ResponseReceiver = Receiver<String>;
+
+/// A search running on its own thread.
+struct BackgroundSearch {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<FinishedSearch>,
+    // One of the `BESTMOVE_*` states. Infinite and pondering searches hold
+    // their `bestmove` back until `stop`, or for pondering `ponderhit`.
+    bestmove: Arc<AtomicU8>,
+    // Set when a new `go` replaces this search; its `bestmove` is dropped.
+    discard: Arc<AtomicBool>,
+    // The `go ponder` command, until `ponderhit` turns it into a real search.
+    ponder: Option<GoParams>,
+    // Set by `ponderhit` to end the search when its time is up.
+    deadline: Option<Arc<search::Deadline>>,
+}
+
+// The search sends its `bestmove` as soon as it is done.
+const BESTMOVE_SEND: u8 = 0;
+// The search keeps its `bestmove` when done, moving to `BESTMOVE_HELD`.
+const BESTMOVE_HOLD: u8 = 1;
+const BESTMOVE_HELD: u8 = 2;
+
+/// A parsed `go` command. The clock is kept as given rather than turned into
+/// a move time, so that a `go ponder` search is only timed from `ponderhit`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct GoParams {
+    limits: SearchLimits,
+    /// The side to move's clock, if `wtime` or `btime` gave one.
+    clock: Option<time::Clock>,
+    ponder: bool,
+}
+
+impl GoParams {
+    /// The limits to search with from now on, the clock's share of time
+    /// becoming the move time unless one was given.
+    fn timed_limits(&self, options: &Options) -> SearchLimits {
+        let mut limits = self.limits.clone();
+        if let (Some(clock), None) = (&self.clock, limits.movetime) {
+            let overhead = Duration::from_millis(options.move_overhead_ms);
+            limits.movetime = Some(time::allocate(clock, overhead, options.slow_mover));
+        }
+        limits
+    }
+}
+
+/// How search results are shown, as the options ask for a search of one
+/// position.
+#[derive(Clone, Copy, Debug)]
+struct Reporting {
+    // The model for `wdl` figures, if they are shown at all.
+    wdl: Option<wdl::WdlModel>,
+    // Black is to move and scores are wanted from White's side.
+    flip: bool,
+    // Most PV moves per line; 0 shows them all.
+    pv_limit: usize,
+    // Lines the GUI asked for; weakened play may search more.
+    lines: usize,
+}
+
+impl Reporting {
+    fn result(&self, result: &SearchResult) -> SearchResult {
+        let mut result = if self.flip { result.negated() } else { result.clone() };
+        result.extra_lines.truncate(self.lines.saturating_sub(1));
+        if self.pv_limit > 0 {
+            // A prefix of a legal line is still legal.
+            result.pv.truncate(self.pv_limit);
+            for line in &mut result.extra_lines {
+                line.pv.truncate(self.pv_limit);
+            }
+        }
+        result
+    }
+
+    fn event_lines(&self, event: SearchEvent<'_>) -> Vec<String> {
+        match event {
+            SearchEvent::Iteration { result, elapsed } => {
+                search::format_all_lines(&self.result(result), elapsed, self.wdl)
+            }
+            event => search::format_event(event, self.wdl),
+        }
+    }
+}
+
+struct FinishedSearch {
+    // Output not sent to the sink, ending with the `bestmove` line.
+    lines: Vec<String>,
+    board: Board,
+    result: SearchResult,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Engine {
+    // A running search borrows the table and the output sink; it must not
+    // outlive the engine, least of all one freed through `engine_free`.
+    fn drop(&mut self) {
+        self.abort_search();
+        if let Some(handle) = self.pending_tt.take() {
+            let _ = handle.join();
+        }
+    }
 }
 
 impl Engine {
     pub fn new() -> Self {
         INIT.call_once(|| {
-            env_logger::init();
+            // The host (e.g. the binary) may already have installed a logger.
+            let _ = env_logger::try_init();
         });
-        
+
         Self {
             initialized: false,
+            game: Game::default(),
+            position_set: false,
+            warmup: false,
+            options: Options::default(),
+            tt: Arc::new(TranspositionTable::new(tt::DEFAULT_HASH_MB)),
+            pending_tt: None,
+            background: None,
+            output: None,
+            rng: Rng::default(),
+            stats_log: None,
+            tablebases: Tablebases::default(),
+            tablebase_probe: None,
+            last_search: None,
+            move_cache: MoveCache::default(),
+            books: Vec::new(),
         }
     }
 
@@ -40,40 +295,1097 @@ impl Engine {
         }
 
         info!("Initializing BrainFish engine");
-        // TODO: Add actual initialization logic
+        // `new` has already allocated the hash table; this only unlocks the commands.
         self.initialized = true;
         Ok(())
     }
 
-    pub fn process_command(&self, command: &str) -> Result<String, EngineError> {
+    /// Whether the engine is initialized and no hash table resize is still
+    /// allocating, i.e. whether `isready` would answer at once.
+    pub fn ready(&self) -> bool {
+        self.initialized && self.pending_tt.as_ref().is_none_or(|h| h.is_finished())
+    }
+
+    /// Blocks until the engine is `ready`, initializing it if needed and
+    /// installing a resized hash table. The library form of `isready`.
+    pub fn wait_ready(&mut self) -> Result<(), EngineError> {
+        if !self.initialized {
+            self.initialize()?;
+        }
+        if let Some(handle) = self.pending_tt.take() {
+            match handle.join() {
+                Ok(tt) => self.tt = Arc::new(tt),
+                Err(_) => error!("Hash table allocation panicked, keeping the old table"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles one UCI command. Initializes the engine first if the host
+    /// hasn't, since some GUIs send `isready` or `position` before `uci`.
+    /// Tokens are split on any whitespace, so `\r\n` endings and runs of
+    /// spaces don't reach the parsers; other control characters are dropped.
+    pub fn process_command(&mut self, command: &str) -> Result<String, EngineError> {
         if !self.initialized {
-            return Err(EngineError::NotInitialized);
+            info!("Command received before initialization, initializing now");
         }
+        // Every command, `isready` included, sees the resized hash table.
+        self.wait_ready()?;
 
-        // TODO: Implement actual command processing
-        match command.trim() {
-            "uci" => Ok(String::from("id name BrainFish\nid author BlackBoxAI\nuciok")),
-            "isready" => Ok(String::from("readyok")),
+        let command = strip_control(command);
+        let mut tokens = command.split_whitespace();
+        match tokens.next() {
+            Some("uci") => {
+                let mut lines = vec![
+                    format!("id name {}", engine_name()),
+                    String::from("id author BlackBoxAI"),
+                ];
+                lines.extend(options::OPTIONS.iter().map(|spec| spec.uci_declaration()));
+                lines.push(String::from("uciok"));
+                Ok(lines.join("\n"))
+            }
+            Some("isready") => Ok(String::from("readyok")),
+            // Valid UCI that needs no reply from this engine.
+            Some("debug") | Some("register") => Ok(String::new()),
+            Some("setoption") => {
+                let (name, value) = Self::parse_setoption(tokens)?;
+                let hash_mb = self.options.hash_mb;
+                let seed = self.options.seed;
+                let log_file = self.options.log_file.clone();
+                let book_file = self.options.book_file.clone();
+                let syzygy_path = self.options.syzygy_path.clone();
+                self.options.set(&name, &value)?;
+                self.move_cache.set_capacity(self.options.move_cache_size);
+                if self.options.log_file != log_file {
+                    if let Err(e) = self.open_stats_log() {
+                        self.options.log_file = log_file;
+                        return Err(e);
+                    }
+                }
+                if self.options.book_file != book_file {
+                    if let Err(e) = self.load_books() {
+                        self.options.book_file = book_file;
+                        return Err(e);
+                    }
+                }
+                if self.options.hash_mb != hash_mb {
+                    // Large tables take a while to allocate; the host may get
+                    // on with other work until the next command or `wait_ready`.
+                    let megabytes = self.options.hash_mb;
+                    self.pending_tt =
+                        Some(thread::spawn(move || TranspositionTable::new(megabytes)));
+                }
+                if self.options.seed != seed {
+                    self.rng = Rng::new(self.options.seed);
+                }
+                if name.eq_ignore_ascii_case("Clear Hash") {
+                    self.tt.clear();
+                }
+                if self.options.syzygy_path != syzygy_path {
+                    return Ok(self.load_tablebases());
+                }
+                Ok(String::new())
+            }
+            Some("ucinewgame") => {
+                self.game = Game::default();
+                self.position_set = false;
+                self.tt.clear();
+                self.move_cache.clear();
+                self.rng = Rng::new(self.options.seed);
+                self.warmup = true;
+                Ok(String::new())
+            }
+            Some("position") => {
+                self.game = Self::parse_position(tokens)?;
+                self.position_set = true;
+                Ok(String::new())
+            }
+            Some("cleartt") => {
+                self.tt.clear();
+                Ok(String::new())
+            }
+            Some("tt") => Ok(match self.tt_entry() {
+                Some(entry) => format!(
+                    "tt depth {} score {} bound {} move {}",
+                    entry.depth,
+                    Score::from_internal(tt::score_from_tt(entry.score, 0)),
+                    entry.bound,
+                    entry.best_move.map_or(String::from("none"), |mv| mv.to_uci())
+                ),
+                None => String::from("tt no entry"),
+            }),
+            // One `<name> <value>` line per option; the value is the rest of
+            // the line, with empty strings shown as `<empty>`.
+            Some("options") => Ok(self
+                .options
+                .values()
+                .iter()
+                .map(|(name, value)| format!("{} {}", name, value))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            Some("fen") => Ok(self.fen()),
+            Some("moves") => Ok(self.game.movetext()),
+            Some("status") => Ok(self.game_status().to_string()),
+            Some("wdl") => Ok(self.wdl_report()),
+            Some("loadpgn") => {
+                self.game = Game::from_pgn(&tokens.collect::<Vec<_>>().join(" "))?;
+                self.position_set = true;
+                Ok(String::new())
+            }
+            Some("setfen") => {
+                let fen = tokens.collect::<Vec<_>>().join(" ");
+                self.game = Game::new(Board::from_fen(&fen)?);
+                self.position_set = true;
+                Ok(String::new())
+            }
+            Some("perftsuite") => {
+                let path = tokens.collect::<Vec<_>>().join(" ");
+                Ok(Self::perft_suite(std::path::Path::new(&path))?)
+            }
+            Some("tacticsuite") => self.tactic_suite_command(tokens),
+            Some("selfplay") => self.self_play(tokens),
+            Some("go") => self.go(tokens),
+            Some("ponderhit") => Ok(self.ponder_hit()),
+            Some("stop") | Some("quit") => Ok(self.stop()),
             _ => Ok(String::from("unknown command")),
         }
     }
 
+    /// Parses the arguments of `position [startpos | fen <fen>] [moves <m1> ...]`.
+    fn parse_position<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Result<Game, EngineError> {
+        let mut game = match tokens.next() {
+            Some("startpos") => {
+                match tokens.next() {
+                    Some("moves") | None => {}
+                    Some(other) => return Err(EngineError::InvalidCommand(other.to_string())),
+                }
+                Game::default()
+            }
+            Some("fen") => {
+                let fields: Vec<&str> = tokens.by_ref().take_while(|&t| t != "moves").collect();
+                Game::new(Board::from_fen(&fields.join(" "))?)
+            }
+            _ => return Err(EngineError::InvalidCommand(String::from("position"))),
+        };
+        for text in tokens {
+            game.play_uci(text)?;
+        }
+        Ok(game)
+    }
+
+    /// Splits `setoption name <name> [value <value>]`; both parts may contain spaces.
+    fn parse_setoption<'a>(
+        mut tokens: impl Iterator<Item = &'a str>,
+    ) -> Result<(String, String), EngineError> {
+        if tokens.next() != Some("name") {
+            return Err(EngineError::InvalidCommand(String::from("setoption")));
+        }
+        let name: Vec<&str> = tokens.by_ref().take_while(|&t| t != "value").collect();
+        if name.is_empty() {
+            return Err(EngineError::InvalidCommand(String::from("setoption")));
+        }
+        let value: Vec<&str> = tokens.collect();
+        Ok((name.join(" "), value.join(" ")))
+    }
+
+    /// Parses `go` arguments into search limits for the side to move on `board`.
+    fn parse_go<'a>(
+        mut tokens: impl Iterator<Item = &'a str>,
+        board: &Board,
+    ) -> Result<GoParams, EngineError> {
+        let side_to_move = board.side_to_move();
+        let mut limits = SearchLimits::default();
+        let mut ponder = false;
+        let mut clock = time::Clock {
+            move_number: board.fullmove_number(),
+            ..Default::default()
+        };
+        let mut timed = false;
+        while let Some(token) = tokens.next() {
+            let mut value = || -> Result<u64, EngineError> {
+                tokens
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .ok_or_else(|| EngineError::InvalidCommand(format!("go {}", token)))
+            };
+            match (token, side_to_move) {
+                ("depth", _) => limits.depth = Some(value()? as u32),
+                ("nodes", _) => limits.nodes = Some(value()?),
+                ("mate", _) => limits.mate = Some(value()? as u32),
+                ("movetime", _) => limits.movetime = Some(Duration::from_millis(value()?)),
+                ("wtime", Color::White) | ("btime", Color::Black) => {
+                    clock.remaining = Duration::from_millis(value()?);
+                    timed = true;
+                }
+                ("winc", Color::White) | ("binc", Color::Black) => {
+                    clock.increment = Duration::from_millis(value()?)
+                }
+                ("wtime" | "btime" | "winc" | "binc", _) => {
+                    value()?;
+                }
+                ("movestogo", _) => clock.moves_to_go = Some(value()? as u32),
+                ("infinite", _) => limits.infinite = true,
+                ("ponder", _) => ponder = true,
+                _ => {}
+            }
+        }
+        Ok(GoParams {
+            limits,
+            clock: timed.then_some(clock),
+            ponder,
+        })
+    }
+
+    /// Sends search output to `sink` as it happens. Every `go` then runs in
+    /// the background and returns at once, so the caller stays free to answer
+    /// `isready` and `stop` mid-search.
+    pub fn set_output(&mut self, sink: impl Fn(&str) + Send + Sync + 'static) {
+        self.output = Some(Arc::new(sink));
+    }
+
+    fn go<'a>(&mut self, tokens: impl Iterator<Item = &'a str>) -> Result<String, EngineError> {
+        // A finished timed search has already reported its bestmove.
+        if self
+            .background
+            .as_ref()
+            .is_some_and(|bg| {
+                bg.bestmove.load(Ordering::Acquire) == BESTMOVE_SEND && bg.handle.is_finished()
+            })
+        {
+            self.stop();
+        }
+        if self.background.is_some() {
+            warn!("go received while searching, restarting the search");
+            self.abort_search();
+        }
+        let mut lines = Vec::new();
+        if !self.position_set {
+            warn!("go received before position, using the start position");
+            lines.push(String::from(
+                "info string no position set, searching from the start position",
+            ));
+        }
+
+        let board = self.game.board();
+        let params = Self::parse_go(tokens, board)?;
+        // Until `ponderhit` a pondering search has no clock to keep, not
+        // even a given movetime.
+        let mut limits = if params.ponder {
+            SearchLimits {
+                movetime: None,
+                ..params.limits.clone()
+            }
+        } else {
+            params.timed_limits(&self.options)
+        };
+        if let Err(e) = board.check_consistency() {
+            // Only a bug gets here; answer safely rather than panic mid-search.
+            error!("Corrupt board, not searching: {}", e);
+            lines.push(format!("info string error: {}", e));
+            lines.push(String::from("bestmove 0000"));
+            return Ok(lines.join("\n"));
+        }
+        self.clear_tt_if_deterministic();
+        if movegen::generate_legal_moves(board).is_empty() {
+            // Mate or stalemate: nothing to search, so answer at once, even
+            // for `go infinite`.
+            let stop = AtomicBool::new(false);
+            let config = self.search_config();
+            let result = search::search(board, &limits, &config, &self.tt, &stop, |_| {});
+            let score = self.reporting(board).result(&result).score;
+            lines.push(format!("info depth 0 score {}", score));
+            lines.push(Self::bestmove_line(&result));
+            self.last_search = Some((board.clone(), result));
+            return Ok(lines.join("\n"));
+        }
+        let start = Instant::now();
+        // Checked first, so pondering doesn't draw from the book's random numbers.
+        let book = if params.ponder { None } else { self.book_move(&limits) };
+        if let Some(mv) = book {
+            lines.push(String::from("info string book move"));
+            if self.output.is_some() {
+                // Answered from the background, so `stop` cuts the wait short.
+                let config = self.search_config();
+                self.spawn_search(limits, config, lines, None, None, Some(mv));
+                return Ok(String::new());
+            }
+            let result = SearchResult {
+                best_move: Some(mv),
+                pv: vec![mv],
+                ..Default::default()
+            };
+            // A book move is no reason to answer sooner than a searched one.
+            Self::wait_minimum(start, self.minimum_thinking_time(), &AtomicBool::new(false));
+            lines.push(Self::bestmove_line(&result));
+            self.last_search = Some((self.game.board().clone(), result));
+            return Ok(lines.join("\n"));
+        }
+        let mut config = self.search_config();
+        if std::mem::take(&mut self.warmup) {
+            config.lmr_percent = self.options.lmr_warmup;
+        }
+        if params.ponder {
+            limits.deadline = Some(Arc::new(search::Deadline::new()));
+            self.spawn_search(limits, config, lines, None, Some(params), None);
+            return Ok(String::new());
+        }
+        if limits.infinite || self.output.is_some() {
+            // Without a sink nothing is printed until `stop`, which returns
+            // the buffered output.
+            self.spawn_search(limits, config, lines, None, None, None);
+            return Ok(String::new());
+        }
+
+        let weakening = self.weaken(&mut limits, &mut config);
+        let board = self.game.board();
+        let reporting = self.reporting(board);
+        let stop = AtomicBool::new(false);
+        let mut result = search::search(board, &limits, &config, &self.tt, &stop, |event| {
+            lines.extend(reporting.event_lines(event));
+        });
+        if let Some((strength, mut rng)) = weakening {
+            result = strength.choose(&result, &mut rng);
+        }
+        Self::record_stats(self.stats_log.as_deref(), board, &result, start.elapsed());
+        Self::wait_minimum(start, self.minimum_thinking_time(), &stop);
+        lines.push(Self::bestmove_line(&result));
+        self.last_search = Some((board.clone(), result));
+        Ok(lines.join("\n"))
+    }
+
+    // With UCI_LimitStrength on or a Skill Level below the top, caps the depth
+    // and asks for the lines to choose among, returning how to choose.
+    // Analysis is never weakened.
+    fn weaken(
+        &mut self,
+        limits: &mut SearchLimits,
+        config: &mut SearchConfig,
+    ) -> Option<(Strength, Rng)> {
+        if self.options.analyse_mode || limits.infinite {
+            return None;
+        }
+        let strength = if self.options.limit_strength {
+            Strength::for_elo(self.options.elo)
+        } else if self.options.skill_level < strength::MAX_SKILL_LEVEL {
+            Strength::for_skill(self.options.skill_level)
+        } else {
+            return None;
+        };
+        limits.depth = Some(limits.depth.map_or(strength.depth, |d| d.min(strength.depth)));
+        config.multi_pv = config.multi_pv.max(strength::CANDIDATES);
+        // Drawn here so a search on another thread picks reproducibly too.
+        Some((strength, Rng::new(self.rng.next_u64())))
+    }
+
+    // A move from the book, unless it is switched off, the GUI is analysing
+    // or the search has no natural end.
+    fn book_move(&mut self, limits: &SearchLimits) -> Option<Move> {
+        if !self.options.own_book || self.options.analyse_mode || limits.infinite {
+            return None;
+        }
+        let board = self.game.board();
+        let rng = &mut self.rng;
+        self.books.iter().find_map(|book| book.probe(board, rng))
+    }
+
+    // Every book in BookFile (separated like `PATH`) must load, or none is used.
+    fn load_books(&mut self) -> Result<(), EngineError> {
+        let mut books = Vec::new();
+        for path in std::env::split_paths(&self.options.book_file) {
+            if path.as_os_str().is_empty() {
+                continue;
+            }
+            let book = OpeningBook::load(&path).map_err(|e| {
+                warn!("Cannot load opening book {}: {}", path.display(), e);
+                EngineError::InvalidOptionValue {
+                    name: String::from("BookFile"),
+                    value: self.options.book_file.clone(),
+                }
+            })?;
+            info!("Loaded opening book {} with {} positions", path.display(), book.len());
+            books.push(book);
+        }
+        self.books = books;
+        Ok(())
+    }
+
+    // On failure the log already open, if any, keeps being written.
+    fn open_stats_log(&mut self) -> Result<(), EngineError> {
+        if self.options.log_file.is_empty() {
+            self.stats_log = None;
+            return Ok(());
+        }
+        let log = StatsLog::open(std::path::Path::new(&self.options.log_file)).map_err(|e| {
+            warn!("Cannot open log file {}: {}", self.options.log_file, e);
+            EngineError::InvalidOptionValue {
+                name: String::from("LogFile"),
+                value: self.options.log_file.clone(),
+            }
+        })?;
+        self.stats_log = Some(Arc::new(Mutex::new(log)));
+        Ok(())
+    }
+
+    /// Whether the game given by the last `position` command is over,
+    /// judged on the full move history.
+    pub fn game_status(&self) -> GameStatus {
+        self.game.status()
+    }
+
+    /// Whether any loaded book has a move for the current position. Ignores
+    /// OwnBook, so a GUI can show it either way.
+    pub fn in_book(&self) -> bool {
+        self.books.iter().any(|book| book.contains(self.game.board()))
+    }
+
+    /// What the hash table holds for the current position, if anything.
+    pub fn tt_entry(&self) -> Option<tt::TtEntry> {
+        self.tt.probe(self.game.board().hash())
+    }
+
+    /// Tablebase files found under `SyzygyPath`.
+    pub fn tablebases(&self) -> &Tablebases {
+        &self.tablebases
+    }
+
+    /// Looks up positions the loaded tables cover, for the `wdl` command.
+    pub fn set_tablebase_probe(
+        &mut self,
+        probe: impl Fn(&Board) -> Option<TableResult> + Send + Sync + 'static,
+    ) {
+        self.tablebase_probe = Some(Arc::new(probe));
+    }
+
+    // Without a probe, tables for the position can be found but not read.
+    fn wdl_report(&self) -> String {
+        let board = self.game.board();
+        if !self.tablebases.covers(board) {
+            return tablebase::describe(board, None);
+        }
+        match &self.tablebase_probe {
+            Some(probe) => tablebase::describe(board, probe(board)),
+            None => String::from("wdl tables found but probing unsupported"),
+        }
+    }
+
+    /// Loads tablebases from `SyzygyPath`. Bad or missing files are
+    /// skipped with a warning; the returned `info string` reports the result.
+    fn load_tablebases(&mut self) -> String {
+        let (tablebases, warnings) = Tablebases::load(&self.options.syzygy_path);
+        let mut lines = Vec::new();
+        for warning in &warnings {
+            warn!("Syzygy: {}", warning);
+            lines.push(format!("info string Syzygy: {}", warning));
+        }
+        lines.push(if tablebases.is_empty() {
+            String::from("info string Syzygy: no tablebases loaded, probing disabled")
+        } else {
+            format!(
+                "info string Syzygy: found {} tables up to {} pieces",
+                tablebases.len(),
+                tablebases.max_pieces()
+            )
+        });
+        self.tablebases = tablebases;
+        lines.join("\n")
+    }
+
+    fn record_stats(
+        log: Option<&Mutex<StatsLog>>,
+        board: &Board,
+        result: &SearchResult,
+        elapsed: Duration,
+    ) {
+        let Some(log) = log else {
+            return;
+        };
+        let mut log = log.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Err(e) = log.record(&board.to_fen(), result, elapsed) {
+            warn!("Failed to write search statistics: {}", e);
+        }
+    }
+
+    // Analysis answers as soon as it is done.
+    fn minimum_thinking_time(&self) -> Duration {
+        if self.options.analyse_mode {
+            return Duration::ZERO;
+        }
+        Duration::from_millis(self.options.minimum_thinking_ms)
+    }
+
+    /// Holds a finished timed search back until `minimum` has passed since
+    /// `start`, so replies never come instantly. `stop` cuts the wait short.
+    fn wait_minimum(start: Instant, minimum: Duration, stop: &AtomicBool) {
+        while !stop.load(Ordering::Relaxed) {
+            let remaining = minimum.saturating_sub(start.elapsed());
+            if remaining.is_zero() {
+                break;
+            }
+            thread::sleep(remaining.min(Duration::from_millis(5)));
+        }
+    }
+
+    fn reporting(&self, board: &Board) -> Reporting {
+        Reporting {
+            wdl: self.options.show_wdl.then(|| self.options.wdl_model()),
+            flip: self.options.score_white_pov && board.side_to_move() == Color::Black,
+            pv_limit: self.options.pv_limit,
+            lines: self.options.multi_pv,
+        }
+    }
+
+    fn bestmove_line(result: &SearchResult) -> String {
+        let best = result.best_move.map_or(String::from("0000"), |mv| mv.to_uci());
+        format!("bestmove {}", best)
+    }
+
+    /// Starts searching the current position on a background thread. Output
+    /// goes to the sink if there is one and is buffered in `lines` otherwise;
+    /// each completed depth is also sent to `snapshots`. A search for
+    /// `ponder` keeps its `bestmove` until `ponderhit`; with a `book` move
+    /// there is no search, only the wait for the minimum thinking time.
+    fn spawn_search(
+        &mut self,
+        mut limits: SearchLimits,
+        mut config: SearchConfig,
+        mut lines: Vec<String>,
+        snapshots: Option<Sender<SearchResult>>,
+        ponder: Option<GoParams>,
+        book: Option<Move>,
+    ) {
+        let output = self.output.clone();
+        if let Some(output) = &output {
+            lines.drain(..).for_each(|line| output(&line));
+        }
+        let infinite = limits.infinite;
+        let board = self.game.board().clone();
+        let weakening = match book {
+            Some(_) => None,
+            None => self.weaken(&mut limits, &mut config),
+        };
+        let reporting = self.reporting(&board);
+        let tt = Arc::clone(&self.tt);
+        let stats_log = self.stats_log.clone();
+        let minimum = self.minimum_thinking_time();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let discard = Arc::new(AtomicBool::new(false));
+        let thread_discard = Arc::clone(&discard);
+        let hold = infinite || ponder.is_some();
+        let deadline = limits.deadline.clone();
+        let bestmove = Arc::new(AtomicU8::new(if hold { BESTMOVE_HOLD } else { BESTMOVE_SEND }));
+        let thread_bestmove = Arc::clone(&bestmove);
+        let handle = thread::spawn(move || {
+            let on_event = |event: SearchEvent<'_>| {
+                if let SearchEvent::Iteration { result, .. } = event {
+                    if let Some(snapshots) = &snapshots {
+                        // The receiver may have been dropped; keep searching until stopped.
+                        let _ = snapshots.send(result.clone());
+                    }
+                }
+                for line in reporting.event_lines(event) {
+                    match &output {
+                        Some(output) => output(&line),
+                        None => lines.push(line),
+                    }
+                }
+            };
+            let start = Instant::now();
+            let result = match book {
+                Some(mv) => SearchResult {
+                    best_move: Some(mv),
+                    pv: vec![mv],
+                    ..Default::default()
+                },
+                None => {
+                    let mut result =
+                        search::search(&board, &limits, &config, &tt, &thread_stop, on_event);
+                    if let Some((strength, mut rng)) = weakening {
+                        result = strength.choose(&result, &mut rng);
+                    }
+                    Self::record_stats(stats_log.as_deref(), &board, &result, start.elapsed());
+                    result
+                }
+            };
+            if !infinite {
+                Self::wait_minimum(start, minimum, &thread_stop);
+            }
+            let bestmove = Self::bestmove_line(&result);
+            // Pairs with the release store of `stop` in `abort_search`.
+            std::sync::atomic::fence(Ordering::Acquire);
+            // Once held, a `ponderhit` can no longer hand the move to us.
+            let held = thread_bestmove
+                .compare_exchange(BESTMOVE_HOLD, BESTMOVE_HELD, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok();
+            match &output {
+                _ if thread_discard.load(Ordering::Relaxed) => {}
+                Some(output) if !held => output(&bestmove),
+                _ => lines.push(bestmove),
+            }
+            FinishedSearch {
+                lines,
+                board,
+                result,
+            }
+        });
+        self.background = Some(BackgroundSearch {
+            stop,
+            handle,
+            bestmove,
+            discard,
+            ponder,
+            deadline,
+        });
+    }
+
+    /// Analyzes the current position until `stop`, sending a snapshot after
+    /// every completed depth. The channel closes once the search has stopped.
+    pub fn analyze_stream(&mut self) -> Result<Receiver<SearchResult>, EngineError> {
+        if !self.initialized {
+            return Err(EngineError::NotInitialized);
+        }
+        if self.background.is_some() {
+            return Err(EngineError::InvalidCommand(String::from(
+                "analyze: a search is already running",
+            )));
+        }
+        let (sender, receiver) = mpsc::channel();
+        let limits = SearchLimits {
+            infinite: true,
+            ..Default::default()
+        };
+        let config = self.search_config();
+        self.clear_tt_if_deterministic();
+        self.spawn_search(limits, config, Vec::new(), Some(sender), None, None);
+        Ok(receiver)
+    }
+
+    /// Runs an engine on its own thread, driven like the UCI binary: each
+    /// command's response and all search output come back line by line.
+    /// `quit`, or dropping the sender, stops it and closes the receiver.
+    pub fn spawn() -> (CommandSender, ResponseReceiver) {
+        let (command_sender, commands) = mpsc::channel::<String>();
+        let (response_sender, responses) = mpsc::channel();
+        thread::spawn(move || {
+            let send = |text: &str| {
+                // A dropped receiver only means nobody is listening any more.
+                text.lines().for_each(|line| {
+                    let _ = response_sender.send(line.to_string());
+                });
+            };
+            let mut engine = Engine::new();
+            if let Err(e) = engine.initialize() {
+                send(&format!("error initializing engine: {}", e));
+                return;
+            }
+            let sink = response_sender.clone();
+            engine.set_output(move |line| {
+                let _ = sink.send(line.to_string());
+            });
+            for command in commands {
+                match engine.process_command(&command) {
+                    Ok(response) => send(&response),
+                    Err(e) => send(&format!("error processing command: {}", e)),
+                }
+                if command.split_whitespace().next() == Some("quit") {
+                    break;
+                }
+            }
+            send(&engine.stop());
+        });
+        (command_sender, responses)
+    }
+
+    /// Stops a background search and returns its output, or an empty string
+    /// when none is running.
+    pub fn stop(&mut self) -> String {
+        if let Some(background) = &self.background {
+            background.stop.store(true, Ordering::Relaxed);
+        }
+        self.join_search()
+    }
+
+    // Waits for the background search to end by itself and returns its output.
+    fn join_search(&mut self) -> String {
+        let Some(background) = self.background.take() else {
+            return String::new();
+        };
+        match background.handle.join() {
+            Ok(finished) => {
+                self.last_search = Some((finished.board, finished.result));
+                finished.lines.join("\n")
+            }
+            Err(_) => {
+                error!("Search thread panicked");
+                String::from("bestmove 0000")
+            }
+        }
+    }
+
+    /// The opponent played the expected move: the pondering search goes on as
+    /// an ordinary one, its time worked out from the `go ponder` clock now.
+    /// Without a sink this waits for the search and returns its output, unless
+    /// it has no limit, in which case `stop` collects that.
+    fn ponder_hit(&mut self) -> String {
+        let Some(background) = &mut self.background else {
+            return String::new();
+        };
+        let Some(params) = background.ponder.take() else {
+            return String::new();
+        };
+        let sent = background.bestmove.compare_exchange(
+            BESTMOVE_HOLD,
+            BESTMOVE_SEND,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        );
+        if sent.is_err() {
+            // Done while pondering; its output is waiting to be collected.
+            return self.stop();
+        }
+        let limits = params.timed_limits(&self.options);
+        if let (Some(movetime), Some(deadline)) = (limits.movetime, &background.deadline) {
+            deadline.set(movetime);
+        }
+        let bounded = limits.movetime.is_some() || limits.depth.is_some() || limits.nodes.is_some();
+        if self.output.is_none() && bounded {
+            return self.join_search();
+        }
+        String::new()
+    }
+
+    /// Stops a background search without reporting it and waits for its
+    /// thread, so the next search has the transposition table to itself.
+    fn abort_search(&mut self) {
+        let Some(background) = self.background.take() else {
+            return;
+        };
+        // Marked before stopping so the thread sees it once the search ends.
+        background.discard.store(true, Ordering::Relaxed);
+        background.stop.store(true, Ordering::Release);
+        if background.handle.join().is_err() {
+            error!("Search thread panicked");
+        }
+    }
+
+    /// The principal variation of the last finished search, each move paired
+    /// with the FEN after it. Stops early if a move is not legal.
+    pub fn pv_positions(&self) -> Vec<(Move, String)> {
+        let Some((board, result)) = &self.last_search else {
+            return Vec::new();
+        };
+        let mut board = board.clone();
+        let mut positions = Vec::new();
+        for &mv in &result.pv {
+            if !movegen::generate_legal_moves(&board).contains(&mv) {
+                break;
+            }
+            board.make_move(mv);
+            positions.push((mv, board.to_fen()));
+        }
+        positions
+    }
+
+    // Deterministic searches start from an empty table, so what earlier
+    // searches left behind can't change their results.
+    fn clear_tt_if_deterministic(&self) {
+        if self.options.deterministic {
+            self.tt.clear();
+        }
+    }
+
+    fn search_config(&self) -> SearchConfig {
+        SearchConfig {
+            threads: self.options.threads,
+            deterministic: self.options.deterministic,
+            aspiration_delta: self.options.aspiration_delta,
+            lmr_base: self.options.lmr_base,
+            lmr_divisor: self.options.lmr_divisor,
+            razor_margin: self.options.razor_margin,
+            analyse_mode: self.options.analyse_mode,
+            multi_pv: self.options.multi_pv,
+            currline: self.options.show_currline,
+            ..Default::default()
+        }
+    }
+
+    /// FEN of the current position.
+    pub fn fen(&self) -> String {
+        self.game.board().to_fen()
+    }
+
     pub fn analyze_position(&self, fen: &str, depth: i32) -> Result<String, EngineError> {
         if !self.initialized {
             return Err(EngineError::NotInitialized);
         }
 
-        if !self.validate_fen(fen) {
-            return Err(EngineError::InvalidFen);
+        // An empty FEN analyzes the current position, which is the start
+        // position until a `position` command says otherwise.
+        let board = match fen.trim() {
+            "" => self.game.board().clone(),
+            fen => Board::from_fen(fen)?,
+        };
+        let limits = SearchLimits {
+            depth: Some(depth.max(1) as u32),
+            ..Default::default()
+        };
+        let start = std::time::Instant::now();
+        let stop = AtomicBool::new(false);
+        self.clear_tt_if_deterministic();
+        let result =
+            search::search(&board, &limits, &self.search_config(), &self.tt, &stop, |_| {});
+        let reporting = self.reporting(&board);
+        Ok(search::format_info(&reporting.result(&result), start.elapsed(), reporting.wdl))
+    }
+
+    /// Searches each `(fen, depth)` pair, results in the same order. An empty
+    /// FEN is the current position. With several Threads the positions are
+    /// shared out among them, each searched on one thread; all of them share
+    /// the hash table, which is kept from one position to the next.
+    pub fn analyze_batch(
+        &self,
+        positions: &[(String, i32)],
+    ) -> Vec<Result<SearchResult, EngineError>> {
+        if !self.initialized {
+            return positions.iter().map(|_| Err(EngineError::NotInitialized)).collect();
+        }
+        let mut config = self.search_config();
+        let workers = if config.deterministic { 1 } else { config.threads };
+        let workers = workers.clamp(1, positions.len().max(1));
+        if workers > 1 {
+            config.threads = 1;
+        }
+        let analyze = |(fen, depth): &(String, i32)| {
+            let board = match fen.trim() {
+                "" => self.game.board().clone(),
+                fen => Board::from_fen(fen)?,
+            };
+            let limits = SearchLimits {
+                depth: Some((*depth).max(1) as u32),
+                ..Default::default()
+            };
+            let stop = AtomicBool::new(false);
+            self.clear_tt_if_deterministic();
+            Ok(search::search(&board, &limits, &config, &self.tt, &stop, |_| {}))
+        };
+        let next = AtomicUsize::new(0);
+        let mut results: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut done = Vec::new();
+                        loop {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(position) = positions.get(index) else {
+                                return done;
+                            };
+                            done.push((index, analyze(position)));
+                        }
+                    })
+                })
+                .collect();
+            handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+        });
+        results.sort_by_key(|&(index, _)| index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Checks movegen against the perft counts in an EPD file, one line per
+    /// position and a summary at the end.
+    pub fn perft_suite(path: &std::path::Path) -> Result<String, EngineError> {
+        let outcomes = epd::run_perft_suite(&epd::load_perft_suite(path)?);
+        let passed = outcomes.iter().filter(|o| o.passed()).count();
+        let mut lines: Vec<String> = outcomes.iter().map(|o| o.to_string()).collect();
+        lines.push(format!("perftsuite: {}/{} passed", passed, outcomes.len()));
+        Ok(lines.join("\n"))
+    }
+
+    /// Parses `tacticsuite <path> [depth <n>] [movetime <ms>]`; without a
+    /// limit each position is searched to depth 6.
+    fn tactic_suite_command<'a>(
+        &self,
+        tokens: impl Iterator<Item = &'a str>,
+    ) -> Result<String, EngineError> {
+        let tokens: Vec<&str> = tokens.collect();
+        let split = tokens
+            .iter()
+            .position(|&t| t == "depth" || t == "movetime")
+            .unwrap_or(tokens.len());
+        let path = tokens[..split].join(" ");
+        let mut limits = SearchLimits::default();
+        for pair in tokens[split..].chunks(2) {
+            let value = pair.get(1).and_then(|v| v.parse::<u64>().ok()).ok_or_else(|| {
+                EngineError::InvalidCommand(format!("tacticsuite {}", pair.join(" ")))
+            })?;
+            match pair[0] {
+                "depth" => limits.depth = Some(value as u32),
+                "movetime" => limits.movetime = Some(Duration::from_millis(value)),
+                other => return Err(EngineError::InvalidCommand(other.to_string())),
+            }
+        }
+        if limits.depth.is_none() && limits.movetime.is_none() {
+            limits.depth = Some(6);
+        }
+        self.tactic_suite(std::path::Path::new(&path), &limits)
+    }
+
+    /// Parses `selfplay <movetime_ms>`: the engine plays both sides from the
+    /// current position, searching each move for that long, until the game is
+    /// over, and answers with the game as PGN. The current position is kept.
+    fn self_play<'a>(
+        &mut self,
+        mut tokens: impl Iterator<Item = &'a str>,
+    ) -> Result<String, EngineError> {
+        let movetime = tokens
+            .next()
+            .and_then(|t| t.parse::<u64>().ok())
+            .ok_or_else(|| EngineError::InvalidCommand(String::from("selfplay")))?;
+        self.abort_search();
+        let limits = SearchLimits {
+            movetime: Some(Duration::from_millis(movetime)),
+            ..Default::default()
+        };
+        let config = self.search_config();
+        let stop = AtomicBool::new(false);
+        let mut game = self.game.clone();
+        // Mate, stalemate, threefold repetition, fifty moves or too little
+        // material always comes, so this ends.
+        while game.status() == GameStatus::Ongoing {
+            self.clear_tt_if_deterministic();
+            let result = search::search(game.board(), &limits, &config, &self.tt, &stop, |_| {});
+            let Some(mv) = result.best_move else {
+                break;
+            };
+            game.play(mv);
         }
+        let name = engine_name();
+        Ok(game.pgn(&name, &name))
+    }
+
+    /// Searches every `bm`/`am` position in an EPD file within `limits`,
+    /// one line per position and a solved/total summary at the end.
+    pub fn tactic_suite(
+        &self,
+        path: &std::path::Path,
+        limits: &SearchLimits,
+    ) -> Result<String, EngineError> {
+        let cases = epd::load_tactic_suite(path)?;
+        let outcomes = epd::run_tactic_suite(&cases, limits, &self.search_config(), &self.tt);
+        let solved = outcomes.iter().filter(|o| o.solved).count();
+        let mut lines: Vec<String> = outcomes.iter().map(|o| o.to_string()).collect();
+        lines.push(format!("tacticsuite: {}/{} solved", solved, outcomes.len()));
+        Ok(lines.join("\n"))
+    }
+
+    /// Legal moves in `fen`, or in the current game when `fen` is empty.
+    /// Recently asked-for positions are answered from `move_cache`.
+    pub fn legal_moves(&mut self, fen: &str) -> Result<Vec<Move>, EngineError> {
+        Ok(match fen.trim() {
+            "" => self.move_cache.legal_moves(self.game.board()),
+            fen => self.move_cache.legal_moves(&Board::from_fen(fen)?),
+        })
+    }
+
+    /// Legal moves, in UCI notation, of the piece on `square` (e.g. `b1`) in
+    /// `fen`, or in the current game when `fen` is empty. An empty or enemy
+    /// square has none.
+    pub fn moves_from_square(
+        &mut self,
+        fen: &str,
+        square: &str,
+    ) -> Result<Vec<String>, EngineError> {
+        let from = types::Square::from_algebraic(square.trim())
+            .ok_or_else(|| EngineError::InvalidSquare(square.to_string()))?;
+        Ok(self
+            .legal_moves(fen)?
+            .into_iter()
+            .filter(|mv| mv.from == from)
+            .map(|mv| mv.to_uci())
+            .collect())
+    }
+
+    /// Legal captures in `fen`, or in the current game when `fen` is empty:
+    /// en passant included, promotions only if they capture too.
+    pub fn capture_moves(&self, fen: &str) -> Result<Vec<Move>, EngineError> {
+        let board = match fen.trim() {
+            "" => self.game.board().clone(),
+            fen => Board::from_fen(fen)?,
+        };
+        Ok(movegen::generate_captures(&board)
+            .into_iter()
+            .filter(|mv| mv.promotion.is_none() || board.piece_at(mv.to).is_some())
+            .collect())
+    }
+
+    /// The cache behind `legal_moves`, sized by the Move Cache option.
+    pub fn move_cache(&self) -> &MoveCache {
+        &self.move_cache
+    }
+
+    /// Static evaluation of `fen` (the current game when empty) in
+    /// centipawns from White's point of view, for eval bars. The search keeps
+    /// using side-to-move scores.
+    pub fn evaluate_white_pov(&self, fen: &str) -> Result<i32, EngineError> {
+        let board = match fen.trim() {
+            "" => self.game.board().clone(),
+            fen => Board::from_fen(fen)?,
+        };
+        let eval = eval::evaluate(&board);
+        Ok(match board.side_to_move() {
+            Color::White => eval,
+            Color::Black => -eval,
+        })
+    }
 
-        // TODO: Implement actual position analysis
-        Ok(format!("info depth {} score cp 100 pv e2e4 e7e5", depth))
+    /// Side to move, legal move count, check, game status, static eval and
+    /// material for `fen`, or for the current game when `fen` is empty.
+    pub fn position_report(&self, fen: &str) -> Result<PositionReport, EngineError> {
+        Ok(match fen.trim() {
+            "" => PositionReport::new(&self.game),
+            fen => PositionReport::new(&Game::new(Board::from_fen(fen)?)),
+        })
     }
 
-    fn validate_fen(&self, fen: &str) -> bool {
-        // TODO: Implement proper FEN validation
-        !fen.is_empty() && fen.contains('/')
+    /// Scores each of `moves` (in UCI notation) in the position `fen` by
+    /// searching the reply to `depth` plies in total. Scores are from the
+    /// point of view of the side to move in `fen`. Fails on the first
+    /// illegal move before searching anything.
+    pub fn compare_moves(
+        &self,
+        fen: &str,
+        moves: &[&str],
+        depth: u32,
+    ) -> Result<Vec<(String, Score)>, EngineError> {
+        let start = Board::from_fen(fen)?;
+        let children = moves
+            .iter()
+            .map(|&text| {
+                let mut game = Game::new(start.clone());
+                game.play_uci(text)?;
+                Ok((text.to_string(), game.board().clone()))
+            })
+            .collect::<Result<Vec<_>, EngineError>>()?;
+
+        let limits = SearchLimits {
+            depth: Some(depth.saturating_sub(1).max(1)),
+            ..Default::default()
+        };
+        let config = self.search_config();
+        let stop = AtomicBool::new(false);
+        Ok(children
+            .into_iter()
+            .map(|(text, board)| {
+                let score = if !movegen::generate_legal_moves(&board).is_empty() {
+                    self.clear_tt_if_deterministic();
+                    search::search(&board, &limits, &config, &self.tt, &stop, |_| {}).score
+                } else if board.in_check() {
+                    Score::from_internal(-search::MATE)
+                } else {
+                    Score::Cp(0)
+                };
+                (text, score.for_parent())
+            })
+            .collect())
     }
 }
 
@@ -89,8 +1401,13 @@ pub extern "C" fn engine_new() -> *mut Engine {
     }
 }
 
+/// Stops any running search and waits for it before freeing the engine.
+///
+/// # Safety
+///
+/// `ptr` must be null or a pointer returned by `engine_new` that has not been freed.
 #[no_mangle]
-pub extern "C" fn engine_free(ptr: *mut Engine) {
+pub unsafe extern "C" fn engine_free(ptr: *mut Engine) {
     if !ptr.is_null() {
         unsafe {
             drop(Box::from_raw(ptr));
@@ -98,8 +1415,11 @@ pub extern "C" fn engine_free(ptr: *mut Engine) {
     }
 }
 
+/// # Safety
+///
+/// `ptr` must be null or a live pointer returned by `engine_new`.
 #[no_mangle]
-pub extern "C" fn engine_initialize(ptr: *mut Engine) -> bool {
+pub unsafe extern "C" fn engine_initialize(ptr: *mut Engine) -> bool {
     let engine = unsafe {
         if ptr.is_null() {
             error!("Null pointer passed to engine_initialize");
@@ -117,17 +1437,39 @@ pub extern "C" fn engine_initialize(ptr: *mut Engine) -> bool {
     }
 }
 
+/// Whether the current position is in a loaded book; false for a null `ptr`.
+///
+/// # Safety
+///
+/// `ptr` must be null or a live pointer returned by `engine_new`.
 #[no_mangle]
-pub extern "C" fn engine_process_command(
-    ptr: *const Engine,
+pub unsafe extern "C" fn engine_in_book(ptr: *const Engine) -> bool {
+    match unsafe { ptr.as_ref() } {
+        Some(engine) => engine.in_book(),
+        None => {
+            error!("Null pointer passed to engine_in_book");
+            false
+        }
+    }
+}
+
+/// # Safety
+///
+/// `ptr` must be null or a live pointer returned by `engine_new`, and `command`
+/// must be null or a valid NUL-terminated string. The returned string must be
+/// released with `engine_free_string`.
+#[no_mangle]
+pub unsafe extern "C" fn engine_process_command(
+    ptr: *mut Engine,
     command: *const c_char,
 ) -> *mut c_char {
-    let result = std::panic::catch_unwind(|| {
+    // A panic abandons the command; the engine itself is left as it was.
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
         let engine = unsafe {
             if ptr.is_null() {
                 return CString::new("null engine pointer").unwrap().into_raw();
             }
-            &*ptr
+            &mut *ptr
         };
 
         let c_str = unsafe {
@@ -137,16 +1479,11 @@ pub extern "C" fn engine_process_command(
             CStr::from_ptr(command)
         };
 
-        let command_str = match c_str.to_str() {
-            Ok(s) => s,
-            Err(_) => return CString::new("invalid UTF-8").unwrap().into_raw(),
-        };
-
-        match engine.process_command(command_str) {
+        match engine.process_command(&decode_command(c_str.to_bytes())) {
             Ok(response) => CString::new(response).unwrap().into_raw(),
             Err(e) => CString::new(format!("error: {}", e)).unwrap().into_raw(),
         }
-    });
+    }));
 
     match result {
         Ok(ptr) => ptr,
@@ -154,13 +1491,52 @@ pub extern "C" fn engine_process_command(
     }
 }
 
+/// The legal captures in `fen` (the current game when empty) as UCI moves
+/// separated by spaces, for highlighting threatened pieces. Errors come back
+/// as "error: ..." like `engine_process_command`'s.
+///
+/// # Safety
+///
+/// `ptr` must be null or a live pointer returned by `engine_new`, and `fen`
+/// must be null or a valid NUL-terminated string. The returned string must be
+/// released with `engine_free_string`.
 #[no_mangle]
-pub extern "C" fn engine_free_string(ptr: *mut c_char) {
-    if !ptr.is_null() {
-        unsafe {
-            drop(CString::from_raw(ptr));
-        }
-    }
+pub unsafe extern "C" fn engine_capture_moves(
+    ptr: *const Engine,
+    fen: *const c_char,
+) -> *mut c_char {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let Some(engine) = (unsafe { ptr.as_ref() }) else {
+            return CString::new("null engine pointer").unwrap().into_raw();
+        };
+        if fen.is_null() {
+            return CString::new("null FEN pointer").unwrap().into_raw();
+        }
+        // Cleaned up like a command, so stray bytes give an invalid FEN error.
+        let fen = decode_command(unsafe { CStr::from_ptr(fen) }.to_bytes());
+        let text = match engine.capture_moves(&fen) {
+            Ok(moves) => moves.iter().map(|mv| mv.to_uci()).collect::<Vec<_>>().join(" "),
+            Err(e) => format!("error: {}", e),
+        };
+        CString::new(text).unwrap().into_raw()
+    }));
+
+    match result {
+        Ok(ptr) => ptr,
+        Err(_) => CString::new("panic occurred").unwrap().into_raw(),
+    }
+}
+
+/// # Safety
+///
+/// `ptr` must be null or a string returned by this library that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn engine_free_string(ptr: *mut c_char) {
+    if !ptr.is_null() {
+        unsafe {
+            drop(CString::from_raw(ptr));
+        }
+    }
 }
 
 // Tests
@@ -187,4 +1563,1371 @@ mod tests {
         let response = engine.process_command("isready").unwrap();
         assert_eq!(response, "readyok");
     }
+
+    #[test]
+    fn test_minimum_thinking_time_delays_forced_moves() {
+        let mut engine = Engine::new();
+        engine
+            .process_command("setoption name Minimum Thinking Time value 300")
+            .unwrap();
+        // Kxb2 is the only legal move.
+        engine.process_command("position fen k7/8/8/8/8/8/1q6/K7 w - - 0 1").unwrap();
+        let start = Instant::now();
+        let response = engine.process_command("go wtime 60000 btime 60000").unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert!(response.ends_with("bestmove a1b2"));
+        // Not while analysing.
+        engine.process_command("setoption name UCI_AnalyseMode value true").unwrap();
+        let start = Instant::now();
+        engine.process_command("go depth 1").unwrap();
+        assert!(start.elapsed() < Duration::from_millis(300));
+        engine.process_command("setoption name UCI_AnalyseMode value false").unwrap();
+
+        // A stop cuts the wait short.
+        engine.process_command("setoption name Minimum Thinking Time value 5000").unwrap();
+        engine.set_output(|_| {});
+        let start = Instant::now();
+        engine.process_command("go movetime 1").unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        engine.process_command("stop").unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_crlf_and_extra_spaces_are_ignored() {
+        let mut engine = Engine::new();
+        assert_eq!(engine.process_command("isready\r\n").unwrap(), "readyok");
+        engine
+            .process_command("  position  startpos   moves e2e4\te7e5  g1f3\r\n")
+            .unwrap();
+        assert_eq!(engine.process_command("moves\r").unwrap(), "1. e4 e5 2. Nf3");
+        engine
+            .process_command("position fen 4k3/8/8/8/8/8/8/4K2R  w  K -  0 1 moves e1g1\r\n")
+            .unwrap();
+        assert_eq!(engine.fen(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+        engine
+            .process_command("setoption name  Move   Overhead value 30 \r\n")
+            .unwrap();
+        assert_eq!(engine.options.move_overhead_ms, 30);
+        let response = engine.process_command("go depth 1\r\n").unwrap();
+        assert!(response.lines().last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn test_stray_control_characters_and_bytes_are_dropped() {
+        let mut engine = Engine::new();
+        assert_eq!(engine.process_command("\u{7}is\u{0}ready\u{1b}\r\n").unwrap(), "readyok");
+        engine.process_command("position startpos\u{1} moves e2e4\u{7f}").unwrap();
+        assert_eq!(engine.process_command("moves").unwrap(), "1. e4");
+        // Only C0 and C1 controls go; tabs still separate tokens.
+        assert_eq!(decode_command(b"go\tdepth\x1f 1\r\n"), "go\tdepth 1\r\n");
+        assert_eq!(decode_command(b"stop\xff\xfe"), "stop\u{fffd}\u{fffd}");
+
+        let ptr = engine_new();
+        unsafe {
+            let command = CString::new(b"\xffisready\x01".to_vec()).unwrap();
+            let raw = engine_process_command(ptr, command.as_ptr());
+            // The replacement character is still glued to the command.
+            assert_eq!(CStr::from_ptr(raw).to_str().unwrap(), "unknown command");
+            engine_free_string(raw);
+            let command = CString::new(b"isready\x01 \xff".to_vec()).unwrap();
+            let raw = engine_process_command(ptr, command.as_ptr());
+            assert_eq!(CStr::from_ptr(raw).to_str().unwrap(), "readyok");
+            engine_free_string(raw);
+            engine_free(ptr);
+        }
+    }
+
+    #[test]
+    fn test_position_report() {
+        let engine = Engine::new();
+        // Black, a rook down, is in check from the bishop on b5.
+        let report = engine
+            .position_report("r3k3/8/8/1B6/8/8/8/R3K2R b KQq - 0 1")
+            .unwrap();
+        assert_eq!(report.side_to_move, Color::Black);
+        assert!(report.in_check);
+        assert_eq!(report.legal_moves, 4);
+        assert!(report.material_balance > 0);
+        assert!(report.eval_cp < 0);
+        assert_eq!(report.status, GameStatus::Ongoing);
+        assert!(engine.position_report("bad fen").is_err());
+        assert_eq!(engine.position_report("").unwrap().legal_moves, 20);
+    }
+
+    #[test]
+    fn test_compare_moves_ranks_the_capture_first() {
+        let engine = Engine::new();
+        let fen = "4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1";
+        let scores = engine.compare_moves(fen, &["d2d5", "d2d3"], 3).unwrap();
+        assert_eq!(scores[0].0, "d2d5");
+        assert!(scores[0].1.to_internal() > 400, "{:?}", scores);
+        assert!(scores[1].1.to_internal() < -500, "{:?}", scores);
+
+        let mate = engine.compare_moves("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", &["a1a8"], 2);
+        assert_eq!(mate.unwrap()[0].1, Score::Mate(1));
+        assert!(matches!(
+            engine.compare_moves(fen, &["d2d5", "d2e4"], 3),
+            Err(EngineError::IllegalMove(mv)) if mv == "d2e4"
+        ));
+    }
+
+    #[test]
+    fn test_isready_before_uci_initializes() {
+        let mut engine = Engine::new();
+        assert_eq!(engine.process_command("isready").unwrap(), "readyok");
+        assert!(engine.initialized);
+        assert!(engine.process_command("uci").unwrap().ends_with("uciok"));
+        assert_eq!(engine.process_command("debug on").unwrap(), "");
+    }
+
+    #[test]
+    fn test_go_before_position_searches_start_position() {
+        let mut engine = Engine::new();
+        let response = engine.process_command("go depth 2").unwrap();
+        assert!(response.starts_with("info string no position set"));
+        assert!(response.lines().last().unwrap().starts_with("bestmove "));
+        assert_eq!(engine.fen(), board::START_FEN);
+    }
+
+    #[test]
+    fn test_id_name_includes_version() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        let response = engine.process_command("uci").unwrap();
+        let id = response.lines().find(|line| line.starts_with("id name ")).unwrap();
+        assert!(id.starts_with(&format!("id name BrainFish {}", env!("CARGO_PKG_VERSION"))));
+    }
+
+    #[test]
+    fn test_setfen_fen_round_trip() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4";
+        assert_eq!(engine.process_command(&format!("setfen {}", fen)).unwrap(), "");
+        assert_eq!(engine.process_command("fen").unwrap(), fen);
+    }
+
+    #[test]
+    fn test_setfen_rejects_invalid_fen() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+
+        let before = engine.fen();
+        let err = engine.process_command("setfen rnbqkbnr/pppppppp/8 w KQkq - 0 1");
+        assert!(matches!(err, Err(EngineError::InvalidFen(FenError::RankCount(3)))));
+        assert_eq!(engine.fen(), before);
+    }
+
+    #[test]
+    fn test_position_command_with_moves() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+
+        engine
+            .process_command("position startpos moves e2e4 c7c5 g1f3")
+            .unwrap();
+        assert_eq!(
+            engine.fen(),
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+
+        let err = engine.process_command("position startpos moves e2e5");
+        assert!(matches!(err, Err(EngineError::IllegalMove(_))));
+    }
+
+    #[test]
+    fn test_loadpgn_sets_the_final_position() {
+        let mut engine = Engine::new();
+        let pgn = "1. e4 e5 2. Nf3 Nc6 3. Bb5 {Ruy Lopez} a6 4. Bxc6 dxc6 5. O-O 1-0";
+        assert_eq!(engine.process_command(&format!("loadpgn {}", pgn)).unwrap(), "");
+        assert_eq!(
+            engine.fen(),
+            "r1bqkbnr/1pp2ppp/p1p5/4p3/4P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 1 5"
+        );
+        let moves = engine.process_command("moves").unwrap();
+        assert_eq!(moves, "1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Bxc6 dxc6 5. O-O");
+
+        let error = engine.process_command("loadpgn 1. e4 e5 2. Nf3 Nf6 3. Bb6").unwrap_err();
+        assert!(matches!(&error, EngineError::InvalidSan { number: 3, text } if text == "Bb6"));
+        assert_eq!(error.to_string(), "Invalid SAN move Bb6 at move 3");
+        // A failed load keeps the game that was there.
+        assert_eq!(engine.game.moves().len(), 9);
+    }
+
+    #[test]
+    fn test_moves_command_outputs_san_movetext() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+
+        engine
+            .process_command("position startpos moves e2e4 e7e5 g1f3 b8c6 d2d4 e5d4 f3d4")
+            .unwrap();
+        assert_eq!(
+            engine.process_command("moves").unwrap(),
+            "1. e4 e5 2. Nf3 Nc6 3. d4 exd4 4. Nxd4"
+        );
+    }
+
+    #[test]
+    fn test_go_before_position_uses_start_position() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+
+        let response = engine.process_command("go depth 1").unwrap();
+        assert!(response.starts_with("info string no position set"));
+        let best = response.lines().last().unwrap().strip_prefix("bestmove ").unwrap();
+        let legal: Vec<String> = movegen::generate_legal_moves(&Board::startpos())
+            .iter()
+            .map(|mv| mv.to_uci())
+            .collect();
+        assert!(legal.iter().any(|mv| mv == best));
+    }
+
+    #[test]
+    fn test_analyze_position_defaults_to_start_position() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+
+        let info = engine.analyze_position("", 2).unwrap();
+        assert!(info.starts_with("info depth 2 "));
+        assert!(engine.analyze_position("not a fen", 2).is_err());
+    }
+
+    #[test]
+    fn test_analyze_batch_keeps_input_order() {
+        let positions: Vec<(String, i32)> = [
+            (board::START_FEN, 3),
+            ("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 2),
+            ("not a fen", 2),
+            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 2),
+            ("", 1),
+        ]
+        .iter()
+        .map(|&(fen, depth)| (fen.to_string(), depth))
+        .collect();
+        let mut engine = Engine::new();
+        assert!(engine.analyze_batch(&positions).iter().all(|r| r.is_err()));
+        engine.initialize().unwrap();
+        for threads in ["1", "3"] {
+            engine.process_command(&format!("setoption name Threads value {}", threads)).unwrap();
+            let results = engine.analyze_batch(&positions);
+            assert_eq!(results.len(), positions.len());
+            for ((fen, depth), result) in positions.iter().zip(&results) {
+                if fen == "not a fen" {
+                    assert!(result.is_err());
+                    continue;
+                }
+                let result = result.as_ref().unwrap();
+                assert_eq!(result.depth, *depth as u32, "{}", fen);
+                let fen = if fen.is_empty() { board::START_FEN } else { fen };
+                let board = Board::from_fen(fen).unwrap();
+                let legal = movegen::generate_legal_moves(&board);
+                assert!(legal.contains(&result.best_move.unwrap()), "{}", fen);
+            }
+            assert_eq!(results[1].as_ref().unwrap().best_move.unwrap().to_uci(), "a1a8");
+        }
+    }
+
+    #[test]
+    fn test_show_wdl_option() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+
+        let response = engine.process_command("uci").unwrap();
+        assert!(response.contains("option name UCI_ShowWDL type check default false"));
+
+        engine.process_command("position startpos").unwrap();
+        assert!(!engine.process_command("go depth 1").unwrap().contains(" wdl "));
+
+        engine
+            .process_command("setoption name UCI_ShowWDL value true")
+            .unwrap();
+        let response = engine.process_command("go depth 1").unwrap();
+        let info = response.lines().find(|l| l.starts_with("info depth")).unwrap();
+        let tokens: Vec<&str> = info.split_whitespace().collect();
+        let at = tokens.iter().position(|&t| t == "wdl").unwrap();
+        let sum: u32 = tokens[at + 1..at + 4].iter().map(|t| t.parse::<u32>().unwrap()).sum();
+        assert_eq!(sum, 1000);
+    }
+
+    #[test]
+    fn test_wdl_model_options_recalibrate_the_split() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        let response = engine.process_command("uci").unwrap();
+        assert!(response.contains("option name WDL Model A type spin default 220 min 1 max 5000"));
+        engine.process_command("setoption name UCI_ShowWDL value true").unwrap();
+        // A rook up: the same score every time.
+        engine.process_command("position fen 4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let wdl = |engine: &mut Engine| {
+            let response = engine.process_command("go depth 1").unwrap();
+            let info = response.lines().find(|l| l.starts_with("info depth")).unwrap();
+            let tokens: Vec<&str> = info.split_whitespace().collect();
+            let at = tokens.iter().position(|&t| t == "wdl").unwrap();
+            let score = tokens[tokens.iter().position(|&t| t == "cp").unwrap() + 1].to_string();
+            let numbers: Vec<u32> =
+                tokens[at + 1..at + 4].iter().map(|t| t.parse().unwrap()).collect();
+            (score, numbers)
+        };
+        let (score, default) = wdl(&mut engine);
+        // Needing a bigger edge for a likely win turns wins into draws.
+        engine.process_command("setoption name WDL Model A value 600").unwrap();
+        let (same_score, harder) = wdl(&mut engine);
+        assert_eq!(same_score, score);
+        assert!(harder[0] < default[0] && harder[1] > default[1], "{:?} {:?}", default, harder);
+        // A steeper curve makes the same edge count for more.
+        engine.process_command("setoption name WDL Model B value 20").unwrap();
+        let (_, steeper) = wdl(&mut engine);
+        assert!(steeper[0] > harder[0], "{:?} {:?}", harder, steeper);
+        assert!(engine.process_command("setoption name WDL Model B value 0").is_err());
+    }
+
+    #[test]
+    fn test_pv_length_caps_info_lines() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        engine.process_command("setoption name PV Length value 3").unwrap();
+        engine.process_command("position startpos").unwrap();
+        let response = engine.process_command("go depth 6").unwrap();
+        let info = response.lines().find(|l| l.starts_with("info depth 6 ")).unwrap();
+        let pv: Vec<&str> = info.split(" pv ").nth(1).unwrap().split(' ').collect();
+        assert_eq!(pv.len(), 3, "{}", info);
+        let mut board = Board::startpos();
+        for text in pv {
+            let mv = movegen::generate_legal_moves(&board)
+                .into_iter()
+                .find(|mv| mv.to_uci() == text)
+                .unwrap();
+            board.make_move(mv);
+        }
+        // The search itself keeps the whole line.
+        assert!(engine.pv_positions().len() > 3);
+    }
+
+    #[test]
+    fn test_limit_strength_sometimes_plays_a_lesser_move() {
+        let bestmoves = |limit: bool| {
+            let mut engine = Engine::new();
+            engine.initialize().unwrap();
+            engine
+                .process_command(&format!("setoption name UCI_LimitStrength value {}", limit))
+                .unwrap();
+            engine.process_command("setoption name UCI_Elo value 800").unwrap();
+            engine.process_command("setoption name Seed value 42").unwrap();
+            engine.process_command("setoption name OwnBook value false").unwrap();
+            engine.process_command("ucinewgame").unwrap();
+            let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3";
+            engine.process_command(&format!("position fen {}", fen)).unwrap();
+            (0..12)
+                .map(|_| {
+                    let response = engine.process_command("go depth 1").unwrap();
+                    assert!(!response.contains(" multipv "), "{}", response);
+                    let played = response.lines().last().unwrap().replace("bestmove ", "");
+                    let board = Board::from_fen(fen).unwrap();
+                    let legal = movegen::generate_legal_moves(&board);
+                    let mv = legal.into_iter().find(|mv| mv.to_uci() == played).unwrap();
+                    // Nothing that simply loses material.
+                    let mut after = board.clone();
+                    after.make_move(mv);
+                    assert!(eval::evaluate(&after) < 100, "{}", played);
+                    played
+                })
+                .collect::<Vec<String>>()
+        };
+        let weak = bestmoves(true);
+        assert!(weak.iter().any(|mv| mv != &weak[0]), "{:?}", weak);
+        assert_eq!(bestmoves(true), weak);
+        let full = bestmoves(false);
+        assert!(full.iter().all(|mv| mv == &full[0]), "{:?}", full);
+    }
+
+    #[test]
+    fn test_skill_level_blends_best_and_lesser_moves() {
+        let bestmoves = |level: u32| {
+            let mut engine = Engine::new();
+            engine.initialize().unwrap();
+            engine.process_command(&format!("setoption name Skill Level value {}", level)).unwrap();
+            engine.process_command("setoption name Seed value 7").unwrap();
+            engine.process_command("setoption name OwnBook value false").unwrap();
+            engine.process_command("ucinewgame").unwrap();
+            // Only exd5 wins the pawn.
+            engine.process_command("position fen 4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1").unwrap();
+            (0..12)
+                .map(|_| {
+                    let response = engine.process_command("go depth 3").unwrap();
+                    response.lines().last().unwrap().replace("bestmove ", "")
+                })
+                .collect::<Vec<String>>()
+        };
+        assert!(bestmoves(20).iter().all(|mv| mv == "e4d5"));
+        let low = bestmoves(0);
+        assert!(low.iter().any(|mv| mv != "e4d5"), "{:?}", low);
+        assert_eq!(bestmoves(0), low);
+    }
+
+    #[test]
+    fn test_score_white_pov_option() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        // Black to move and a queen up.
+        engine.process_command("position fen 4k3/8/8/3q4/8/8/8/4K3 b - - 0 1").unwrap();
+        let score = |engine: &mut Engine| {
+            let response = engine.process_command("go depth 2").unwrap();
+            let info = response.lines().find(|l| l.starts_with("info depth 2")).unwrap();
+            let tokens: Vec<&str> = info.split_whitespace().collect();
+            let at = tokens.iter().position(|&t| t == "cp").unwrap();
+            tokens[at + 1].parse::<i32>().unwrap()
+        };
+        assert!(score(&mut engine) > 0);
+        engine.process_command("setoption name UCI_ScoreWhitePov value true").unwrap();
+        assert!(score(&mut engine) < 0);
+        // Black being mated reads as White mating.
+        engine.process_command("position fen 1k6/8/1K6/8/8/8/8/7R b - - 0 1").unwrap();
+        let response = engine.process_command("go depth 8").unwrap();
+        assert!(response.contains(" score mate 2 "), "{}", response);
+    }
+
+    #[test]
+    fn test_analyse_mode_finds_what_reductions_miss() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        let response = engine.process_command("uci").unwrap();
+        assert!(response.contains("option name UCI_AnalyseMode type check default false"));
+
+        // The mate in 4 needs quiet rook and king moves that get reduced. Not
+        // the bare-king mate of 8/R7/8/8/1K6/8/1k6/8: the king-to-the-edge
+        // evaluation finds that one even with reductions.
+        engine.process_command("position fen 8/7p/7R/8/1K6/8/k7/8 w - - 0 1").unwrap();
+        let pruned = engine.process_command("go depth 7").unwrap();
+        assert!(!pruned.contains("score mate"), "{}", pruned);
+        engine.process_command("setoption name UCI_AnalyseMode value true").unwrap();
+        // Start over so the first search's hash entries don't carry across.
+        engine.process_command("setoption name Clear Hash").unwrap();
+        let full = engine.process_command("go depth 7").unwrap();
+        assert!(full.contains("info depth 7 score mate 4 "), "{}", full);
+    }
+
+    #[test]
+    fn test_white_pov_eval_flips_with_the_mirror() {
+        let engine = Engine::new();
+        // The same position with colours swapped and the board turned around.
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+        let mirror = "rnbqk2r/pppp1ppp/5n2/2b1p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R b KQkq - 4 4";
+        let white = engine.evaluate_white_pov(fen).unwrap();
+        assert_eq!(engine.evaluate_white_pov(mirror).unwrap(), -white);
+        assert_eq!(white, eval::evaluate(&Board::from_fen(fen).unwrap()));
+
+        // Black to move and a queen up: good for Black, so negative.
+        let black_up = "4k3/8/8/3q4/8/8/8/4K3 b - - 0 1";
+        assert!(engine.evaluate_white_pov(black_up).unwrap() < -500);
+        assert!(engine.evaluate_white_pov("").is_ok());
+    }
+
+    #[test]
+    fn test_wait_ready_installs_the_resized_table() {
+        let mut engine = Engine::new();
+        assert!(!engine.ready());
+        engine.process_command("setoption name Hash value 256").unwrap();
+        engine.wait_ready().unwrap();
+        assert!(engine.ready());
+        assert_eq!(engine.tt.len(), 256 * 1024 * 1024 / 16);
+        // A later command picks up a resize without an explicit wait.
+        engine.process_command("setoption name Hash value 1").unwrap();
+        assert_eq!(engine.process_command("isready").unwrap(), "readyok");
+        assert!(engine.pending_tt.is_none());
+        assert_eq!(engine.tt.len(), 1024 * 1024 / 16);
+    }
+
+    #[test]
+    fn test_apply_moves() {
+        let scholars_mate = ["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6", "h5f7"];
+        assert_eq!(
+            apply_moves(board::START_FEN, &scholars_mate).unwrap(),
+            "r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4"
+        );
+        assert_eq!(apply_moves(board::START_FEN, &[]).unwrap(), board::START_FEN);
+        assert!(matches!(
+            apply_moves(board::START_FEN, &["e2e4", "e7e5", "e4e5"]),
+            Err(EngineError::IllegalMoveAt { index: 2, text }) if text == "e4e5"
+        ));
+        assert!(matches!(apply_moves("bad fen", &[]), Err(EngineError::InvalidFen(_))));
+    }
+
+    #[test]
+    fn test_go_depth_and_mate_together() {
+        let mut engine = Engine::new();
+        engine.process_command("position fen 1k6/8/3K4/8/8/8/8/7R w - - 0 1").unwrap();
+        let response = engine.process_command("go depth 20 mate 5").unwrap();
+        let last_info = response.lines().rev().find(|l| l.starts_with("info depth")).unwrap();
+        assert!(last_info.contains(" score mate 3 "), "{}", last_info);
+        assert!(!response.contains("info depth 20 "));
+        assert!(response.lines().last().unwrap().starts_with("bestmove "));
+        // Without a mate to find, the depth limit still applies.
+        engine.process_command("position startpos").unwrap();
+        let response = engine.process_command("go depth 2 mate 1").unwrap();
+        assert!(response.contains("info depth 2 "));
+        // Nor does a mate search alone go on forever.
+        let start = Instant::now();
+        let response = engine.process_command("go mate 1").unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        let last_info = response.lines().rev().find(|l| l.starts_with("info depth")).unwrap();
+        assert!(last_info.starts_with("info depth 2 "), "{}", last_info);
+        assert!(last_info.contains(" score cp "), "{}", last_info);
+        assert!(response.lines().last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn test_tt_command_shows_the_root_entry() {
+        let mut engine = Engine::new();
+        engine.process_command("position startpos moves e2e4").unwrap();
+        assert_eq!(engine.process_command("tt").unwrap(), "tt no entry");
+
+        let response = engine.process_command("go depth 4").unwrap();
+        let best = response.lines().last().unwrap().strip_prefix("bestmove ").unwrap();
+        let entry = engine.process_command("tt").unwrap();
+        assert!(entry.starts_with("tt depth 4 score cp "), "{}", entry);
+        assert!(entry.contains(" bound exact "), "{}", entry);
+        assert!(entry.ends_with(&format!(" move {}", best)), "{}", entry);
+    }
+
+    #[test]
+    fn test_moves_from_square() {
+        let mut engine = Engine::new();
+        assert_eq!(engine.moves_from_square("", "b1").unwrap(), ["b1a3", "b1c3"]);
+        assert!(engine.moves_from_square("", "e4").unwrap().is_empty());
+        assert!(engine.moves_from_square("", "e8").unwrap().is_empty());
+        let fen = "4k3/8/8/8/8/8/8/R3K3 w Q - 0 1";
+        assert_eq!(engine.moves_from_square(fen, "e1").unwrap().len(), 6);
+        assert!(matches!(
+            engine.moves_from_square("", "i9"),
+            Err(EngineError::InvalidSquare(_))
+        ));
+    }
+
+    #[test]
+    fn test_legal_moves_are_cached() {
+        let mut engine = Engine::new();
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let first = engine.legal_moves(fen).unwrap();
+        let second = engine.legal_moves(fen).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first.len(), 26);
+        assert_eq!((engine.move_cache().hits(), engine.move_cache().misses()), (1, 1));
+
+        engine.process_command("ucinewgame").unwrap();
+        assert!(engine.move_cache().is_empty());
+        engine.process_command("setoption name Move Cache value 0").unwrap();
+        engine.legal_moves("").unwrap();
+        assert!(engine.move_cache().is_empty());
+    }
+
+    #[test]
+    fn test_perftsuite_command() {
+        let mut engine = Engine::new();
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/data/perftsuite.epd");
+        let response = engine.process_command(&format!("perftsuite {}", path)).unwrap();
+        assert!(response.lines().take(6).all(|line| line.starts_with("PASS ")));
+        assert!(response.ends_with("perftsuite: 6/6 passed"));
+        assert!(matches!(
+            engine.process_command("perftsuite /no/such/file.epd"),
+            Err(EngineError::Epd(_))
+        ));
+    }
+
+    #[test]
+    fn test_selfplay_plays_out_a_won_position() {
+        let mut engine = Engine::new();
+        let fen = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+        engine.process_command(&format!("position fen {}", fen)).unwrap();
+        let pgn = engine.process_command("selfplay 20").unwrap();
+        assert!(pgn.contains("[Result \"1-0\"]"), "{}", pgn);
+        assert!(pgn.contains(&format!("[FEN \"{}\"]", fen)), "{}", pgn);
+        assert!(pgn.ends_with("\n1. Ra8# 1-0"), "{}", pgn);
+        // The game was played on a copy.
+        assert_eq!(engine.fen(), fen);
+
+        engine.process_command("position fen 8/8/8/8/8/2k5/8/K1N5 w - - 0 1").unwrap();
+        assert!(engine.process_command("selfplay 20").unwrap().ends_with("\n\n1/2-1/2"));
+        assert!(engine.process_command("selfplay").is_err());
+    }
+
+    #[test]
+    fn test_tacticsuite_command() {
+        let name = format!("brainfish-tactics-{}.epd", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(
+            &path,
+            "6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; id \"back rank\";\n\
+             6k1/5ppp/8/8/8/8/8/R5K1 w - - am Ra8#;\n",
+        )
+        .unwrap();
+        let mut engine = Engine::new();
+        let command = format!("tacticsuite {} depth 2", path.display());
+        let response = engine.process_command(&command).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = response.lines().collect();
+        assert_eq!(lines[0], "SOLVED back rank played Ra8#");
+        assert!(lines[1].starts_with("FAILED 6k1/5ppp/8/8/8/8/8/R5K1 w - - played Ra8#"));
+        assert_eq!(lines[2], "tacticsuite: 1/2 solved");
+        assert!(engine.process_command("tacticsuite x.epd depth deep").is_err());
+    }
+
+    #[test]
+    fn test_go_infinite_runs_until_stop() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+
+        engine.process_command("position startpos").unwrap();
+        assert_eq!(engine.process_command("go infinite").unwrap(), "");
+        assert_eq!(engine.process_command("isready").unwrap(), "readyok");
+        std::thread::sleep(Duration::from_millis(50));
+        let response = engine.process_command("stop").unwrap();
+        assert!(response.lines().last().unwrap().starts_with("bestmove "));
+        assert_eq!(engine.process_command("stop").unwrap(), "");
+    }
+
+    #[test]
+    fn test_go_ponder_keeps_the_clock_for_ponderhit() {
+        let board = Board::from_fen(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+        )
+        .unwrap();
+        let tokens = "ponder wtime 60000 btime 30000 winc 1000 binc 500 movestogo 20";
+        let params = Engine::parse_go(tokens.split_whitespace(), &board).unwrap();
+        let clock = time::Clock {
+            remaining: Duration::from_secs(30),
+            increment: Duration::from_millis(500),
+            moves_to_go: Some(20),
+            move_number: 1,
+        };
+        assert!(params.ponder);
+        assert_eq!(params.clock, Some(clock));
+        assert_eq!(params.limits.movetime, None);
+        let options = Options::default();
+        let overhead = Duration::from_millis(options.move_overhead_ms);
+        assert_eq!(
+            params.timed_limits(&options).movetime,
+            Some(time::allocate(&clock, overhead, options.slow_mover))
+        );
+    }
+
+    #[test]
+    fn test_ponderhit_times_the_search_from_then() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        engine.process_command("position startpos moves e2e4").unwrap();
+        assert_eq!(engine.process_command("go ponder wtime 2000 btime 2000").unwrap(), "");
+        std::thread::sleep(Duration::from_millis(100));
+        let board = engine.game.board().clone();
+        let params = Engine::parse_go("wtime 2000 btime 2000".split_whitespace(), &board).unwrap();
+        let budget = params.timed_limits(&engine.options).movetime.unwrap();
+        let start = Instant::now();
+        let response = engine.process_command("ponderhit").unwrap();
+        assert!(start.elapsed() >= budget, "{:?}", start.elapsed());
+        assert!(response.lines().last().unwrap().starts_with("bestmove "), "{}", response);
+        assert_eq!(engine.process_command("ponderhit").unwrap(), "");
+
+        // A miss stops the ponder search, which reports its move.
+        engine.process_command("go ponder wtime 2000 btime 2000").unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        let response = engine.process_command("stop").unwrap();
+        assert!(response.lines().last().unwrap().starts_with("bestmove "), "{}", response);
+    }
+
+    #[test]
+    fn test_go_ponder_movetime_starts_at_ponderhit() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        engine.process_command("position startpos moves e2e4").unwrap();
+        assert_eq!(engine.process_command("go ponder movetime 300").unwrap(), "");
+        std::thread::sleep(Duration::from_millis(500));
+        let start = Instant::now();
+        let response = engine.process_command("ponderhit").unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(300), "{:?}", start.elapsed());
+        assert!(response.lines().last().unwrap().starts_with("bestmove "), "{}", response);
+    }
+
+    #[test]
+    fn test_stopped_multipv_search_plays_the_last_complete_first_line() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        engine.process_command("setoption name MultiPV value 3").unwrap();
+        engine.process_command("position startpos").unwrap();
+        engine.process_command("go infinite").unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        let response = engine.process_command("stop").unwrap();
+
+        let bestmoves: Vec<&str> = response.lines().filter(|l| l.starts_with("bestmove")).collect();
+        assert_eq!(bestmoves.len(), 1, "{}", response);
+        let infos: Vec<&str> = response.lines().filter(|l| l.contains(" multipv ")).collect();
+        // Every reported iteration has all three lines, the best first.
+        assert_eq!(infos.len() % 3, 0, "{}", response);
+        let last = &infos[infos.len() - 3..];
+        let first_move = |line: &str| {
+            let pv = line.split(" pv ").nth(1)?;
+            pv.split(' ').next().map(str::to_string)
+        };
+        let depth = |line: &str| line.split_whitespace().nth(2).map(str::to_string);
+        for (i, line) in last.iter().enumerate() {
+            assert!(line.contains(&format!(" multipv {} ", i + 1)), "{}", line);
+            assert_eq!(depth(line), depth(last[0]));
+        }
+        assert_ne!(first_move(last[0]), first_move(last[1]));
+        assert_ne!(first_move(last[1]), first_move(last[2]));
+        let played = bestmoves[0].strip_prefix("bestmove ").map(str::to_string);
+        assert_eq!(played, first_move(last[0]));
+        let board = Board::startpos();
+        assert!(movegen::generate_legal_moves(&board)
+            .iter()
+            .any(|mv| Some(mv.to_uci()) == played));
+    }
+
+    #[test]
+    fn test_spawned_engine_answers_over_channels() {
+        let (commands, responses) = Engine::spawn();
+        let timeout = Duration::from_secs(10);
+        commands.send(String::from("uci")).unwrap();
+        let mut lines = Vec::new();
+        while lines.last().map(String::as_str) != Some("uciok") {
+            lines.push(responses.recv_timeout(timeout).unwrap());
+        }
+        assert!(lines[0].starts_with("id name "));
+
+        commands.send(String::from("position startpos")).unwrap();
+        commands.send(String::from("go depth 2")).unwrap();
+        let bestmove = std::iter::from_fn(|| responses.recv_timeout(timeout).ok())
+            .find(|line| line.starts_with("bestmove "));
+        assert!(bestmove.is_some());
+        commands.send(String::from("position fen not-a-fen")).unwrap();
+        let error = responses.recv_timeout(timeout).unwrap();
+        assert!(error.starts_with("error processing command"), "{}", error);
+        drop(commands);
+        assert!(responses.recv_timeout(timeout).is_err());
+    }
+
+    #[test]
+    fn test_go_without_legal_moves_answers_null_move() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        // Fool's mate, then a bare-king stalemate.
+        let mated = "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3";
+        let stalemated = "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1";
+        for (fen, score) in [(mated, "mate 0"), (stalemated, "cp 0")] {
+            engine.process_command(&format!("position fen {}", fen)).unwrap();
+            let start = Instant::now();
+            let response = engine.process_command("go depth 5").unwrap();
+            assert!(start.elapsed() < Duration::from_secs(1));
+            assert!(response.contains(score), "{}", response);
+            assert_eq!(response.lines().last(), Some("bestmove 0000"));
+        }
+        // No waiting for `stop` either.
+        let response = engine.process_command("go infinite").unwrap();
+        assert_eq!(response.lines().last(), Some("bestmove 0000"));
+        assert_eq!(engine.process_command("stop").unwrap(), "");
+    }
+
+    #[test]
+    fn test_moves_ending_in_mate_leave_nothing_to_search() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        // Fool's mate, then the quickest stalemate known.
+        let mate = "position startpos moves f2f3 e7e5 g2g4 d8h4";
+        let stalemate = "position startpos moves e2e3 a7a5 d1h5 a8a6 h5a5 h7h5 h2h4 a6h6 \
+                         a5c7 f7f6 c7d7 e8f7 d7b7 d8d3 b7b8 d3h7 b8c8 f7g6 c8e6";
+        for command in [mate, stalemate] {
+            engine.process_command(command).unwrap();
+            assert!(movegen::generate_legal_moves(engine.game.board()).is_empty());
+            let response = engine.process_command("go depth 5").unwrap();
+            assert_eq!(response.lines().last(), Some("bestmove 0000"), "{}", command);
+        }
+        assert_eq!(engine.game_status(), GameStatus::Stalemate);
+        // Nothing can follow the mate.
+        assert!(engine.process_command(&format!("{} a2a3", mate)).is_err());
+    }
+
+    #[test]
+    fn test_go_on_a_corrupt_board_reports_an_error() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        engine.process_command("position startpos").unwrap();
+        let e8 = types::Square::from_algebraic("e8").unwrap();
+        engine.game = Game::new(Board::startpos().corrupted(e8));
+        let response = engine.process_command("go depth 3").unwrap();
+        assert!(response.contains("info string error: "), "{}", response);
+        assert_eq!(response.lines().last(), Some("bestmove 0000"));
+    }
+
+    #[test]
+    fn test_go_while_searching_restarts_the_search() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        let output = Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+        let sink = Arc::clone(&output);
+        engine.set_output(move |line| sink.lock().unwrap().push(line.to_string()));
+
+        engine.process_command("position startpos").unwrap();
+        assert_eq!(engine.process_command("go movetime 10000").unwrap(), "");
+        assert_eq!(engine.process_command("go infinite").unwrap(), "");
+        assert_eq!(engine.process_command("go infinite").unwrap(), "");
+        std::thread::sleep(Duration::from_millis(50));
+        let response = engine.process_command("stop").unwrap();
+
+        let output = output.lock().unwrap();
+        let bestmoves: Vec<&str> = output
+            .iter()
+            .map(String::as_str)
+            .chain(response.lines())
+            .filter(|line| line.starts_with("bestmove"))
+            .collect();
+        assert_eq!(bestmoves.len(), 1, "{:?}", bestmoves);
+        assert!(response.ends_with(bestmoves[0]));
+        assert!(engine.background.is_none());
+    }
+
+    #[test]
+    fn test_analyze_stream_yields_deeper_snapshots_until_stop() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+
+        let snapshots = engine.analyze_stream().unwrap();
+        let first = snapshots.recv().unwrap();
+        let second = snapshots.recv().unwrap();
+        assert!(second.depth > first.depth);
+        assert!(second.best_move.is_some());
+
+        engine.stop();
+        // Whatever was sent before the stop drains, then the channel is closed.
+        let rest: Vec<SearchResult> = snapshots.iter().collect();
+        assert!(rest.iter().all(|r| r.depth > second.depth));
+        assert!(snapshots.recv().is_err());
+    }
+
+    #[test]
+    fn test_isready_answers_during_search() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        let output = Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+        let sink = Arc::clone(&output);
+        engine.set_output(move |line| sink.lock().unwrap().push(line.to_string()));
+
+        // The resize is installed before the next command runs, so readyok
+        // and the search both follow it.
+        engine.process_command("setoption name Hash value 2").unwrap();
+        engine.process_command("position startpos").unwrap();
+        assert_eq!(engine.tt.len(), 2 * 1024 * 1024 / 16);
+
+        assert_eq!(engine.process_command("go movetime 10000").unwrap(), "");
+        let start = std::time::Instant::now();
+        assert_eq!(engine.process_command("isready").unwrap(), "readyok");
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        assert_eq!(engine.process_command("stop").unwrap(), "");
+        assert!(start.elapsed() < Duration::from_secs(5));
+        let last = output.lock().unwrap().last().cloned().unwrap();
+        assert!(last.starts_with("bestmove "));
+    }
+
+    #[test]
+    fn test_same_seed_reproduces_book_choices() {
+        let name = format!("brainfish-seed-book-{}.json", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        let moves = r#""e2e4": {"frequency": 30}, "d2d4": {"frequency": 10},
+            "c2c4": {"frequency": 55}, "g1f3": {"frequency": 5}"#;
+        let book = format!(r#"{{"{}": {{"moves": {{{}}}}}}}"#, board::START_FEN, moves);
+        std::fs::write(&path, book).unwrap();
+        let engine_with_seed = |seed: u32| {
+            let mut engine = Engine::new();
+            engine.initialize().unwrap();
+            engine.process_command(&format!("setoption name Seed value {}", seed)).unwrap();
+            engine
+                .process_command(&format!("setoption name BookFile value {}", path.display()))
+                .unwrap();
+            engine.process_command("position startpos").unwrap();
+            engine
+        };
+        let picks = |seed: u32| -> Vec<String> {
+            let mut engine = engine_with_seed(seed);
+            (0..16)
+                .map(|_| {
+                    let response = engine.process_command("go depth 2").unwrap();
+                    assert!(response.starts_with("info string book move"), "{}", response);
+                    response.lines().last().unwrap().to_string()
+                })
+                .collect()
+        };
+        let first = picks(1234);
+        let other = picks(99);
+        let again = picks(1234);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+        // Several book moves get played, not just the most frequent.
+        assert!(first.iter().any(|pick| pick != &first[0]), "{:?}", first);
+    }
+
+    #[test]
+    fn test_zobrist_keys_do_not_depend_on_the_seed() {
+        // Every board shares one set of keys, made from the default seed.
+        let hash = |seed: u32| {
+            let mut engine = Engine::new();
+            engine.process_command(&format!("setoption name Seed value {}", seed)).unwrap();
+            engine.process_command("position startpos moves e2e4 e7e5").unwrap();
+            engine.game.board().hash()
+        };
+        assert_eq!(hash(1234), hash(99));
+    }
+
+    #[test]
+    fn test_long_search_reports_currmove() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+
+        engine.process_command("position startpos").unwrap();
+        let response = engine.process_command("go movetime 1500").unwrap();
+        let legal: Vec<String> = movegen::generate_legal_moves(&Board::startpos())
+            .iter()
+            .map(|mv| mv.to_uci())
+            .collect();
+        let currmoves: Vec<&str> = response
+            .lines()
+            .filter(|line| line.contains(" currmove "))
+            .collect();
+        assert!(!currmoves.is_empty());
+        for line in currmoves {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let at = tokens.iter().position(|&t| t == "currmove").unwrap();
+            assert!(legal.iter().any(|mv| mv == tokens[at + 1]));
+        }
+    }
+
+    #[test]
+    fn test_currline_follows_legal_moves_from_the_root() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        let response = engine.process_command("uci").unwrap();
+        assert!(response.contains("option name UCI_ShowCurrLine type check default false"));
+        engine.process_command("position startpos moves e2e4").unwrap();
+        let response = engine.process_command("go depth 5").unwrap();
+        assert!(!response.contains("currline"));
+
+        engine.process_command("setoption name UCI_ShowCurrLine value true").unwrap();
+        let response = engine.process_command("go depth 5").unwrap();
+        let lines: Vec<&str> = response
+            .lines()
+            .filter_map(|line| line.strip_prefix("info currline "))
+            .collect();
+        assert!(!lines.is_empty());
+        for line in lines {
+            let mut board = engine.game.board().clone();
+            for text in line.split_whitespace() {
+                let mv = movegen::generate_legal_moves(&board)
+                    .into_iter()
+                    .find(|mv| mv.to_uci() == text);
+                let Some(mv) = mv else {
+                    panic!("{} is not legal in {}", text, line);
+                };
+                board.make_move(mv);
+            }
+        }
+    }
+
+    #[test]
+    fn test_warmup_lasts_one_search_and_keeps_the_tactic() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        let response = engine.process_command("uci").unwrap();
+        assert!(response.contains("option name LMR Warmup type spin default 50 min 0 max 100"));
+        engine.process_command("ucinewgame").unwrap();
+        assert!(engine.warmup);
+        // Scholar's mate.
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 2 3";
+        engine.process_command(&format!("position fen {}", fen)).unwrap();
+        let cold = engine.process_command("go depth 6").unwrap();
+        assert!(!engine.warmup);
+        let warm = engine.process_command("go depth 6").unwrap();
+        assert_eq!(cold.lines().last(), Some("bestmove f3f7"));
+        assert_eq!(warm.lines().last(), cold.lines().last());
+    }
+
+    #[test]
+    fn test_deterministic_go_repeats_without_ucinewgame() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        engine.process_command("setoption name Deterministic value true").unwrap();
+        engine.process_command("position startpos moves e2e4 e7e5 g1f3").unwrap();
+        let mut nodes = || {
+            let response = engine.process_command("go depth 5").unwrap();
+            let info = response.lines().rev().find(|l| l.contains(" nodes ")).unwrap();
+            info.split(" nodes ").nth(1).unwrap().split(' ').next().unwrap().to_string()
+        };
+        let first = nodes();
+        assert_eq!(nodes(), first);
+    }
+
+    #[test]
+    fn test_warmup_applies_to_searches_reporting_to_a_sink() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        let output = Arc::new(Mutex::new(Vec::<String>::new()));
+        let sink = Arc::clone(&output);
+        engine.set_output(move |line| sink.lock().unwrap().push(line.to_string()));
+        engine.process_command("setoption name Deterministic value true").unwrap();
+        let mut nodes = |warmup: u32| {
+            engine
+                .process_command(&format!("setoption name LMR Warmup value {}", warmup))
+                .unwrap();
+            output.lock().unwrap().clear();
+            engine.process_command("ucinewgame").unwrap();
+            engine.process_command("position startpos moves e2e4 e7e5 g1f3").unwrap();
+            assert_eq!(engine.process_command("go depth 6").unwrap(), "");
+            engine.join_search();
+            assert!(!engine.warmup);
+            let lines = output.lock().unwrap();
+            assert!(lines.last().unwrap().starts_with("bestmove "), "{:?}", lines);
+            let info = lines.iter().rev().find(|l| l.contains(" nodes ")).unwrap();
+            let count = info.split(" nodes ").nth(1).unwrap().split(' ').next().unwrap();
+            count.parse::<u64>().unwrap()
+        };
+        // At 100 percent the first search reduces as usual, at 50 it reduces less.
+        let usual = nodes(100);
+        assert!(nodes(50) > usual);
+        assert_eq!(nodes(100), usual);
+    }
+
+    #[test]
+    fn test_clear_hash_keeps_position() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        let response = engine.process_command("uci").unwrap();
+        assert!(response.contains("option name Clear Hash type button"));
+
+        engine.process_command("setoption name Hash value 1").unwrap();
+        engine.process_command("position startpos moves e2e4 e7e5").unwrap();
+        let fen = engine.fen();
+        for clear in ["setoption name Clear Hash", "cleartt"] {
+            engine.process_command("go depth 4").unwrap();
+            assert!(engine.tt.hashfull() > 0);
+            engine.process_command(clear).unwrap();
+            assert_eq!(engine.tt.hashfull(), 0);
+            assert_eq!(engine.fen(), fen);
+        }
+    }
+
+    #[test]
+    fn test_log_file_records_each_search() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        let name = format!("brainfish-engine-{}.log", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+
+        engine
+            .process_command(&format!("setoption name LogFile value {}", path.display()))
+            .unwrap();
+        // A log that cannot be opened leaves the current one in place.
+        assert!(engine.process_command("setoption name LogFile value /no/such/dir/x.log").is_err());
+        assert_eq!(engine.options.log_file, path.display().to_string());
+        engine.process_command("position startpos moves e2e4").unwrap();
+        engine.process_command("go depth 3").unwrap();
+        engine.process_command("setoption name LogFile value <empty>").unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let record: serde_json::Value = serde_json::from_str(text.trim()).unwrap();
+        assert_eq!(record["depth"], 3);
+        assert_eq!(record["fen"], engine.fen());
+        assert!(record["bestmove"].as_str().unwrap().len() >= 4);
+        let hit_rate = record["tt_hit_rate"].as_f64().unwrap();
+        assert!((0.0..=1.0).contains(&hit_rate));
+    }
+
+    #[test]
+    fn test_options_command_shows_current_values() {
+        let mut engine = Engine::new();
+        engine.process_command("setoption name Hash value 64").unwrap();
+        let response = engine.process_command("options").unwrap();
+        let lines: Vec<&str> = response.lines().collect();
+        assert_eq!(lines[0], "Hash 64");
+        assert!(lines.contains(&"Move Overhead 10"));
+        assert!(lines.contains(&"LogFile <empty>"));
+    }
+
+    #[test]
+    fn test_books_are_probed_in_order() {
+        let dir = std::env::temp_dir();
+        let write_book = |name: &str, fen: &str, mv: &str| {
+            let path = dir.join(format!("brainfish-{}-{}.json", name, std::process::id()));
+            let json = format!(r#"{{"{}": {{"moves": {{"{}": {{"frequency": 1}}}}}}}}"#, fen, mv);
+            std::fs::write(&path, json).unwrap();
+            path
+        };
+        let after_e4 = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        let first = write_book("main", after_e4, "c7c5");
+        let second = write_book("extra", board::START_FEN, "b2b3");
+        let third = write_book("late", board::START_FEN, "g2g3");
+        let paths = std::env::join_paths([&first, &second, &third]).unwrap();
+
+        let mut engine = Engine::new();
+        engine
+            .process_command(&format!("setoption name BookFile value {}", paths.to_str().unwrap()))
+            .unwrap();
+        for path in [first, second, third] {
+            std::fs::remove_file(path).unwrap();
+        }
+        // Only the second and third books know the start position; the second wins.
+        engine.process_command("position startpos").unwrap();
+        let response = engine.process_command("go depth 1").unwrap();
+        assert_eq!(response.lines().last(), Some("bestmove b2b3"));
+        engine.process_command("position startpos moves e2e4").unwrap();
+        let response = engine.process_command("go depth 1").unwrap();
+        assert_eq!(response.lines().last(), Some("bestmove c7c5"));
+    }
+
+    #[test]
+    fn test_capture_moves_ffi_lists_only_captures() {
+        let ptr = engine_new();
+        let capture_moves = |fen: &str| unsafe {
+            let fen = CString::new(fen).unwrap();
+            let raw = engine_capture_moves(ptr, fen.as_ptr());
+            let text = CStr::from_ptr(raw).to_str().unwrap().to_string();
+            engine_free_string(raw);
+            text
+        };
+        // exd6 en passant and exf6; a7a8 promotes without capturing.
+        let text = capture_moves("4k3/P7/5n2/3pP3/8/8/8/4K3 w - d6 0 1");
+        let mut moves: Vec<&str> = text.split(' ').collect();
+        moves.sort();
+        assert_eq!(moves, ["e5d6", "e5f6"]);
+        // The start position has none.
+        assert_eq!(capture_moves(""), "");
+        assert!(capture_moves("not a fen").starts_with("error: "));
+        // Stray control characters are dropped; invalid UTF-8 makes a bad FEN.
+        assert_eq!(capture_moves("4k3/8/8/8/8/8/3p4/4K3\u{1} w - - 0 1"), "e1d2");
+        unsafe {
+            let bytes = CString::new(b"4k3/8/8/8/8/8/8/4K3\xff w - - 0 1".to_vec()).unwrap();
+            let raw = engine_capture_moves(ptr, bytes.as_ptr());
+            assert!(CStr::from_ptr(raw).to_str().unwrap().starts_with("error: "));
+            engine_free_string(raw);
+            let empty = CString::new("").unwrap();
+            let raw = engine_capture_moves(std::ptr::null(), empty.as_ptr());
+            assert_eq!(CStr::from_ptr(raw).to_str().unwrap(), "null engine pointer");
+            engine_free_string(raw);
+            engine_free(ptr);
+        }
+    }
+
+    #[test]
+    fn test_in_book_follows_the_position() {
+        let name = format!("brainfish-in-book-{}.json", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        let book = format!(
+            r#"{{"{}": {{"moves": {{"e2e4": {{"frequency": 10}}}}}}}}"#,
+            board::START_FEN
+        );
+        std::fs::write(&path, book).unwrap();
+        let ptr = engine_new();
+        let engine = unsafe { &mut *ptr };
+        assert!(!engine.in_book());
+        engine
+            .process_command(&format!("setoption name BookFile value {}", path.display()))
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(engine.in_book());
+        assert!(unsafe { engine_in_book(ptr) });
+        engine.process_command("position startpos moves a2a4").unwrap();
+        assert!(!engine.in_book());
+        unsafe {
+            assert!(!engine_in_book(ptr));
+            assert!(!engine_in_book(std::ptr::null()));
+            engine_free(ptr);
+        }
+    }
+
+    #[test]
+    fn test_drop_joins_infinite_search() {
+        let lines = Arc::new(Mutex::new(Vec::<String>::new()));
+        let sink_lines = Arc::clone(&lines);
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        engine.set_output(move |line| sink_lines.lock().unwrap().push(line.to_string()));
+        engine.process_command("position startpos").unwrap();
+        engine.process_command("go infinite").unwrap();
+        drop(engine);
+        // The search thread held the sink; once joined only this handle is left.
+        assert_eq!(Arc::strong_count(&lines), 1);
+        let count = lines.lock().unwrap().len();
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(lines.lock().unwrap().len(), count);
+    }
+
+    #[test]
+    fn test_book_is_skipped_when_disabled_or_analysing() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        let name = format!("brainfish-book-{}.json", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        let book = format!(
+            r#"{{"{}": {{"moves": {{"a2a3": {{"frequency": 1}}}}}}}}"#,
+            board::START_FEN
+        );
+        std::fs::write(&path, book).unwrap();
+        engine
+            .process_command(&format!("setoption name BookFile value {}", path.display()))
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        engine.process_command("position startpos").unwrap();
+
+        let response = engine.process_command("go depth 2").unwrap();
+        assert_eq!(response, "info string book move\nbestmove a2a3");
+        // Book moves wait out the minimum thinking time too.
+        engine.process_command("setoption name Minimum Thinking Time value 300").unwrap();
+        let start = Instant::now();
+        let response = engine.process_command("go wtime 60000 btime 60000").unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert_eq!(response, "info string book move\nbestmove a2a3");
+        engine.process_command("setoption name Minimum Thinking Time value 0").unwrap();
+        for setting in ["OwnBook value false", "UCI_AnalyseMode value true"] {
+            engine.process_command(&format!("setoption name {}", setting)).unwrap();
+            let response = engine.process_command("go depth 2").unwrap();
+            assert!(response.contains("info depth 2"), "{}", response);
+            assert!(!response.contains("book move"));
+            engine.process_command("setoption name OwnBook value true").unwrap();
+        }
+        assert!(matches!(
+            engine.process_command("setoption name BookFile value /no/such/book.json"),
+            Err(EngineError::InvalidOptionValue { .. })
+        ));
+        // The book that was loaded stays in use, as BookFile still names it.
+        assert_eq!(engine.options.book_file, path.display().to_string());
+        engine.process_command("setoption name UCI_AnalyseMode value false").unwrap();
+        let response = engine.process_command("go depth 2").unwrap();
+        assert_eq!(response, "info string book move\nbestmove a2a3");
+
+        // With a sink the wait happens in the background, where `stop` ends it.
+        let output = Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+        let sink = Arc::clone(&output);
+        engine.set_output(move |line| sink.lock().unwrap().push(line.to_string()));
+        engine.process_command("setoption name Minimum Thinking Time value 5000").unwrap();
+        let start = Instant::now();
+        assert_eq!(engine.process_command("go wtime 60000 btime 60000").unwrap(), "");
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(engine.process_command("stop").unwrap(), "");
+        assert!(start.elapsed() < Duration::from_secs(2), "{:?}", start.elapsed());
+        assert_eq!(*output.lock().unwrap(), ["info string book move", "bestmove a2a3"]);
+    }
+
+    #[test]
+    fn test_status_reports_repetition_across_position_moves() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        let shuffle = "g1f3 g8f6 f3g1 f6g8";
+        engine
+            .process_command(&format!("position startpos moves e2e4 e7e5 {} {}", shuffle, shuffle))
+            .unwrap();
+        assert_eq!(engine.game_status(), GameStatus::ThreefoldRepetition);
+        assert_eq!(
+            engine.process_command("status").unwrap(),
+            "draw by threefold repetition"
+        );
+        engine.process_command("position startpos moves e2e4").unwrap();
+        assert_eq!(engine.process_command("status").unwrap(), "ongoing");
+    }
+
+    #[test]
+    fn test_wdl_command_without_tables() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        engine.process_command("position fen 8/8/8/8/2k5/8/8/Q3K3 w - - 0 1").unwrap();
+        assert_eq!(engine.process_command("wdl").unwrap(), "wdl not in tablebase");
+    }
+
+    #[test]
+    fn test_wdl_command_reports_what_the_probe_finds() {
+        let dir = std::env::temp_dir().join(format!("brainfish-wdl-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("KQvK.rtbw"), [0x71, 0xE8, 0x23, 0x5D]).unwrap();
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        engine
+            .process_command(&format!("setoption name SyzygyPath value {}", dir.display()))
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        engine.process_command("position fen 8/8/8/8/2k5/8/8/Q3K3 w - - 0 1").unwrap();
+        let response = engine.process_command("wdl").unwrap();
+        assert_eq!(response, "wdl tables found but probing unsupported");
+        engine.set_tablebase_probe(|board| {
+            assert_eq!(board.to_fen(), "8/8/8/8/2k5/8/8/Q3K3 w - - 0 1");
+            Some(TableResult {
+                wdl: tablebase::Wdl::Win,
+                dtz: Some(17),
+            })
+        });
+        assert_eq!(engine.process_command("wdl").unwrap(), "wdl win dtz 17");
+        // Material without a table is never probed.
+        engine.process_command("position fen 8/8/8/8/2k5/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(engine.process_command("wdl").unwrap(), "wdl not in tablebase");
+    }
+
+    #[test]
+    fn test_missing_syzygy_path_falls_back_to_search() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        let missing = std::env::temp_dir().join("brainfish-no-such-syzygy-dir");
+        let response = engine
+            .process_command(&format!("setoption name SyzygyPath value {}", missing.display()))
+            .unwrap();
+        assert!(response.contains("info string Syzygy: cannot read"));
+        assert!(response.ends_with("probing disabled"));
+        assert!(engine.tablebases().is_empty());
+
+        engine.process_command("position fen 8/8/8/8/2k5/8/8/Q3K3 w - - 0 1").unwrap();
+        let response = engine.process_command("go depth 4").unwrap();
+        assert!(response.contains("info depth 4"));
+        assert!(response.lines().last().unwrap().starts_with("bestmove "));
+    }
+
+    #[test]
+    fn test_position_moves_track_castling_and_en_passant() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        let start = "position fen r3k2r/2pppppp/8/1P6/8/8/2PPPPPP/R3K2R b KQkq - 0 1 moves";
+
+        // The en passant square lasts for exactly one reply.
+        engine.process_command(&format!("{} c7c5", start)).unwrap();
+        assert_eq!(engine.fen(), "r3k2r/3ppppp/8/1Pp5/8/8/2PPPPPP/R3K2R w KQkq c6 0 2");
+        engine.process_command(&format!("{} c7c5 g2g3", start)).unwrap();
+        assert!(engine.fen().contains(" KQkq - 0 2"));
+
+        // En passant, then Rxa8+ costs White the queenside (rook moved) and
+        // Black the queenside (rook taken) and kingside (king moved).
+        engine
+            .process_command(&format!("{} c7c5 b5c6 d7c6 a1a8 e8d7", start))
+            .unwrap();
+        assert_eq!(engine.fen(), "R6r/3kpppp/2p5/8/8/8/2PPPPPP/4K2R w K - 1 4");
+    }
+
+    #[test]
+    fn test_pv_positions_follow_each_other() {
+        let mut engine = Engine::new();
+        engine.initialize().unwrap();
+        assert!(engine.pv_positions().is_empty());
+
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        engine.process_command(&format!("position fen {}", fen)).unwrap();
+        engine.process_command("go depth 4").unwrap();
+        let positions = engine.pv_positions();
+        assert!(!positions.is_empty());
+
+        let mut previous = fen.to_string();
+        for (mv, after) in positions {
+            let mut board = Board::from_fen(&previous).unwrap();
+            assert!(movegen::generate_legal_moves(&board).contains(&mv));
+            board.make_move(mv);
+            assert_eq!(board.to_fen(), after);
+            previous = after;
+        }
+    }
 }
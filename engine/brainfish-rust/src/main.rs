@@ -1,4 +1,4 @@
-use brainfish_rust::Engine;
+use brainfish_rust::{decode_command, Engine};
 use log::{error, info};
 use std::io::{self, BufRead, Write};
 
@@ -14,30 +14,43 @@ fn main() -> anyhow::Result<()> {
         return Err(e.into());
     }
     info!("Engine initialized successfully");
+    // Searches report from their own thread so this loop can keep reading.
+    engine.set_output(|line| {
+        println!("{}", line);
+        let _ = io::stdout().flush();
+    });
 
     // Create input reader
     let stdin = io::stdin();
     let mut reader = stdin.lock();
-    let mut line = String::new();
+    let mut bytes = Vec::new();
 
     // Main UCI protocol loop
     loop {
         // Clear the line buffer
-        line.clear();
+        bytes.clear();
 
-        // Read input
-        if reader.read_line(&mut line)? == 0 {
-            break; // EOF
+        // Read input; stray bytes are cleaned up rather than ending the loop
+        if reader.read_until(b'\n', &mut bytes)? == 0 {
+            // EOF: finish off any search still running.
+            let response = engine.stop();
+            if !response.is_empty() {
+                println!("{}", response);
+            }
+            break;
         }
+        let line = decode_command(&bytes);
 
         // Process command
         match engine.process_command(&line) {
             Ok(response) => {
-                println!("{}", response);
-                io::stdout().flush()?;
+                if !response.is_empty() {
+                    println!("{}", response);
+                    io::stdout().flush()?;
+                }
 
                 // Check for quit command
-                if line.trim() == "quit" {
+                if line.split_whitespace().next() == Some("quit") {
                     info!("Received quit command, shutting down");
                     break;
                 }
@@ -0,0 +1,133 @@
+use crate::board::Board;
+use crate::movegen;
+use crate::moves::Move;
+use std::collections::HashMap;
+
+/// Positions remembered when nothing else is configured.
+pub const DEFAULT_MOVE_CACHE_SIZE: usize = 64;
+
+/// Least-recently-used cache of legal move lists, keyed by Zobrist hash.
+/// Front ends ask for the same position's moves over and over.
+#[derive(Clone, Debug)]
+pub struct MoveCache {
+    capacity: usize,
+    // Hash -> (moves, tick of the last use).
+    entries: HashMap<u64, (Vec<Move>, u64)>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl Default for MoveCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_MOVE_CACHE_SIZE)
+    }
+}
+
+impl MoveCache {
+    /// A cache holding up to `capacity` positions; 0 disables caching.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::with_capacity(capacity),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Legal moves on `board`, generated only if not already cached.
+    pub fn legal_moves(&mut self, board: &Board) -> Vec<Move> {
+        self.tick += 1;
+        let key = board.hash();
+        if let Some((moves, used)) = self.entries.get_mut(&key) {
+            *used = self.tick;
+            self.hits += 1;
+            return moves.clone();
+        }
+        self.misses += 1;
+        let moves = movegen::generate_legal_moves(board);
+        if self.capacity > 0 {
+            if self.entries.len() >= self.capacity {
+                self.evict_oldest();
+            }
+            self.entries.insert(key, (moves.clone(), self.tick));
+        }
+        moves
+    }
+
+    fn evict_oldest(&mut self) {
+        if let Some(key) = self
+            .entries
+            .iter()
+            .min_by_key(|(_, (_, used))| *used)
+            .map(|(&key, _)| key)
+        {
+            self.entries.remove(&key);
+        }
+    }
+
+    /// Changes the bound, dropping the least recently used positions to fit.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.evict_oldest();
+        }
+    }
+
+    /// Forgets every position; the hit and miss counts start over too.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.hits = 0;
+        self.misses = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = MoveCache::new(2);
+        let start = Board::startpos();
+        let kings = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let rooks = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        cache.legal_moves(&start);
+        cache.legal_moves(&kings);
+        // Touching the start position makes the bare kings the oldest.
+        assert_eq!(cache.legal_moves(&start).len(), 20);
+        cache.legal_moves(&rooks);
+        assert_eq!(cache.len(), 2);
+        assert_eq!((cache.hits(), cache.misses()), (1, 3));
+        cache.legal_moves(&start);
+        assert_eq!(cache.hits(), 2);
+        cache.legal_moves(&kings);
+        assert_eq!(cache.misses(), 4);
+    }
+
+    #[test]
+    fn test_zero_capacity_caches_nothing() {
+        let mut cache = MoveCache::new(0);
+        cache.legal_moves(&Board::startpos());
+        cache.legal_moves(&Board::startpos());
+        assert!(cache.is_empty());
+        assert_eq!(cache.hits(), 0);
+    }
+}
@@ -0,0 +1,605 @@
+use crate::bitboard::{self, squares, Bitboard};
+use crate::board::{Board, CastlingRights};
+use crate::moves::Move;
+use crate::types::{Color, PieceKind, Square};
+
+const PROMOTIONS: [PieceKind; 4] = [
+    PieceKind::Queen,
+    PieceKind::Rook,
+    PieceKind::Bishop,
+    PieceKind::Knight,
+];
+
+fn push_pawn_move(moves: &mut Vec<Move>, from: Square, to: Square) {
+    if to.rank() == 0 || to.rank() == 7 {
+        for kind in PROMOTIONS {
+            moves.push(Move::with_promotion(from, to, kind));
+        }
+    } else {
+        moves.push(Move::new(from, to));
+    }
+}
+
+// With `captures_only`, pushes are generated only when they promote.
+fn generate_pawn_moves(board: &Board, moves: &mut Vec<Move>, captures_only: bool) {
+    let us = board.side_to_move();
+    let them_bb = board.color_occupancy(us.opposite());
+    let empty = !board.occupancy();
+    let (start_rank, last_rank) = match us {
+        Color::White => (1, 7),
+        Color::Black => (6, 0),
+    };
+
+    for from in squares(board.pieces(us, PieceKind::Pawn)) {
+        if let Some(one) = from.forward(us) {
+            if captures_only {
+                if one.rank() == last_rank && empty & one.bitboard() != 0 {
+                    push_pawn_move(moves, from, one);
+                }
+            } else if empty & one.bitboard() != 0 {
+                push_pawn_move(moves, from, one);
+                if from.rank() == start_rank {
+                    let two = one.forward(us).unwrap();
+                    if empty & two.bitboard() != 0 {
+                        moves.push(Move::new(from, two));
+                    }
+                }
+            }
+        }
+
+        let mut targets = them_bb;
+        if let Some(ep) = board.en_passant() {
+            targets |= ep.bitboard();
+        }
+        for to in squares(bitboard::pawn_attacks(us, from) & targets) {
+            push_pawn_move(moves, from, to);
+        }
+    }
+}
+
+fn generate_piece_moves(board: &Board, moves: &mut Vec<Move>, targets: Bitboard) {
+    let us = board.side_to_move();
+    let occupancy = board.occupancy();
+
+    for kind in [
+        PieceKind::Knight,
+        PieceKind::Bishop,
+        PieceKind::Rook,
+        PieceKind::Queen,
+        PieceKind::King,
+    ] {
+        for from in squares(board.pieces(us, kind)) {
+            let attacks: Bitboard = match kind {
+                PieceKind::Knight => bitboard::knight_attacks(from),
+                PieceKind::Bishop => bitboard::bishop_attacks(from, occupancy),
+                PieceKind::Rook => bitboard::rook_attacks(from, occupancy),
+                PieceKind::Queen => bitboard::queen_attacks(from, occupancy),
+                _ => bitboard::king_attacks(from),
+            };
+            for to in squares(attacks & targets) {
+                moves.push(Move::new(from, to));
+            }
+        }
+    }
+}
+
+/// Castling needs the squares between king and rook empty, and the king's
+/// start, transit and destination squares all free of attack. The b-file
+/// square on the queenside only has to be empty.
+fn generate_castling_moves(board: &Board, moves: &mut Vec<Move>) {
+    let us = board.side_to_move();
+    let them = us.opposite();
+    let rank = match us {
+        Color::White => 0,
+        Color::Black => 7,
+    };
+    let rights = board.castling_rights();
+    let sq = |file: u8| Square::from_coords(file, rank).unwrap();
+    let king = sq(4);
+
+    let sides = [
+        (CastlingRights::kingside(us), &[5u8, 6][..], &[4u8, 5, 6][..], 6),
+        (CastlingRights::queenside(us), &[1, 2, 3][..], &[4, 3, 2][..], 2),
+    ];
+    for (right, must_be_empty, must_be_safe, target) in sides {
+        // Only castling with the corner rook can be expressed as a king move.
+        if !rights.contains(right) || !rights.is_standard(right) {
+            continue;
+        }
+        if must_be_empty
+            .iter()
+            .any(|&file| board.occupancy() & sq(file).bitboard() != 0)
+        {
+            continue;
+        }
+        if must_be_safe
+            .iter()
+            .any(|&file| board.is_square_attacked(sq(file), them))
+        {
+            continue;
+        }
+        moves.push(Move::new(king, sq(target)));
+    }
+}
+
+/// Moves that obey piece movement rules but may leave the king in check.
+pub fn generate_pseudo_legal_moves(board: &Board) -> Vec<Move> {
+    let mut moves = Vec::with_capacity(64);
+    generate_pawn_moves(board, &mut moves, false);
+    let not_own = !board.color_occupancy(board.side_to_move());
+    generate_piece_moves(board, &mut moves, not_own);
+    generate_castling_moves(board, &mut moves);
+    moves
+}
+
+pub fn generate_legal_moves(board: &Board) -> Vec<Move> {
+    if board.checkers() != 0 {
+        return generate_evasions(board);
+    }
+    let mut moves = generate_pseudo_legal_moves(board);
+    retain_legal(board, &mut moves);
+    moves
+}
+
+/// Legal moves for a side in check: king steps, and against a single checker
+/// also captures of it and interpositions on a sliding check.
+fn generate_evasions(board: &Board) -> Vec<Move> {
+    let us = board.side_to_move();
+    let king = board.king_square(us);
+    let checkers = board.checkers();
+
+    let mut moves = Vec::with_capacity(16);
+    for to in squares(bitboard::king_attacks(king) & !board.color_occupancy(us)) {
+        moves.push(Move::new(king, to));
+    }
+    if checkers.count_ones() == 1 {
+        let checker = squares(checkers).next().unwrap();
+        let targets = checkers | bitboard::between(king, checker);
+
+        let mut pawn_moves = Vec::new();
+        generate_pawn_moves(board, &mut pawn_moves, false);
+        // En passant can also remove a checking pawn that just moved two squares.
+        let en_passant = board
+            .en_passant()
+            .filter(|ep| ep.forward(us.opposite()) == Some(checker));
+        pawn_moves.retain(|mv| targets & mv.to.bitboard() != 0 || Some(mv.to) == en_passant);
+        moves.extend(pawn_moves);
+
+        let mut piece_moves = Vec::new();
+        generate_piece_moves(board, &mut piece_moves, targets);
+        piece_moves.retain(|mv| mv.from != king);
+        moves.extend(piece_moves);
+    }
+    retain_legal(board, &mut moves);
+    moves
+}
+
+/// Legal captures, en passant included, plus every promotion.
+pub fn generate_captures(board: &Board) -> Vec<Move> {
+    let mut moves = Vec::with_capacity(16);
+    generate_captures_into(board, &mut moves);
+    moves
+}
+
+/// `generate_captures` into `moves`, replacing what was there; reusing one
+/// buffer saves an allocation per call.
+pub fn generate_captures_into(board: &Board, moves: &mut Vec<Move>) {
+    moves.clear();
+    generate_pawn_moves(board, moves, true);
+    let them = board.color_occupancy(board.side_to_move().opposite());
+    generate_piece_moves(board, moves, them);
+    retain_legal(board, moves);
+}
+
+/// Legal moves that are neither captures nor promotions: exactly the legal
+/// moves `generate_captures` leaves out.
+pub fn generate_quiets(board: &Board) -> Vec<Move> {
+    let mut moves = Vec::with_capacity(48);
+    generate_quiets_into(board, &mut moves);
+    moves
+}
+
+/// `generate_quiets` into `moves`, replacing what was there.
+pub fn generate_quiets_into(board: &Board, moves: &mut Vec<Move>) {
+    moves.clear();
+    generate_pawn_moves(board, moves, false);
+    // Pawns capture diagonally, en passant included.
+    moves.retain(|mv| mv.promotion.is_none() && mv.from.file() == mv.to.file());
+    generate_piece_moves(board, moves, !board.occupancy());
+    generate_castling_moves(board, moves);
+    retain_legal(board, moves);
+}
+
+/// Whether `mv` is legal on `board`, without generating every move. Any
+/// value is accepted, e.g. a move read from the hash table.
+pub fn is_legal(board: &Board, mv: Move) -> bool {
+    let us = board.side_to_move();
+    let Some(piece) = board.piece_at(mv.from).filter(|p| p.color() == us) else {
+        return false;
+    };
+    if board.color_occupancy(us) & mv.to.bitboard() != 0 {
+        return false;
+    }
+    let occupancy = board.occupancy();
+    let pseudo_legal = match piece.kind() {
+        PieceKind::Pawn => is_pseudo_legal_pawn_move(board, mv),
+        _ if mv.promotion.is_some() => false,
+        PieceKind::King if mv.from.file().abs_diff(mv.to.file()) == 2 => {
+            let mut moves = Vec::new();
+            generate_castling_moves(board, &mut moves);
+            moves.contains(&mv)
+        }
+        kind => {
+            let attacks = match kind {
+                PieceKind::Knight => bitboard::knight_attacks(mv.from),
+                PieceKind::Bishop => bitboard::bishop_attacks(mv.from, occupancy),
+                PieceKind::Rook => bitboard::rook_attacks(mv.from, occupancy),
+                PieceKind::Queen => bitboard::queen_attacks(mv.from, occupancy),
+                _ => bitboard::king_attacks(mv.from),
+            };
+            attacks & mv.to.bitboard() != 0
+        }
+    };
+    pseudo_legal && keeps_king_safe(board, pinned_pieces(board), &mut board.clone(), mv)
+}
+
+// `generate_pawn_moves` for a single move, without building a list.
+fn is_pseudo_legal_pawn_move(board: &Board, mv: Move) -> bool {
+    let us = board.side_to_move();
+    let promotes = mv.to.rank() == 0 || mv.to.rank() == 7;
+    let promotion_ok = match mv.promotion {
+        Some(kind) => promotes && PROMOTIONS.contains(&kind),
+        None => !promotes,
+    };
+    if !promotion_ok {
+        return false;
+    }
+    let empty = !board.occupancy();
+    let Some(one) = mv.from.forward(us) else {
+        return false;
+    };
+    if mv.to == one {
+        return empty & one.bitboard() != 0;
+    }
+    let start_rank = match us {
+        Color::White => 1,
+        Color::Black => 6,
+    };
+    if mv.from.rank() == start_rank && one.forward(us) == Some(mv.to) {
+        return empty & one.bitboard() != 0 && empty & mv.to.bitboard() != 0;
+    }
+    let mut targets = board.color_occupancy(us.opposite());
+    if let Some(ep) = board.en_passant() {
+        targets |= ep.bitboard();
+    }
+    bitboard::pawn_attacks(us, mv.from) & targets & mv.to.bitboard() != 0
+}
+
+/// Our pieces that stand alone between our king and an enemy slider.
+fn pinned_pieces(board: &Board) -> Bitboard {
+    let us = board.side_to_move();
+    let them = us.opposite();
+    let king = board.king_square(us);
+    let queens = board.pieces(them, PieceKind::Queen);
+    let snipers = bitboard::rook_attacks(king, 0) & (board.pieces(them, PieceKind::Rook) | queens)
+        | bitboard::bishop_attacks(king, 0) & (board.pieces(them, PieceKind::Bishop) | queens);
+
+    let mut pinned = 0;
+    for sniper in squares(snipers) {
+        let blockers = bitboard::between(king, sniper) & board.occupancy();
+        if blockers.count_ones() == 1 {
+            pinned |= blockers & board.color_occupancy(us);
+        }
+    }
+    pinned
+}
+
+fn leaves_king_safe(scratch: &mut Board, mv: Move) -> bool {
+    let undo = scratch.make_move(mv);
+    let legal = !scratch.left_king_in_check();
+    scratch.unmake_move(mv, &undo);
+    legal
+}
+
+/// Whether capturing en passant with `mv` leaves the king attacked. Both pawns
+/// leave the rank at once, which can open it to a rook or queen even when
+/// neither pawn counts as pinned, so the check uses the occupancy after the
+/// capture rather than the pin mask.
+fn en_passant_exposes_king(board: &Board, mv: Move) -> bool {
+    let us = board.side_to_move();
+    let king = board.king_square(us);
+    let captured = Square::from_coords(mv.to.file(), mv.from.rank()).unwrap().bitboard();
+    let occupancy = board.occupancy() & !mv.from.bitboard() & !captured | mv.to.bitboard();
+    let enemies = board.color_occupancy(us.opposite()) & !captured;
+    board.attackers_to(king, occupancy) & enemies != 0
+}
+
+/// Keeps the legal moves among pseudo-legal `moves`.
+fn retain_legal(board: &Board, moves: &mut Vec<Move>) {
+    let pinned = pinned_pieces(board);
+    let mut scratch = board.clone();
+    moves.retain(|&mv| keeps_king_safe(board, pinned, &mut scratch, mv));
+}
+
+/// Whether pseudo-legal `mv` is legal, given the `pinned` pieces and a copy
+/// of `board` to try moves on. Out of check, a piece that isn't pinned can't
+/// expose its king and a pinned one only has to stay on its pin line; en
+/// passant gets its own occupancy test, and king moves and check evasions
+/// are verified by playing them.
+fn keeps_king_safe(board: &Board, pinned: Bitboard, scratch: &mut Board, mv: Move) -> bool {
+    let king = board.king_square(board.side_to_move());
+    let en_passant = Some(mv.to) == board.en_passant()
+        && board.piece_at(mv.from).map(|p| p.kind()) == Some(PieceKind::Pawn);
+    if en_passant {
+        !en_passant_exposes_king(board, mv)
+    } else if board.checkers() != 0 || mv.from == king {
+        leaves_king_safe(scratch, mv)
+    } else if pinned & mv.from.bitboard() != 0 {
+        bitboard::line(king, mv.from) & mv.to.bitboard() != 0
+    } else {
+        true
+    }
+}
+
+/// Counts leaf nodes of the legal move tree to the given depth.
+pub fn perft(board: &mut Board, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = generate_legal_moves(board);
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    let mut nodes = 0;
+    for mv in moves {
+        let undo = board.make_move(mv);
+        nodes += perft(board, depth - 1);
+        board.unmake_move(mv, &undo);
+    }
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn perft_fen(fen: &str, depth: u32) -> u64 {
+        perft(&mut Board::from_fen(fen).unwrap(), depth)
+    }
+
+    #[test]
+    fn test_perft_start_position() {
+        assert_eq!(perft(&mut Board::startpos(), 1), 20);
+        assert_eq!(perft(&mut Board::startpos(), 2), 400);
+        assert_eq!(perft(&mut Board::startpos(), 3), 8902);
+    }
+
+    #[test]
+    fn test_perft_tricky_positions() {
+        // Kiwipete: castling, en passant and promotions all in play.
+        assert_eq!(
+            perft_fen(
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                2
+            ),
+            2039
+        );
+        assert_eq!(perft_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 3), 2812);
+        assert_eq!(
+            perft_fen(
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                2
+            ),
+            264
+        );
+        assert_eq!(
+            perft_fen(
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                2
+            ),
+            1486
+        );
+    }
+
+    #[test]
+    fn test_generate_captures_matches_filtered_legal_moves() {
+        for fen in [
+            crate::board::START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            let mut expected: Vec<String> = generate_legal_moves(&board)
+                .into_iter()
+                .filter(|mv| {
+                    let en_passant = Some(mv.to) == board.en_passant()
+                        && board.piece_at(mv.from).unwrap().kind() == PieceKind::Pawn;
+                    board.piece_at(mv.to).is_some() || en_passant || mv.promotion.is_some()
+                })
+                .map(|mv| mv.to_uci())
+                .collect();
+            let mut captures: Vec<String> =
+                generate_captures(&board).iter().map(|mv| mv.to_uci()).collect();
+            expected.sort();
+            captures.sort();
+            assert_eq!(captures, expected, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_quiets_and_captures_split_the_legal_moves() {
+        for fen in [
+            crate::board::START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "4r1k1/8/8/8/8/2N5/3B4/4K3 w - - 0 1",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            let legal = generate_legal_moves(&board);
+            let mut split = generate_captures(&board);
+            split.extend(generate_quiets(&board));
+            assert_eq!(split.len(), legal.len(), "{}", fen);
+            assert!(legal.iter().all(|mv| split.contains(mv)), "{}", fen);
+
+            // `is_legal` agrees with the generator on every conceivable move.
+            // A king "promotion" can only come from a corrupt hash entry.
+            let promotions = [
+                None,
+                Some(PieceKind::Queen),
+                Some(PieceKind::Knight),
+                Some(PieceKind::King),
+            ];
+            for from in (0..64).filter_map(Square::new) {
+                for to in (0..64).filter_map(Square::new) {
+                    for promotion in promotions {
+                        let mv = Move { from, to, promotion };
+                        assert_eq!(is_legal(&board, mv), legal.contains(&mv), "{} {}", fen, mv);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Legal moves by brute force: play every pseudo-legal move.
+    fn slow_legal_moves(board: &Board) -> Vec<String> {
+        let mut scratch = board.clone();
+        let mut moves: Vec<String> = generate_pseudo_legal_moves(board)
+            .into_iter()
+            .filter(|&mv| leaves_king_safe(&mut scratch, mv))
+            .map(|mv| mv.to_uci())
+            .collect();
+        moves.sort();
+        moves
+    }
+
+    fn assert_matches_slow_legal(fen: &str) -> Vec<String> {
+        let board = Board::from_fen(fen).unwrap();
+        let mut moves: Vec<String> =
+            generate_legal_moves(&board).iter().map(|mv| mv.to_uci()).collect();
+        moves.sort();
+        assert_eq!(moves, slow_legal_moves(&board), "{}", fen);
+        moves
+    }
+
+    fn assert_evasions_match(fen: &str) -> Vec<String> {
+        let board = Board::from_fen(fen).unwrap();
+        assert_ne!(board.checkers(), 0, "{}", fen);
+        let expected = slow_legal_moves(&board);
+        let mut evasions: Vec<String> =
+            generate_evasions(&board).iter().map(|mv| mv.to_uci()).collect();
+        evasions.sort();
+        assert_eq!(evasions, expected, "{}", fen);
+        evasions
+    }
+
+    #[test]
+    fn test_evasions_block_a_sliding_check() {
+        // Rook check along the e-file; the bishop and knight can interpose.
+        let moves = assert_evasions_match("4r1k1/8/8/8/8/2N5/3B4/4K3 w - - 0 1");
+        assert!(moves.contains(&String::from("d2e3")));
+        assert!(moves.contains(&String::from("c3e2")));
+    }
+
+    #[test]
+    fn test_evasions_capture_the_checker() {
+        let moves = assert_evasions_match("6k1/8/8/8/8/3n4/2B5/4K3 w - - 0 1");
+        assert!(moves.contains(&String::from("c2d3")));
+        // The checking pawn can be taken en passant.
+        let moves = assert_evasions_match("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1");
+        assert!(moves.contains(&String::from("e4d3")));
+    }
+
+    #[test]
+    fn test_double_check_allows_only_king_moves() {
+        // Rook on e8 and bishop on b4 both give check.
+        let fen = "4r1k1/8/8/8/1b6/8/8/4K1N1 w - - 0 1";
+        let board = Board::from_fen(fen).unwrap();
+        assert_eq!(board.checkers().count_ones(), 2);
+        let king = board.king_square(Color::White);
+        for mv in generate_legal_moves(&board) {
+            assert_eq!(mv.from, king);
+        }
+        assert_evasions_match(fen);
+    }
+
+    fn castles(fen: &str) -> Vec<String> {
+        let board = Board::from_fen(fen).unwrap();
+        let king = board.king_square(board.side_to_move());
+        generate_legal_moves(&board)
+            .into_iter()
+            .filter(|mv| mv.from == king && mv.from.file().abs_diff(mv.to.file()) == 2)
+            .map(|mv| mv.to_uci())
+            .collect()
+    }
+
+    #[test]
+    fn test_no_castling_out_of_check() {
+        assert!(castles("4r1k1/8/8/8/8/8/8/R3K2R w KQ - 0 1").is_empty());
+    }
+
+    #[test]
+    fn test_no_castling_through_an_attacked_square() {
+        // The rook on f8 covers f1, so only the queenside remains.
+        assert_eq!(castles("5rk1/8/8/8/8/8/8/R3K2R w KQ - 0 1"), ["e1c1"]);
+        // d8 covers d1 on the queenside path.
+        assert_eq!(castles("3r2k1/8/8/8/8/8/8/R3K2R w KQ - 0 1"), ["e1g1"]);
+    }
+
+    #[test]
+    fn test_castling_to_safe_squares_is_legal() {
+        let mut both = castles("6k1/8/8/8/8/8/8/R3K2R w KQ - 0 1");
+        both.sort();
+        assert_eq!(both, ["e1c1", "e1g1"]);
+        // An attacked b1 does not matter, the king never crosses it.
+        assert_eq!(castles("1r4k1/8/8/8/8/8/8/R3K3 w Q - 0 1"), ["e1c1"]);
+        assert_eq!(castles("r3k2r/8/8/8/8/8/8/6K1 b kq - 0 1").len(), 2);
+    }
+
+    #[test]
+    fn test_pinned_knight_cannot_move() {
+        let fen = "4r1k1/8/8/8/8/8/4N3/4K3 w - - 0 1";
+        let moves = assert_matches_slow_legal(fen);
+        assert!(moves.iter().all(|mv| !mv.starts_with("e2")));
+    }
+
+    #[test]
+    fn test_pinned_rook_slides_along_the_pin() {
+        let moves = assert_matches_slow_legal("4r1k1/8/8/8/8/8/4R3/4K3 w - - 0 1");
+        let rook: Vec<&String> = moves.iter().filter(|mv| mv.starts_with("e2")).collect();
+        assert_eq!(rook.len(), 6);
+        assert!(rook.iter().all(|mv| mv.as_bytes()[2] == b'e'));
+        assert!(moves.contains(&String::from("e2e8")));
+    }
+
+    #[test]
+    fn test_en_passant_pins() {
+        // Taking en passant would clear both pawns off the king's rank.
+        let moves = assert_matches_slow_legal("8/8/8/KPp4r/8/8/8/7k w - c6 0 2");
+        assert!(!moves.contains(&String::from("b5c6")));
+        // Along a diagonal pin the capture stays on the line and is fine.
+        let moves = assert_matches_slow_legal("b6k/8/8/2pP4/8/8/6K1/8 w - c6 0 2");
+        assert!(moves.contains(&String::from("d5c6")));
+        assert!(!moves.contains(&String::from("d5d6")));
+        assert_matches_slow_legal(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        );
+    }
+
+    #[test]
+    fn test_en_passant_discovered_check() {
+        // After c7-c5 the rook on a5 is screened from the king on e5 by both
+        // pawns; dxc6 e.p. would remove them together.
+        let moves = assert_matches_slow_legal("8/8/8/r1pPK3/8/8/8/7k w - c6 0 2");
+        assert!(!moves.contains(&String::from("d5c6")));
+        assert!(moves.contains(&String::from("d5d6")));
+        // The same capture is fine when it removes a checking pawn.
+        let moves = assert_matches_slow_legal("8/8/8/2pP4/3K4/8/8/7k w - c6 0 2");
+        assert!(moves.contains(&String::from("d5c6")));
+        // Black's version, with a queen along the fourth rank.
+        let moves = assert_matches_slow_legal("8/8/8/8/kpP4Q/8/8/7K b - c3 0 1");
+        assert!(!moves.contains(&String::from("b4c3")));
+    }
+}
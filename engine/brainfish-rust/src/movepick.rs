@@ -0,0 +1,281 @@
+use crate::board::Board;
+use crate::eval::PIECE_VALUES;
+use crate::movegen;
+use crate::moves::Move;
+use crate::types::PieceKind;
+
+/// Scores of history entries stay within +-this.
+pub const HISTORY_MAX: i32 = 16_384;
+
+/// How well each quiet move, by from and to square, has done: credited for
+/// cutoffs and debited for failing to cut where another quiet move did, both
+/// weighted by depth. Each search thread keeps its own.
+#[derive(Clone, Debug)]
+pub struct History {
+    scores: Vec<i32>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            scores: vec![0; 64 * 64],
+        }
+    }
+}
+
+impl History {
+    fn index(mv: Move) -> usize {
+        mv.from.index() * 64 + mv.to.index()
+    }
+
+    pub fn score(&self, mv: Move) -> i32 {
+        self.scores[Self::index(mv)]
+    }
+
+    /// Credits `mv` with a cutoff at `depth`. Deep cutoffs count for more.
+    pub fn reward(&mut self, mv: Move, depth: u32) {
+        self.update(mv, Self::bonus(depth));
+    }
+
+    /// Debits `mv`, searched at `depth` before another quiet move cut off.
+    pub fn penalize(&mut self, mv: Move, depth: u32) {
+        self.update(mv, -Self::bonus(depth));
+    }
+
+    fn bonus(depth: u32) -> i32 {
+        depth.saturating_mul(depth).saturating_mul(16).min(HISTORY_MAX as u32) as i32
+    }
+
+    // Each update moves the score part of the way to +-HISTORY_MAX, so old
+    // results fade and the score never leaves the range.
+    fn update(&mut self, mv: Move, bonus: i32) {
+        let score = &mut self.scores[Self::index(mv)];
+        *score += bonus - *score * bonus.abs() / HISTORY_MAX;
+    }
+
+    pub fn clear(&mut self) {
+        self.scores.fill(0);
+    }
+}
+
+/// Most valuable victim, then least valuable attacker. Queen promotions come
+/// just after the captures; every other move scores 0.
+pub fn mvv_lva(board: &Board, mv: Move) -> i32 {
+    let mut score = 0;
+    if let Some(victim) = board.piece_at(mv.to) {
+        let attacker = board.piece_at(mv.from).map_or(0, |p| p.kind().index() as i32);
+        score += 10_000 + PIECE_VALUES[victim.kind().index()] - attacker;
+    }
+    if mv.promotion == Some(PieceKind::Queen) {
+        score += 9_000;
+    }
+    score
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    TtMove,
+    Captures,
+    Killers,
+    Quiets,
+    Done,
+}
+
+/// Yields the legal moves of a position one at a time: the hash move, then
+/// captures and promotions by `mvv_lva`, then the killers, then the other
+/// quiet moves by history. A stage's moves are only generated once the
+/// earlier stages are used up, so a cutoff on the hash move generates none.
+///
+/// The board passed to `next` must be the one the picker was made for.
+#[derive(Clone, Debug)]
+pub struct MovePicker {
+    stage: Stage,
+    tt_move: Option<Move>,
+    killers: [Option<Move>; 2],
+    // The current stage's moves, best last so they can be popped.
+    pending: Vec<Move>,
+    killer_index: usize,
+}
+
+impl MovePicker {
+    /// `tt_move` and `killers` may be anything, legal here or not; they are
+    /// only yielded if legal, and never twice.
+    pub fn new(board: &Board, tt_move: Option<Move>, killers: [Option<Move>; 2]) -> Self {
+        Self::with_buffer(board, tt_move, killers, Vec::new())
+    }
+
+    /// As `new`, generating moves into `buffer` instead of a fresh `Vec`.
+    /// Its contents don't matter; `into_buffer` hands it back for reuse.
+    pub fn with_buffer(
+        board: &Board,
+        tt_move: Option<Move>,
+        killers: [Option<Move>; 2],
+        buffer: Vec<Move>,
+    ) -> Self {
+        Self {
+            stage: Stage::TtMove,
+            tt_move: tt_move.filter(|&mv| movegen::is_legal(board, mv)),
+            killers,
+            pending: buffer,
+            killer_index: 0,
+        }
+    }
+
+    pub fn into_buffer(self) -> Vec<Move> {
+        self.pending
+    }
+
+    pub fn next(&mut self, board: &Board, history: &History) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::TtMove => {
+                    self.stage = Stage::Captures;
+                    let captures = &mut self.pending;
+                    movegen::generate_captures_into(board, captures);
+                    captures.retain(|&mv| Some(mv) != self.tt_move);
+                    // Reversed first so that equal scores pop in generation order.
+                    captures.reverse();
+                    captures.sort_by_key(|&mv| mvv_lva(board, mv));
+                    if self.tt_move.is_some() {
+                        return self.tt_move;
+                    }
+                }
+                Stage::Captures => match self.pending.pop() {
+                    Some(mv) => return Some(mv),
+                    None => self.stage = Stage::Killers,
+                },
+                Stage::Killers => {
+                    let Some(&killer) = self.killers.get(self.killer_index) else {
+                        self.stage = Stage::Quiets;
+                        let mut quiets = std::mem::take(&mut self.pending);
+                        movegen::generate_quiets_into(board, &mut quiets);
+                        quiets.retain(|&mv| !self.already_yielded(mv));
+                        quiets.reverse();
+                        quiets.sort_by_key(|&mv| history.score(mv));
+                        self.pending = quiets;
+                        continue;
+                    };
+                    self.killer_index += 1;
+                    let Some(mv) = killer else { continue };
+                    let earlier = &self.killers[..self.killer_index - 1];
+                    let fresh = Some(mv) != self.tt_move && !earlier.contains(&Some(mv));
+                    if fresh && is_quiet(board, mv) && movegen::is_legal(board, mv) {
+                        return Some(mv);
+                    }
+                    // Not yielded after all, so the quiet stage must not skip it.
+                    self.killers[self.killer_index - 1] = None;
+                }
+                Stage::Quiets => match self.pending.pop() {
+                    Some(mv) => return Some(mv),
+                    None => self.stage = Stage::Done,
+                },
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    fn already_yielded(&self, mv: Move) -> bool {
+        Some(mv) == self.tt_move || self.killers.contains(&Some(mv))
+    }
+}
+
+/// Neither a capture (en passant included) nor a promotion.
+pub fn is_quiet(board: &Board, mv: Move) -> bool {
+    let en_passant = board.en_passant() == Some(mv.to)
+        && board.piece_at(mv.from).map(|p| p.kind()) == Some(PieceKind::Pawn);
+    mv.promotion.is_none() && board.piece_at(mv.to).is_none() && !en_passant
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uci_move(board: &Board, text: &str) -> Move {
+        movegen::generate_legal_moves(board)
+            .into_iter()
+            .find(|mv| mv.to_uci() == text)
+            .unwrap()
+    }
+
+    fn picked(board: &Board, tt_move: Option<Move>, killers: [Option<Move>; 2]) -> Vec<Move> {
+        let history = History::default();
+        let mut picker = MovePicker::new(board, tt_move, killers);
+        std::iter::from_fn(|| picker.next(board, &history)).collect()
+    }
+
+    #[test]
+    fn test_yields_exactly_the_legal_moves() {
+        for fen in [
+            crate::board::START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "4r1k1/8/8/8/8/2N5/3B4/4K3 w - - 0 1",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            let legal = movegen::generate_legal_moves(&board);
+            let killers = [legal.last().copied(), legal.first().copied()];
+            let moves = picked(&board, legal.get(1).copied(), killers);
+            assert_eq!(moves.len(), legal.len(), "{}", fen);
+            assert!(legal.iter().all(|mv| moves.contains(mv)), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_stage_order() {
+        // Kiwipete: pawns, a knight and a bishop can be captured.
+        let board = Board::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        )
+        .unwrap();
+        let tt_move = uci_move(&board, "a2a3");
+        let killer = uci_move(&board, "a1b1");
+        // A capture as a killer is left to the capture stage.
+        let capturing_killer = uci_move(&board, "e5f7");
+        let mut history = History::default();
+        history.reward(uci_move(&board, "e1d1"), 4);
+        let killers = [Some(capturing_killer), Some(killer)];
+        let mut picker = MovePicker::new(&board, Some(tt_move), killers);
+        let moves: Vec<String> = std::iter::from_fn(|| picker.next(&board, &history))
+            .map(|mv| mv.to_uci())
+            .collect();
+
+        let captures = movegen::generate_captures(&board).len();
+        assert_eq!(moves[0], "a2a3");
+        // Bishop takes bishop: the most valuable victim.
+        assert_eq!(moves[1], "e2a6");
+        assert!(moves[1..=captures].contains(&String::from("e5f7")));
+        assert_eq!(moves[captures + 1], "a1b1");
+        assert_eq!(moves[captures + 2], "e1d1");
+        assert_eq!(moves.len(), movegen::generate_legal_moves(&board).len());
+    }
+
+    #[test]
+    fn test_history_stays_in_range() {
+        let board = Board::startpos();
+        let mv = uci_move(&board, "g1f3");
+        let mut history = History::default();
+        history.reward(mv, 3);
+        let once = history.score(mv);
+        assert!(once > 0);
+        for _ in 0..1000 {
+            history.reward(mv, 60);
+        }
+        assert!(history.score(mv) > once && history.score(mv) <= HISTORY_MAX);
+        for _ in 0..1000 {
+            history.penalize(mv, 60);
+        }
+        assert!(history.score(mv) < 0 && history.score(mv) >= -HISTORY_MAX);
+    }
+
+    #[test]
+    fn test_illegal_hash_and_killer_moves_are_skipped() {
+        let board = Board::startpos();
+        let bogus = Move::new(
+            crate::types::Square::from_algebraic("e2").unwrap(),
+            crate::types::Square::from_algebraic("e5").unwrap(),
+        );
+        let moves = picked(&board, Some(bogus), [Some(bogus), None]);
+        assert_eq!(moves.len(), 20);
+        assert!(!moves.contains(&bogus));
+    }
+}
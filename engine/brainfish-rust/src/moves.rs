@@ -0,0 +1,138 @@
+use crate::types::{PieceKind, Square};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// A move in coordinate form. Castling is encoded as the king's two-square
+/// move and en passant as the pawn's diagonal step, as in UCI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Move {
+    pub from: Square,
+    pub to: Square,
+    pub promotion: Option<PieceKind>,
+}
+
+impl Move {
+    pub fn new(from: Square, to: Square) -> Self {
+        Self {
+            from,
+            to,
+            promotion: None,
+        }
+    }
+
+    pub fn with_promotion(from: Square, to: Square, promotion: PieceKind) -> Self {
+        Self {
+            from,
+            to,
+            promotion: Some(promotion),
+        }
+    }
+
+    pub fn to_uci(self) -> String {
+        self.to_string()
+    }
+
+    /// Packs the move into 16 bits for the transposition table. Zero is never
+    /// a real move (a1a1), so it doubles as "no move".
+    pub(crate) fn to_u16(self) -> u16 {
+        let promotion = self.promotion.map_or(0, |kind| kind.index() as u16);
+        self.from.index() as u16 | (self.to.index() as u16) << 6 | promotion << 12
+    }
+
+    pub(crate) fn from_u16(bits: u16) -> Option<Move> {
+        if bits == 0 {
+            return None;
+        }
+        let from = Square::new((bits & 63) as u8)?;
+        let to = Square::new((bits >> 6 & 63) as u8)?;
+        let promotion = match bits >> 12 {
+            0 => None,
+            index => Some(*PieceKind::ALL.get(index as usize)?),
+        };
+        Some(Move {
+            from,
+            to,
+            promotion,
+        })
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ParseMoveError {
+    #[error("expected 4 or 5 characters, found {0}")]
+    Length(usize),
+    #[error("invalid square '{0}'")]
+    InvalidSquare(String),
+    #[error("invalid promotion piece '{0}'")]
+    InvalidPromotion(char),
+}
+
+/// Parses UCI coordinates such as `e2e4` or `e7e8q`. Only the syntax is
+/// checked: without a board there is no telling whether the move is legal.
+impl FromStr for Move {
+    type Err = ParseMoveError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let chars = text.chars().count();
+        if !(4..=5).contains(&chars) || !text.is_ascii() {
+            return Err(ParseMoveError::Length(chars));
+        }
+        let square = |part: &str| {
+            Square::from_algebraic(part).ok_or_else(|| ParseMoveError::InvalidSquare(part.into()))
+        };
+        let from = square(&text[..2])?;
+        let to = square(&text[2..4])?;
+        let promotion = match text[4..].chars().next() {
+            None => None,
+            Some(c @ ('n' | 'b' | 'r' | 'q')) => PieceKind::from_char(c),
+            Some(c) => return Err(ParseMoveError::InvalidPromotion(c)),
+        };
+        Ok(Move {
+            from,
+            to,
+            promotion,
+        })
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.from, self.to)?;
+        if let Some(kind) = self.promotion {
+            write!(f, "{}", kind.to_char())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uci_round_trip() {
+        // A quiet move, a capture, castling as the king's step, promotions.
+        for text in ["e2e4", "d4e5", "e1g1", "e8c8", "e7e8q", "a2a1n", "h7g8r", "b2c1b"] {
+            let mv: Move = text.parse().unwrap();
+            assert_eq!(mv.to_string(), text);
+        }
+        let mv: Move = "e7e8q".parse().unwrap();
+        assert_eq!(mv.promotion, Some(PieceKind::Queen));
+        assert_eq!(mv.from, Square::from_algebraic("e7").unwrap());
+    }
+
+    #[test]
+    fn test_malformed_uci_is_rejected() {
+        let parse = |text: &str| text.parse::<Move>();
+        assert_eq!(parse("e2e"), Err(ParseMoveError::Length(3)));
+        assert_eq!(parse("e2e4qq"), Err(ParseMoveError::Length(6)));
+        assert_eq!(parse("e2é4"), Err(ParseMoveError::Length(4)));
+        assert_eq!(parse("e9e4"), Err(ParseMoveError::InvalidSquare("e9".into())));
+        assert_eq!(parse("e2i4"), Err(ParseMoveError::InvalidSquare("i4".into())));
+        assert_eq!(parse("e7e8k"), Err(ParseMoveError::InvalidPromotion('k')));
+        assert_eq!(parse("e7e8Q"), Err(ParseMoveError::InvalidPromotion('Q')));
+        // Syntax only: this is no move in any position.
+        assert!(parse("a1a1").is_ok());
+    }
+}
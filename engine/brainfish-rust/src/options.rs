@@ -0,0 +1,525 @@
+use crate::movecache::DEFAULT_MOVE_CACHE_SIZE;
+use crate::search::{
+    DEFAULT_ASPIRATION_DELTA, DEFAULT_LMR_BASE, DEFAULT_LMR_DIVISOR, DEFAULT_LMR_WARMUP,
+    DEFAULT_RAZOR_MARGIN, MAX_PLY,
+};
+use crate::strength::{DEFAULT_ELO, MAX_ELO, MAX_SKILL_LEVEL, MIN_ELO};
+use crate::time::DEFAULT_SLOW_MOVER;
+use crate::tt::DEFAULT_HASH_MB;
+use crate::wdl::{WdlModel, WDL_A, WDL_B};
+use crate::EngineError;
+
+/// UCI option types, as announced in the `uci` response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptionKind {
+    Check { default: bool },
+    Spin { default: i64, min: i64, max: i64 },
+    /// Has no value; setting it triggers an action.
+    Button,
+    String { default: &'static str },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct OptionSpec {
+    pub name: &'static str,
+    pub kind: OptionKind,
+}
+
+pub const OPTIONS: &[OptionSpec] = &[
+    OptionSpec {
+        name: "Hash",
+        kind: OptionKind::Spin {
+            default: DEFAULT_HASH_MB as i64,
+            min: 1,
+            max: 65536,
+        },
+    },
+    OptionSpec {
+        name: "Clear Hash",
+        kind: OptionKind::Button,
+    },
+    OptionSpec {
+        name: "Threads",
+        kind: OptionKind::Spin {
+            default: 1,
+            min: 1,
+            max: 256,
+        },
+    },
+    OptionSpec {
+        name: "MultiPV",
+        kind: OptionKind::Spin {
+            default: 1,
+            min: 1,
+            max: 256,
+        },
+    },
+    OptionSpec {
+        name: "Move Overhead",
+        kind: OptionKind::Spin {
+            default: 10,
+            min: 0,
+            max: 5000,
+        },
+    },
+    OptionSpec {
+        name: "Minimum Thinking Time",
+        kind: OptionKind::Spin {
+            default: 0,
+            min: 0,
+            max: 5000,
+        },
+    },
+    OptionSpec {
+        name: "Slow Mover",
+        kind: OptionKind::Spin {
+            default: DEFAULT_SLOW_MOVER as i64,
+            min: 10,
+            max: 1000,
+        },
+    },
+    OptionSpec {
+        name: "Seed",
+        kind: OptionKind::Spin {
+            default: 0,
+            min: 0,
+            max: i32::MAX as i64,
+        },
+    },
+    OptionSpec {
+        name: "Deterministic",
+        kind: OptionKind::Check { default: false },
+    },
+    OptionSpec {
+        name: "Aspiration Delta",
+        kind: OptionKind::Spin {
+            default: DEFAULT_ASPIRATION_DELTA as i64,
+            min: 0,
+            max: 1000,
+        },
+    },
+    OptionSpec {
+        name: "LMR Base",
+        kind: OptionKind::Spin {
+            default: DEFAULT_LMR_BASE as i64,
+            min: 0,
+            max: 500,
+        },
+    },
+    OptionSpec {
+        name: "LMR Divisor",
+        kind: OptionKind::Spin {
+            default: DEFAULT_LMR_DIVISOR as i64,
+            min: 1,
+            max: 10_000,
+        },
+    },
+    OptionSpec {
+        name: "LMR Warmup",
+        kind: OptionKind::Spin {
+            default: DEFAULT_LMR_WARMUP as i64,
+            min: 0,
+            max: 100,
+        },
+    },
+    OptionSpec {
+        name: "Razor Margin",
+        kind: OptionKind::Spin {
+            default: DEFAULT_RAZOR_MARGIN as i64,
+            min: 0,
+            max: 2000,
+        },
+    },
+    OptionSpec {
+        name: "UCI_ShowWDL",
+        kind: OptionKind::Check { default: false },
+    },
+    OptionSpec {
+        name: "WDL Model A",
+        kind: OptionKind::Spin {
+            default: WDL_A as i64,
+            min: 1,
+            max: 5000,
+        },
+    },
+    OptionSpec {
+        name: "WDL Model B",
+        kind: OptionKind::Spin {
+            default: WDL_B as i64,
+            min: 1,
+            max: 5000,
+        },
+    },
+    OptionSpec {
+        name: "UCI_ScoreWhitePov",
+        kind: OptionKind::Check { default: false },
+    },
+    OptionSpec {
+        name: "PV Length",
+        kind: OptionKind::Spin {
+            default: 0,
+            min: 0,
+            max: MAX_PLY as i64,
+        },
+    },
+    OptionSpec {
+        name: "UCI_ShowCurrLine",
+        kind: OptionKind::Check { default: false },
+    },
+    OptionSpec {
+        name: "UCI_AnalyseMode",
+        kind: OptionKind::Check { default: false },
+    },
+    OptionSpec {
+        name: "UCI_LimitStrength",
+        kind: OptionKind::Check { default: false },
+    },
+    OptionSpec {
+        name: "UCI_Elo",
+        kind: OptionKind::Spin {
+            default: DEFAULT_ELO as i64,
+            min: MIN_ELO as i64,
+            max: MAX_ELO as i64,
+        },
+    },
+    OptionSpec {
+        name: "Skill Level",
+        kind: OptionKind::Spin {
+            default: MAX_SKILL_LEVEL as i64,
+            min: 0,
+            max: MAX_SKILL_LEVEL as i64,
+        },
+    },
+    OptionSpec {
+        name: "OwnBook",
+        kind: OptionKind::Check { default: true },
+    },
+    OptionSpec {
+        name: "BookFile",
+        kind: OptionKind::String { default: "" },
+    },
+    OptionSpec {
+        name: "Move Cache",
+        kind: OptionKind::Spin {
+            default: DEFAULT_MOVE_CACHE_SIZE as i64,
+            min: 0,
+            max: 4096,
+        },
+    },
+    OptionSpec {
+        name: "LogFile",
+        kind: OptionKind::String { default: "" },
+    },
+    OptionSpec {
+        name: "SyzygyPath",
+        kind: OptionKind::String { default: "" },
+    },
+];
+
+// How an empty string option is spelled on the wire.
+const EMPTY: &str = "<empty>";
+
+/// Current values of all engine options.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Options {
+    pub hash_mb: usize,
+    pub threads: usize,
+    /// Lines reported by each search.
+    pub multi_pv: usize,
+    /// Milliseconds held back from every timed move.
+    pub move_overhead_ms: u64,
+    /// Timed searches never answer sooner than this many milliseconds.
+    pub minimum_thinking_ms: u64,
+    /// Percent of the usual time spent on each timed move.
+    pub slow_mover: u32,
+    /// Seed for randomized choices; 0 uses the built-in default.
+    pub seed: u64,
+    pub deterministic: bool,
+    /// Search tuning; see `SearchConfig`.
+    pub aspiration_delta: i32,
+    pub lmr_base: u32,
+    pub lmr_divisor: u32,
+    /// Percent of the usual late move reductions in the first search after
+    /// `ucinewgame`, while the hash table and history are cold.
+    pub lmr_warmup: u32,
+    pub razor_margin: i32,
+    pub show_wdl: bool,
+    /// Parameters of the model behind the `wdl` figures; see `WdlModel`.
+    pub wdl_a: u32,
+    pub wdl_b: u32,
+    /// Report scores from White's point of view instead of the side to move's.
+    pub score_white_pov: bool,
+    /// Most moves shown per PV in `info` lines; 0 means no limit.
+    pub pv_limit: usize,
+    /// Send `info currline` now and then during searches; off as it costs time.
+    pub show_currline: bool,
+    /// Set by GUIs while analysing; trades speed for fewer oversights.
+    pub analyse_mode: bool,
+    /// Play at about `elo` instead of full strength; see `Strength`.
+    pub limit_strength: bool,
+    pub elo: u32,
+    /// Below the top level, play weaker in the way of Stockfish's Skill
+    /// Level. UCI_LimitStrength takes precedence.
+    pub skill_level: u32,
+    /// Play moves from the opening book; ignored in analyse mode.
+    pub own_book: bool,
+    /// JSON opening books in the Python engine's format, separated like
+    /// `PATH` and probed in order; empty means none.
+    pub book_file: String,
+    /// Positions whose legal moves `Engine::legal_moves` remembers.
+    pub move_cache_size: usize,
+    /// Where per-search statistics go; empty disables logging.
+    pub log_file: String,
+    /// Directories holding Syzygy tablebase files; empty disables them.
+    pub syzygy_path: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            hash_mb: DEFAULT_HASH_MB,
+            threads: 1,
+            multi_pv: 1,
+            move_overhead_ms: 10,
+            minimum_thinking_ms: 0,
+            slow_mover: DEFAULT_SLOW_MOVER,
+            seed: 0,
+            deterministic: false,
+            aspiration_delta: DEFAULT_ASPIRATION_DELTA,
+            lmr_base: DEFAULT_LMR_BASE,
+            lmr_divisor: DEFAULT_LMR_DIVISOR,
+            lmr_warmup: DEFAULT_LMR_WARMUP,
+            razor_margin: DEFAULT_RAZOR_MARGIN,
+            show_wdl: false,
+            wdl_a: WDL_A as u32,
+            wdl_b: WDL_B as u32,
+            score_white_pov: false,
+            pv_limit: 0,
+            show_currline: false,
+            analyse_mode: false,
+            limit_strength: false,
+            elo: DEFAULT_ELO,
+            skill_level: MAX_SKILL_LEVEL,
+            own_book: true,
+            book_file: String::new(),
+            move_cache_size: DEFAULT_MOVE_CACHE_SIZE,
+            log_file: String::new(),
+            syzygy_path: String::new(),
+        }
+    }
+}
+
+impl OptionSpec {
+    pub fn uci_declaration(&self) -> String {
+        match self.kind {
+            OptionKind::Check { default } => {
+                format!("option name {} type check default {}", self.name, default)
+            }
+            OptionKind::Spin { default, min, max } => format!(
+                "option name {} type spin default {} min {} max {}",
+                self.name, default, min, max
+            ),
+            OptionKind::Button => format!("option name {} type button", self.name),
+            OptionKind::String { default } => format!(
+                "option name {} type string default {}",
+                self.name,
+                if default.is_empty() { EMPTY } else { default }
+            ),
+        }
+    }
+
+    fn invalid(&self, value: &str) -> EngineError {
+        EngineError::InvalidOptionValue {
+            name: self.name.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    fn parse_check(&self, value: &str) -> Result<bool, EngineError> {
+        match value.to_ascii_lowercase().as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(self.invalid(value)),
+        }
+    }
+
+    fn parse_string(&self, value: &str) -> String {
+        match value {
+            EMPTY => String::new(),
+            value => value.to_string(),
+        }
+    }
+
+    fn parse_spin(&self, value: &str) -> Result<i64, EngineError> {
+        let OptionKind::Spin { min, max, .. } = self.kind else {
+            return Err(self.invalid(value));
+        };
+        match value.parse::<i64>() {
+            Ok(v) if (min..=max).contains(&v) => Ok(v),
+            _ => Err(self.invalid(value)),
+        }
+    }
+}
+
+impl Options {
+    pub fn wdl_model(&self) -> WdlModel {
+        WdlModel {
+            a: self.wdl_a as f64,
+            b: self.wdl_b as f64,
+        }
+    }
+
+    /// Applies `setoption name <name> value <value>`. Names are case-insensitive
+    /// per the UCI spec.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), EngineError> {
+        let spec = OPTIONS
+            .iter()
+            .find(|spec| spec.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| EngineError::UnknownOption(name.to_string()))?;
+        match spec.name {
+            "Hash" => self.hash_mb = spec.parse_spin(value)? as usize,
+            "Threads" => self.threads = spec.parse_spin(value)? as usize,
+            "MultiPV" => self.multi_pv = spec.parse_spin(value)? as usize,
+            "Move Overhead" => self.move_overhead_ms = spec.parse_spin(value)? as u64,
+            "Minimum Thinking Time" => self.minimum_thinking_ms = spec.parse_spin(value)? as u64,
+            "Slow Mover" => self.slow_mover = spec.parse_spin(value)? as u32,
+            "Seed" => self.seed = spec.parse_spin(value)? as u64,
+            "Deterministic" => self.deterministic = spec.parse_check(value)?,
+            "Aspiration Delta" => self.aspiration_delta = spec.parse_spin(value)? as i32,
+            "LMR Base" => self.lmr_base = spec.parse_spin(value)? as u32,
+            "LMR Divisor" => self.lmr_divisor = spec.parse_spin(value)? as u32,
+            "LMR Warmup" => self.lmr_warmup = spec.parse_spin(value)? as u32,
+            "Razor Margin" => self.razor_margin = spec.parse_spin(value)? as i32,
+            "UCI_ShowWDL" => self.show_wdl = spec.parse_check(value)?,
+            "WDL Model A" => self.wdl_a = spec.parse_spin(value)? as u32,
+            "WDL Model B" => self.wdl_b = spec.parse_spin(value)? as u32,
+            "UCI_ScoreWhitePov" => self.score_white_pov = spec.parse_check(value)?,
+            "PV Length" => self.pv_limit = spec.parse_spin(value)? as usize,
+            "UCI_ShowCurrLine" => self.show_currline = spec.parse_check(value)?,
+            "UCI_AnalyseMode" => self.analyse_mode = spec.parse_check(value)?,
+            "UCI_LimitStrength" => self.limit_strength = spec.parse_check(value)?,
+            "UCI_Elo" => self.elo = spec.parse_spin(value)? as u32,
+            "Skill Level" => self.skill_level = spec.parse_spin(value)? as u32,
+            "OwnBook" => self.own_book = spec.parse_check(value)?,
+            "BookFile" => self.book_file = spec.parse_string(value),
+            "Move Cache" => self.move_cache_size = spec.parse_spin(value)? as usize,
+            "LogFile" => self.log_file = spec.parse_string(value),
+            "SyzygyPath" => self.syzygy_path = spec.parse_string(value),
+            // Buttons carry no state; the engine acts on them.
+            "Clear Hash" => {}
+            _ => unreachable!("option {} has no setter", spec.name),
+        }
+        Ok(())
+    }
+
+    /// Current value of every option except buttons, in `OPTIONS` order and
+    /// written as `setoption` would take it.
+    pub fn values(&self) -> Vec<(&'static str, String)> {
+        let text = |value: &str| match value {
+            "" => EMPTY.to_string(),
+            value => value.to_string(),
+        };
+        OPTIONS
+            .iter()
+            .filter_map(|spec| {
+                let value = match spec.name {
+                    "Hash" => self.hash_mb.to_string(),
+                    "Threads" => self.threads.to_string(),
+                    "MultiPV" => self.multi_pv.to_string(),
+                    "Move Overhead" => self.move_overhead_ms.to_string(),
+                    "Minimum Thinking Time" => self.minimum_thinking_ms.to_string(),
+                    "Slow Mover" => self.slow_mover.to_string(),
+                    "Seed" => self.seed.to_string(),
+                    "Deterministic" => self.deterministic.to_string(),
+                    "Aspiration Delta" => self.aspiration_delta.to_string(),
+                    "LMR Base" => self.lmr_base.to_string(),
+                    "LMR Divisor" => self.lmr_divisor.to_string(),
+                    "LMR Warmup" => self.lmr_warmup.to_string(),
+                    "Razor Margin" => self.razor_margin.to_string(),
+                    "UCI_ShowWDL" => self.show_wdl.to_string(),
+                    "WDL Model A" => self.wdl_a.to_string(),
+                    "WDL Model B" => self.wdl_b.to_string(),
+                    "UCI_ScoreWhitePov" => self.score_white_pov.to_string(),
+                    "PV Length" => self.pv_limit.to_string(),
+                    "UCI_ShowCurrLine" => self.show_currline.to_string(),
+                    "UCI_AnalyseMode" => self.analyse_mode.to_string(),
+                    "UCI_LimitStrength" => self.limit_strength.to_string(),
+                    "UCI_Elo" => self.elo.to_string(),
+                    "Skill Level" => self.skill_level.to_string(),
+                    "OwnBook" => self.own_book.to_string(),
+                    "BookFile" => text(&self.book_file),
+                    "Move Cache" => self.move_cache_size.to_string(),
+                    "LogFile" => text(&self.log_file),
+                    "SyzygyPath" => text(&self.syzygy_path),
+                    "Clear Hash" => return None,
+                    _ => unreachable!("option {} has no getter", spec.name),
+                };
+                Some((spec.name, value))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_check_option() {
+        let mut options = Options::default();
+        options.set("uci_showwdl", "true").unwrap();
+        assert!(options.show_wdl);
+        assert!(matches!(
+            options.set("UCI_ShowWDL", "maybe"),
+            Err(EngineError::InvalidOptionValue { .. })
+        ));
+        assert!(matches!(
+            options.set("NoSuchOption", "1"),
+            Err(EngineError::UnknownOption(_))
+        ));
+    }
+
+    #[test]
+    fn test_set_spin_option_checks_bounds() {
+        let mut options = Options::default();
+        options.set("Threads", "4").unwrap();
+        assert_eq!(options.threads, 4);
+        assert!(options.set("Threads", "0").is_err());
+        assert!(options.set("Hash", "lots").is_err());
+        assert_eq!(options.hash_mb, DEFAULT_HASH_MB);
+        options.set("move overhead", "250").unwrap();
+        assert_eq!(options.move_overhead_ms, 250);
+        options.set("Slow Mover", "200").unwrap();
+        assert_eq!(options.slow_mover, 200);
+        assert!(options.set("Slow Mover", "5").is_err());
+    }
+
+    #[test]
+    fn test_search_tuning_options() {
+        let mut options = Options::default();
+        options.set("LMR Divisor", "10000").unwrap();
+        options.set("Aspiration Delta", "0").unwrap();
+        assert_eq!(options.lmr_divisor, 10_000);
+        assert_eq!(options.aspiration_delta, 0);
+        // A zero divisor would divide by zero.
+        assert!(options.set("LMR Divisor", "0").is_err());
+    }
+
+    #[test]
+    fn test_values_follow_setoption() {
+        let mut options = Options::default();
+        options.set("Hash", "64").unwrap();
+        options.set("LogFile", "/tmp/search log.jsonl").unwrap();
+        let values = options.values();
+        assert_eq!(values.len(), OPTIONS.len() - 1);
+        assert_eq!(values[0], ("Hash", String::from("64")));
+        assert!(values.contains(&("LogFile", String::from("/tmp/search log.jsonl"))));
+        assert!(values.contains(&("SyzygyPath", String::from("<empty>"))));
+        // Every value reads back through `set` unchanged.
+        let mut copy = Options::default();
+        for (name, value) in &values {
+            copy.set(name, value).unwrap();
+        }
+        assert_eq!(copy, options);
+    }
+}
@@ -0,0 +1,63 @@
+use crate::eval::{self, PIECE_VALUES};
+use crate::game::{Game, GameStatus};
+use crate::movegen;
+use crate::types::{Color, PieceKind};
+use serde::Serialize;
+
+/// Everything a frontend shows about a position, gathered in one call.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PositionReport {
+    pub fen: String,
+    pub side_to_move: Color,
+    pub legal_moves: usize,
+    pub in_check: bool,
+    pub status: GameStatus,
+    /// Static evaluation in centipawns, from the side to move's point of view.
+    pub eval_cp: i32,
+    /// Material in centipawns, White minus Black.
+    pub material_balance: i32,
+}
+
+impl PositionReport {
+    pub fn new(game: &Game) -> Self {
+        let board = game.board();
+        let us = board.side_to_move();
+        let material = board.material_count();
+        let material_balance = PieceKind::ALL
+            .iter()
+            .map(|&kind| {
+                let diff = material.count(Color::White, kind) as i32
+                    - material.count(Color::Black, kind) as i32;
+                diff * PIECE_VALUES[kind.index()]
+            })
+            .sum();
+        Self {
+            fen: board.to_fen(),
+            side_to_move: us,
+            legal_moves: movegen::generate_legal_moves(board).len(),
+            in_check: board.in_check(),
+            status: game.status(),
+            eval_cp: eval::evaluate(board),
+            material_balance,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+
+    #[test]
+    fn test_report_serializes() {
+        let report = PositionReport::new(&Game::default());
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["side_to_move"], "white");
+        assert_eq!(json["status"], "ongoing");
+        assert_eq!(json["legal_moves"], 20);
+
+        let mated = Board::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+        let json = serde_json::to_value(PositionReport::new(&Game::new(mated))).unwrap();
+        assert_eq!(json["status"]["checkmate"]["winner"], "white");
+    }
+}
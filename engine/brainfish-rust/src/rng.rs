@@ -0,0 +1,77 @@
+/// Seed used when none is configured.
+pub const DEFAULT_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Small xorshift64* generator. Everything random in the engine draws from
+/// one of these, so a fixed seed makes the engine reproducible.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED)
+    }
+}
+
+impl Rng {
+    /// A zero seed (which xorshift cannot use) falls back to `DEFAULT_SEED`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: if seed == 0 { DEFAULT_SEED } else { seed },
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform value in `0..n`; `n` must be non-zero.
+    pub fn below(&mut self, n: u64) -> u64 {
+        ((self.next_u64() as u128 * n as u128) >> 64) as u64
+    }
+
+    /// Index chosen with probability proportional to its weight, or `None`
+    /// when every weight is zero.
+    pub fn choose_weighted(&mut self, weights: &[u32]) -> Option<usize> {
+        let total: u64 = weights.iter().map(|&w| w as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut pick = self.below(total);
+        for (index, &weight) in weights.iter().enumerate() {
+            if pick < weight as u64 {
+                return Some(index);
+            }
+            pick -= weight as u64;
+        }
+        unreachable!("pick is below the total weight")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let mut c = Rng::new(43);
+        let first: Vec<u64> = (0..8).map(|_| a.next_u64()).collect();
+        assert_eq!(first, (0..8).map(|_| b.next_u64()).collect::<Vec<_>>());
+        assert_ne!(first, (0..8).map(|_| c.next_u64()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_choose_weighted_skips_zero_weights() {
+        let mut rng = Rng::default();
+        for _ in 0..100 {
+            assert_eq!(rng.choose_weighted(&[0, 5, 0]), Some(1));
+        }
+        assert_eq!(rng.choose_weighted(&[0, 0]), None);
+    }
+}
@@ -0,0 +1,141 @@
+use crate::board::Board;
+use crate::movegen;
+use crate::moves::Move;
+use crate::types::PieceKind;
+
+/// Standard Algebraic Notation for `mv`, which must be legal on `board`.
+pub fn to_san(board: &Board, mv: Move) -> String {
+    let piece = board.piece_at(mv.from).expect("SAN for a move from an empty square");
+    let legal = movegen::generate_legal_moves(board);
+
+    let mut san = String::new();
+    if piece.kind() == PieceKind::King && mv.from.file().abs_diff(mv.to.file()) == 2 {
+        san.push_str(if mv.to.file() == 6 { "O-O" } else { "O-O-O" });
+    } else {
+        let capture = board.piece_at(mv.to).is_some()
+            || (piece.kind() == PieceKind::Pawn && mv.from.file() != mv.to.file());
+        if piece.kind() == PieceKind::Pawn {
+            if capture {
+                san.push(mv.from.file_char());
+            }
+        } else {
+            san.push(piece.kind().to_san_char());
+            // Other pieces of the same kind that could also reach the target.
+            let rivals: Vec<Move> = legal
+                .iter()
+                .copied()
+                .filter(|other| {
+                    other.to == mv.to
+                        && other.from != mv.from
+                        && board.piece_at(other.from) == Some(piece)
+                })
+                .collect();
+            if !rivals.is_empty() {
+                let same_file = rivals.iter().any(|r| r.from.file() == mv.from.file());
+                let same_rank = rivals.iter().any(|r| r.from.rank() == mv.from.rank());
+                if !same_file {
+                    san.push(mv.from.file_char());
+                } else if !same_rank {
+                    san.push(mv.from.rank_char());
+                } else {
+                    san.push_str(&mv.from.to_algebraic());
+                }
+            }
+        }
+        if capture {
+            san.push('x');
+        }
+        san.push_str(&mv.to.to_algebraic());
+        if let Some(kind) = mv.promotion {
+            san.push('=');
+            san.push(kind.to_san_char());
+        }
+    }
+
+    let mut after = board.clone();
+    after.make_move(mv);
+    if after.in_check() {
+        if movegen::generate_legal_moves(&after).is_empty() {
+            san.push('#');
+        } else {
+            san.push('+');
+        }
+    }
+    san
+}
+
+/// The legal move on `board` written as `text` in SAN. Check marks,
+/// annotations (`!`, `?`), `x` and `=` are optional, and castling may be
+/// spelled with zeros.
+pub fn parse_san(board: &Board, text: &str) -> Option<Move> {
+    let wanted = normalize(&text.replace('0', "O"));
+    movegen::generate_legal_moves(board)
+        .into_iter()
+        .find(|&mv| normalize(&to_san(board, mv)) == wanted)
+}
+
+fn normalize(san: &str) -> String {
+    san.chars().filter(|c| !"+#!?x=".contains(*c)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn san(fen: &str, uci: &str) -> String {
+        let board = Board::from_fen(fen).unwrap();
+        let mv = movegen::generate_legal_moves(&board)
+            .into_iter()
+            .find(|mv| mv.to_uci() == uci)
+            .unwrap();
+        to_san(&board, mv)
+    }
+
+    #[test]
+    fn test_basic_san() {
+        let start = crate::board::START_FEN;
+        assert_eq!(san(start, "e2e4"), "e4");
+        assert_eq!(san(start, "g1f3"), "Nf3");
+        assert_eq!(
+            san("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1c1"),
+            "O-O-O"
+        );
+        assert_eq!(
+            san("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2", "e4d5"),
+            "exd5"
+        );
+        assert_eq!(san("8/4P3/8/8/8/8/k7/4K3 w - - 0 1", "e7e8q"), "e8=Q");
+    }
+
+    #[test]
+    fn test_disambiguation() {
+        // Knights on b1 and f1 can both reach d2.
+        assert_eq!(san("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1", "b1d2"), "Nbd2");
+        // Rooks on a1 and a5 share a file.
+        assert_eq!(san("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3"), "R1a3");
+        // Three queens where neither file nor rank alone is enough.
+        assert_eq!(
+            san("7k/8/8/8/Q1Q5/8/Q7/4K3 w - - 0 1", "a4b3"),
+            "Qa4b3"
+        );
+    }
+
+    #[test]
+    fn test_check_and_mate_suffixes() {
+        assert_eq!(san("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8"), "Ra8#");
+        assert_eq!(san("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1", "a1a8"), "Ra8+");
+    }
+
+    #[test]
+    fn test_parse_san() {
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/1p6/R3K2R b KQkq - 0 1").unwrap();
+        let uci = |text| parse_san(&board, text).map(|mv| mv.to_uci());
+        assert_eq!(uci("O-O").as_deref(), Some("e8g8"));
+        assert_eq!(uci("0-0-0").as_deref(), Some("e8c8"));
+        assert_eq!(uci("bxa1=Q+").as_deref(), Some("b2a1q"));
+        assert_eq!(uci("ba1N").as_deref(), Some("b2a1n"));
+        assert_eq!(uci("Rh1!?").as_deref(), Some("h8h1"));
+        assert_eq!(uci("Ke9"), None);
+        assert_eq!(uci("Qd4"), None);
+    }
+}
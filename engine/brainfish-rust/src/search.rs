@@ -0,0 +1,1635 @@
+use crate::bitboard::{self, squares};
+use crate::board::Board;
+use crate::eval;
+use crate::movegen;
+use crate::movepick::{self, History, MovePicker};
+use crate::moves::Move;
+use crate::tt::{self, Bound, TranspositionTable, TtEntry};
+use crate::types::PieceKind;
+use crate::wdl::WdlModel;
+use log::error;
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const INFINITY: i32 = 32_000;
+pub const MATE: i32 = 31_000;
+pub const MAX_PLY: usize = 128;
+/// Scores at least this far from zero are mates within `MAX_PLY`.
+pub const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+
+// Every score, window edge and negation stays within +-INFINITY, which must
+// also fit the 16 bits the hash table keeps.
+const _: () = assert!(MATE_BOUND > 0 && MATE < INFINITY && INFINITY <= i16::MAX as i32);
+// Static evaluations can never be mistaken for mates.
+const _: () = assert!(eval::EVAL_LIMIT < MATE_BOUND);
+const MAX_DEPTH: u32 = 64;
+
+// In deterministic mode wall-clock limits become node budgets at this rate.
+const DETERMINISTIC_NODES_PER_MS: u64 = 1_000;
+// Short searches don't announce each root move; the GUI would only flicker.
+const CURRMOVE_DELAY: Duration = Duration::from_secs(1);
+// The line under search is shown at most this often.
+const CURRLINE_INTERVAL: Duration = Duration::from_millis(500);
+// A forced move is only searched deep enough to give it a score and spot a
+// short mate.
+const SINGLE_MOVE_DEPTH: u32 = 4;
+// Iterations before this are cheap enough to search with a full window.
+const ASPIRATION_MIN_DEPTH: u32 = 4;
+// Late move reductions only start after this many moves, at this depth.
+const LMR_MIN_MOVES: usize = 3;
+const LMR_MIN_DEPTH: u32 = 3;
+// Each this much history takes a ply off a quiet move's reduction, so at
+// most half a ply, and each this much negative history adds one, up to
+// `LMR_MAX_BAD_PLIES`. A bad record says more about a move than a good one.
+const LMR_GOOD_HISTORY: i32 = 2 * movepick::HISTORY_MAX;
+const LMR_BAD_HISTORY: i32 = 512;
+const LMR_MAX_BAD_PLIES: i32 = 2;
+// PV nodes at least this deep without a hash move get one from a search
+// `IID_REDUCTION` plies shallower.
+const IID_MIN_DEPTH: u32 = 5;
+const IID_REDUCTION: u32 = 2;
+// Razoring only looks this close to the horizon.
+const RAZOR_MAX_DEPTH: u32 = 2;
+
+/// Half-width of the first aspiration window, in centipawns.
+pub const DEFAULT_ASPIRATION_DELTA: i32 = 25;
+/// Late move reduction `base + ln(depth) * ln(moves) / divisor`, both in
+/// hundredths of a ply.
+pub const DEFAULT_LMR_BASE: u32 = 75;
+pub const DEFAULT_LMR_DIVISOR: u32 = 225;
+/// Percent of the usual late move reductions made while the hash table and
+/// history are still cold.
+pub const DEFAULT_LMR_WARMUP: u32 = 50;
+/// Centipawns per ply of depth that the static evaluation may trail alpha
+/// by before a shallow node is razored.
+pub const DEFAULT_RAZOR_MARGIN: i32 = 200;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+    /// Keep deepening until stopped, even after finding a mate.
+    pub infinite: bool,
+    /// Stop as soon as a mate in at most this many moves is found.
+    pub mate: Option<u32>,
+    /// A time limit that may be set while the search runs.
+    pub deadline: Option<Arc<Deadline>>,
+}
+
+/// A wall-clock limit that can be set after a search has started, as
+/// `ponderhit` does for a search begun without one. It applies in
+/// deterministic mode too. Deadlines are only equal to themselves.
+#[derive(Debug)]
+pub struct Deadline {
+    created: Instant,
+    // Milliseconds after `created`; `u64::MAX` while unset.
+    millis: AtomicU64,
+}
+
+impl Deadline {
+    pub fn new() -> Self {
+        Self {
+            created: Instant::now(),
+            millis: AtomicU64::new(u64::MAX),
+        }
+    }
+
+    /// Ends the search `after` from now.
+    pub fn set(&self, after: Duration) {
+        let millis = (self.created.elapsed() + after).as_millis() as u64;
+        self.millis.store(millis, Ordering::Relaxed);
+    }
+
+    fn passed(&self) -> bool {
+        self.created.elapsed().as_millis() as u64 >= self.millis.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for Deadline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialEq for Deadline {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for Deadline {}
+
+/// Engine settings that shape how a search runs, as opposed to when it stops.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchConfig {
+    pub threads: usize,
+    /// Single-threaded and no wall-clock cutoffs, so the same position and
+    /// limits always produce the same result from an empty hash table.
+    pub deterministic: bool,
+    /// Centipawns either side of the last score; 0 searches full windows.
+    pub aspiration_delta: i32,
+    /// Late move reduction terms in hundredths of a ply; see `DEFAULT_LMR_BASE`.
+    pub lmr_base: u32,
+    pub lmr_divisor: u32,
+    /// Share of the usual late move reductions to make, in percent.
+    pub lmr_percent: u32,
+    /// See `DEFAULT_RAZOR_MARGIN`; 0 turns razoring off.
+    pub razor_margin: i32,
+    /// Analysis over speed: no forward pruning or reductions.
+    pub analyse_mode: bool,
+    /// Internal iterative deepening at PV nodes with no hash move.
+    pub iid: bool,
+    /// Late quiet moves with a poor history are reduced more, good ones less.
+    pub history_reductions: bool,
+    /// Best lines to report; only the first decides the move.
+    pub multi_pv: usize,
+    /// Report the line being searched now and then with `CurrentLine`.
+    pub currline: bool,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            threads: 1,
+            deterministic: false,
+            aspiration_delta: DEFAULT_ASPIRATION_DELTA,
+            lmr_base: DEFAULT_LMR_BASE,
+            lmr_divisor: DEFAULT_LMR_DIVISOR,
+            lmr_percent: 100,
+            razor_margin: DEFAULT_RAZOR_MARGIN,
+            analyse_mode: false,
+            iid: true,
+            history_reductions: true,
+            multi_pv: 1,
+            currline: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    pub score: Score,
+    pub depth: u32,
+    pub nodes: u64,
+    /// Hash table usage in permille when the iteration finished.
+    pub hashfull: u32,
+    /// Hash probes and hits in the main thread's search tree.
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    pub pv: Vec<Move>,
+    /// Lines 2 onwards of a MultiPV search, best first.
+    pub extra_lines: Vec<PvLine>,
+}
+
+impl SearchResult {
+    /// This result with every line's score from the other side's point of view.
+    pub fn negated(&self) -> Self {
+        let mut result = self.clone();
+        result.score = result.score.negated();
+        for line in &mut result.extra_lines {
+            line.score = line.score.negated();
+        }
+        result
+    }
+}
+
+/// A line other than the principal one, with its score.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PvLine {
+    pub score: Score,
+    pub pv: Vec<Move>,
+}
+
+/// Progress reported by `search` as it runs.
+#[derive(Clone, Copy, Debug)]
+pub enum SearchEvent<'a> {
+    /// An iteration finished with this result.
+    Iteration {
+        result: &'a SearchResult,
+        elapsed: Duration,
+    },
+    /// The root started on its `number`th move (counting from 1) at `depth`.
+    CurrentMove {
+        depth: u32,
+        mv: Move,
+        number: usize,
+        elapsed: Duration,
+    },
+    /// The moves from the root to the node being searched, sent every so
+    /// often when `SearchConfig::currline` is set.
+    CurrentLine { line: &'a [Move] },
+}
+
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() >= MATE_BOUND
+}
+
+/// A score as reported to the outside, from the side to move's point of view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Score {
+    /// Centipawns.
+    Cp(i32),
+    /// Moves (not plies) until mate; negative when the side to move is mated.
+    Mate(i32),
+}
+
+impl Default for Score {
+    fn default() -> Self {
+        Score::Cp(0)
+    }
+}
+
+impl Score {
+    /// Converts a search score, where mate `n` plies away is `MATE - n`.
+    pub fn from_internal(score: i32) -> Self {
+        if !is_mate_score(score) {
+            return Score::Cp(score);
+        }
+        let moves = (MATE - score.abs() + 1) / 2;
+        Score::Mate(if score > 0 { moves } else { -moves })
+    }
+
+    /// Back to search units, taking the shortest mate the move count allows.
+    /// Out-of-range values saturate rather than overflow.
+    pub fn to_internal(self) -> i32 {
+        let max_moves = MAX_PLY as i32 / 2;
+        match self {
+            Score::Cp(cp) => cp.clamp(-MATE_BOUND + 1, MATE_BOUND - 1),
+            Score::Mate(moves) if moves > 0 => MATE - (2 * moves.min(max_moves) - 1),
+            Score::Mate(moves) => -MATE + 2 * moves.unsigned_abs().min(max_moves as u32) as i32,
+        }
+    }
+
+    /// The same score from the other side's point of view.
+    pub fn negated(self) -> Self {
+        match self {
+            Score::Cp(cp) => Score::Cp(-cp),
+            Score::Mate(moves) => Score::Mate(-moves),
+        }
+    }
+
+    /// This score, found after a move, as seen by the side that made it.
+    pub fn for_parent(self) -> Self {
+        let score = self.to_internal();
+        if is_mate_score(score) {
+            Score::from_internal(-score + score.signum())
+        } else {
+            Score::Cp(-score)
+        }
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Score::Cp(cp) => write!(f, "cp {}", cp),
+            Score::Mate(moves) => write!(f, "mate {}", moves),
+        }
+    }
+}
+
+/// Nodes per second over `elapsed`, timed in whole milliseconds with a 1 ms
+/// floor so an instant search doesn't divide by zero. Saturates instead of
+/// overflowing.
+pub fn nodes_per_second(nodes: u64, elapsed: Duration) -> u64 {
+    let millis = elapsed.as_millis().max(1);
+    u64::try_from(nodes as u128 * 1000 / millis).unwrap_or(u64::MAX)
+}
+
+/// Renders a search result's principal line as a UCI `info` line. It is
+/// tagged `multipv 1` when the result has further lines, and shows `wdl`
+/// figures from the model if one is given.
+pub fn format_info(result: &SearchResult, elapsed: Duration, wdl: Option<WdlModel>) -> String {
+    let number = (!result.extra_lines.is_empty()).then_some(1);
+    format_line(result, number, result.score, &result.pv, elapsed, wdl)
+}
+
+/// One `info` line per line of the result, the principal one first.
+pub fn format_all_lines(
+    result: &SearchResult,
+    elapsed: Duration,
+    wdl: Option<WdlModel>,
+) -> Vec<String> {
+    let mut lines = vec![format_info(result, elapsed, wdl)];
+    for (i, line) in result.extra_lines.iter().enumerate() {
+        lines.push(format_line(result, Some(i + 2), line.score, &line.pv, elapsed, wdl));
+    }
+    lines
+}
+
+fn format_line(
+    result: &SearchResult,
+    number: Option<usize>,
+    score: Score,
+    pv: &[Move],
+    elapsed: Duration,
+    wdl: Option<WdlModel>,
+) -> String {
+    let multipv = number.map_or(String::new(), |n| format!(" multipv {}", n));
+    let mut score_text = score.to_string();
+    if let Some(model) = wdl {
+        let (win, draw, loss) = model.wdl(score.to_internal());
+        score_text.push_str(&format!(" wdl {} {} {}", win, draw, loss));
+    }
+    let millis = elapsed.as_millis() as u64;
+    let nps = nodes_per_second(result.nodes, elapsed);
+    let pv: Vec<String> = pv.iter().map(|mv| mv.to_uci()).collect();
+    format!(
+        "info depth {}{} score {} nodes {} nps {} hashfull {} time {} pv {}",
+        result.depth,
+        multipv,
+        score_text,
+        result.nodes,
+        nps,
+        result.hashfull,
+        millis,
+        pv.join(" ")
+    )
+}
+
+/// The UCI lines for an event; often none.
+pub fn format_event(event: SearchEvent<'_>, wdl: Option<WdlModel>) -> Vec<String> {
+    match event {
+        SearchEvent::Iteration { result, elapsed } => format_all_lines(result, elapsed, wdl),
+        SearchEvent::CurrentMove {
+            depth,
+            mv,
+            number,
+            elapsed,
+        } => (elapsed >= CURRMOVE_DELAY)
+            .then(|| format!("info depth {} currmove {} currmovenumber {}", depth, mv, number))
+            .into_iter()
+            .collect(),
+        SearchEvent::CurrentLine { line } => {
+            let moves: Vec<String> = line.iter().map(|mv| mv.to_uci()).collect();
+            vec![format!("info currline {}", moves.join(" "))]
+        }
+    }
+}
+
+// Called with the moves from the root to a node being searched.
+type LineReport<'a> = dyn Fn(&[Move]) + 'a;
+
+// Storage each ply of the search reuses from node to node, so that once the
+// vectors have grown the hot loop stops allocating.
+#[derive(Clone, Debug, Default)]
+struct PlyBuffers {
+    moves: Vec<Move>,
+    quiets_tried: Vec<Move>,
+}
+
+struct Searcher<'a> {
+    board: Board,
+    tt: &'a TranspositionTable,
+    config: &'a SearchConfig,
+    stop: &'a AtomicBool,
+    // Set once the main thread is done, so helpers wind down with it.
+    main_done: &'a AtomicBool,
+    // Nodes searched by helper threads, flushed in batches.
+    helper_nodes: &'a AtomicU64,
+    is_main: bool,
+    deadline: Option<Instant>,
+    shared_deadline: Option<&'a Deadline>,
+    node_limit: Option<u64>,
+    nodes: u64,
+    flushed_nodes: u64,
+    aborted: bool,
+    tt_probes: u64,
+    tt_hits: u64,
+    // Triangular PV table: `pv[ply]` is the best line found from `ply` on.
+    pv: Vec<Vec<Move>>,
+    // Two quiet moves per ply that recently caused a cutoff there.
+    killers: Vec<[Option<Move>; 2]>,
+    history: History,
+    // The first `ply` moves lead from the root to the node searched at `ply`.
+    line: Vec<Move>,
+    // Hears the current line now and then; only the main thread has one.
+    on_line: Option<&'a LineReport<'a>>,
+    next_line_report: Instant,
+    buffers: Vec<PlyBuffers>,
+    // Off only to check that reuse changes nothing.
+    reuse_buffers: bool,
+}
+
+impl Searcher<'_> {
+    fn should_stop(&mut self) -> bool {
+        if self.aborted {
+            return true;
+        }
+        if self.nodes & 1023 == 0 {
+            if !self.is_main {
+                self.flush_nodes();
+            }
+            if self.stop.load(Ordering::Relaxed) || self.main_done.load(Ordering::Relaxed) {
+                self.aborted = true;
+            }
+            if let Some(deadline) = self.deadline {
+                if Instant::now() >= deadline {
+                    self.aborted = true;
+                }
+            }
+            if self.shared_deadline.is_some_and(Deadline::passed) {
+                self.aborted = true;
+            }
+        }
+        if let Some(limit) = self.node_limit {
+            if self.total_nodes() >= limit {
+                self.aborted = true;
+            }
+        }
+        self.aborted
+    }
+
+    /// Passes the line to the node at `ply` to `on_line`, if it is time to.
+    fn report_line(&mut self, ply: usize) {
+        let Some(on_line) = self.on_line else {
+            return;
+        };
+        if self.nodes & 1023 != 0 {
+            return;
+        }
+        let now = Instant::now();
+        if now >= self.next_line_report {
+            self.next_line_report = now + CURRLINE_INTERVAL;
+            on_line(&self.line[..ply]);
+        }
+    }
+
+    /// The buffers for `ply`, to be handed back with `return_buffers`.
+    fn take_buffers(&mut self, ply: usize) -> PlyBuffers {
+        std::mem::take(&mut self.buffers[ply])
+    }
+
+    fn return_buffers(&mut self, ply: usize, buffers: PlyBuffers) {
+        if self.reuse_buffers {
+            self.buffers[ply] = buffers;
+        }
+    }
+
+    fn flush_nodes(&mut self) {
+        self.helper_nodes
+            .fetch_add(self.nodes - self.flushed_nodes, Ordering::Relaxed);
+        self.flushed_nodes = self.nodes;
+    }
+
+    fn total_nodes(&self) -> u64 {
+        if self.is_main {
+            self.nodes + self.helper_nodes.load(Ordering::Relaxed)
+        } else {
+            self.nodes
+        }
+    }
+
+    /// Sorts `moves` best-first. `tt_move` leads only if it is one of `moves`:
+    /// a hash collision can hand back a move that is illegal here.
+    fn order_moves(&self, moves: &mut [Move], tt_move: Option<Move>) {
+        let tt_move = tt_move.filter(|mv| moves.contains(mv));
+        moves.sort_by_key(|&mv| {
+            if Some(mv) == tt_move {
+                i32::MIN
+            } else {
+                -movepick::mvv_lva(&self.board, mv)
+            }
+        });
+    }
+
+    /// Plies cut from the `number`th move's search at `depth`, always leaving
+    /// at least one. Quiet `mv` is reduced less if its history is good and
+    /// more if it is bad.
+    fn reduction(&self, mv: Move, depth: u32, number: usize) -> u32 {
+        if depth < LMR_MIN_DEPTH || self.config.analyse_mode {
+            return 0;
+        }
+        let config = self.config;
+        let mut hundredths = config.lmr_base as f64
+            + (depth as f64).ln() * (number as f64).ln() * 10_000.0
+                / config.lmr_divisor.max(1) as f64;
+        if config.history_reductions {
+            let history = self.history.score(mv);
+            let per_ply = if history > 0 { LMR_GOOD_HISTORY } else { LMR_BAD_HISTORY };
+            hundredths -= (history * 100 / per_ply).max(-100 * LMR_MAX_BAD_PLIES) as f64;
+        }
+        hundredths *= config.lmr_percent as f64 / 100.0;
+        (hundredths.max(0.0) / 100.0).min((depth - 2) as f64) as u32
+    }
+
+    fn in_check(&self) -> bool {
+        self.board.in_check()
+    }
+
+    /// Whether the side to move, not in check, has no legal move. Most
+    /// positions are ruled out by a safe king step before generating moves.
+    fn is_stalemate(&mut self, ply: usize) -> bool {
+        let board = &self.board;
+        let us = board.side_to_move();
+        let king = board.king_square(us);
+        // Without the king, so squares behind it on a slider's line count as attacked.
+        let occupancy = board.occupancy() & !king.bitboard();
+        let enemies = board.color_occupancy(us.opposite());
+        let king_can_move = squares(bitboard::king_attacks(king) & !board.color_occupancy(us))
+            .any(|sq| board.attackers_to(sq, occupancy) & enemies == 0);
+        !king_can_move && !self.has_legal_move(ply)
+    }
+
+    fn has_legal_move(&mut self, ply: usize) -> bool {
+        let mut buffers = self.take_buffers(ply);
+        movegen::generate_quiets_into(&self.board, &mut buffers.moves);
+        if buffers.moves.is_empty() {
+            movegen::generate_captures_into(&self.board, &mut buffers.moves);
+        }
+        let any = !buffers.moves.is_empty();
+        self.return_buffers(ply, buffers);
+        any
+    }
+
+    /// Score for a position without legal moves: mated if the king is
+    /// attacked (`ply` plies from the root), stalemate otherwise.
+    fn no_moves_score(&self, ply: usize) -> i32 {
+        if self.in_check() {
+            -MATE + ply as i32
+        } else {
+            0
+        }
+    }
+
+    /// `mv` followed by the child's line becomes the line at `ply`.
+    fn update_pv(&mut self, ply: usize, mv: Move) {
+        let (line, rest) = self.pv[ply..].split_first_mut().expect("ply within the PV table");
+        line.clear();
+        line.push(mv);
+        line.extend_from_slice(&rest[0]);
+    }
+
+    fn negamax(&mut self, depth: u32, alpha: i32, beta: i32, ply: usize) -> i32 {
+        self.pv[ply].clear();
+        self.nodes += 1;
+        if self.should_stop() {
+            return 0;
+        }
+        self.report_line(ply);
+        if self.board.material_count().is_insufficient() {
+            return 0;
+        }
+        if self.board.halfmove_clock() >= 100 {
+            // A mate on the hundredth halfmove still counts.
+            if self.in_check() && movegen::generate_legal_moves(&self.board).is_empty() {
+                return -MATE + ply as i32;
+            }
+            return 0;
+        }
+
+        // Mate distance pruning: no line from here beats mating on the next
+        // move or avoids being mated right now, so a window outside that is moot.
+        let mut alpha = alpha.max(-MATE + ply as i32);
+        let beta = beta.min(MATE - ply as i32 - 1);
+        if alpha >= beta {
+            return alpha;
+        }
+
+        let in_check = self.in_check();
+        let depth = if in_check { depth + 1 } else { depth };
+        if depth == 0 || ply >= MAX_PLY {
+            // Quiescence would stand pat on a stalemate as if material mattered.
+            if !in_check && self.is_stalemate(ply) {
+                return 0;
+            }
+            return self.quiescence(alpha, beta, ply);
+        }
+
+        // Cutoffs in PV nodes would leave the PV table with a truncated line.
+        let pv_node = beta - alpha > 1;
+        let key = self.board.hash();
+        let mut tt_move = None;
+        self.tt_probes += 1;
+        if let Some(entry) = self.tt.probe(key) {
+            self.tt_hits += 1;
+            tt_move = entry.best_move;
+            if entry.depth >= depth && !pv_node {
+                let score = tt::score_from_tt(entry.score, ply);
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {}
+                }
+            }
+        }
+
+        if !pv_node && !in_check {
+            if let Some(score) = self.razor(depth, alpha, beta, ply) {
+                return score;
+            }
+        }
+
+        let mut tt_move = tt_move.filter(|&mv| movegen::is_legal(&self.board, mv));
+        if tt_move.is_none() && pv_node && depth >= IID_MIN_DEPTH && self.config.iid {
+            tt_move = self.iid_move(depth, alpha, beta, ply);
+        }
+        let PlyBuffers {
+            moves,
+            // Quiet moves that failed to cut, to be debited if a later one does.
+            mut quiets_tried,
+        } = self.take_buffers(ply);
+        quiets_tried.clear();
+        let mut picker = MovePicker::with_buffer(&self.board, tt_move, self.killers[ply], moves);
+
+        let original_alpha = alpha;
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        let mut searched = 0;
+        while let Some(mv) = picker.next(&self.board, &self.history) {
+            let i = searched;
+            searched += 1;
+            let quiet = movepick::is_quiet(&self.board, mv);
+            self.line.truncate(ply);
+            self.line.push(mv);
+            let undo = self.board.make_move(mv);
+            let reduction = if quiet && !in_check && i >= LMR_MIN_MOVES && !self.in_check() {
+                self.reduction(mv, depth, i + 1)
+            } else {
+                0
+            };
+            let mut score;
+            if reduction > 0 {
+                // A late quiet move only gets a full search if a shallower
+                // null-window one says it might beat alpha.
+                score = -self.negamax(depth - 1 - reduction, -alpha - 1, -alpha, ply + 1);
+                if score > alpha && !self.aborted {
+                    score = -self.negamax(depth - 1, -beta, -alpha, ply + 1);
+                }
+            } else {
+                score = -self.negamax(depth - 1, -beta, -alpha, ply + 1);
+            }
+            self.board.unmake_move(mv, &undo);
+            if self.aborted {
+                break;
+            }
+            if score > best {
+                best = score;
+                best_move = Some(mv);
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(ply, mv);
+                    if alpha >= beta {
+                        if quiet {
+                            self.record_cutoff(mv, depth, ply, &quiets_tried);
+                        }
+                        break;
+                    }
+                }
+            }
+            if quiet {
+                quiets_tried.push(mv);
+            }
+        }
+        let moves = picker.into_buffer();
+        self.return_buffers(ply, PlyBuffers { moves, quiets_tried });
+        if self.aborted {
+            return 0;
+        }
+        if searched == 0 {
+            return self.no_moves_score(ply);
+        }
+
+        let bound = if best >= beta {
+            Bound::Lower
+        } else if best > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(
+            key,
+            TtEntry {
+                best_move,
+                score: tt::score_to_tt(best, ply),
+                depth,
+                bound,
+            },
+        );
+        best
+    }
+
+    // Razoring: just above the horizon, a static evaluation far below alpha
+    // is taken at its word if quiescence agrees it fails low.
+    fn razor(&mut self, depth: u32, alpha: i32, beta: i32, ply: usize) -> Option<i32> {
+        let margin = self.config.razor_margin;
+        if margin <= 0 || depth > RAZOR_MAX_DEPTH || self.config.analyse_mode {
+            return None;
+        }
+        if is_mate_score(alpha) || eval::evaluate(&self.board) + margin * depth as i32 > alpha {
+            return None;
+        }
+        let score = self.quiescence(alpha, beta, ply);
+        (score <= alpha && !self.aborted).then_some(score)
+    }
+
+    // Internal iterative deepening: with no hash move to try first, a
+    // shallower search of this node finds one. Only a legal move is kept.
+    fn iid_move(&mut self, depth: u32, alpha: i32, beta: i32, ply: usize) -> Option<Move> {
+        self.negamax(depth - IID_REDUCTION, alpha, beta, ply);
+        // The shallow line must not pass for this node's PV.
+        self.pv[ply].clear();
+        if self.aborted {
+            return None;
+        }
+        self.tt
+            .probe(self.board.hash())
+            .and_then(|entry| entry.best_move)
+            .filter(|&mv| movegen::is_legal(&self.board, mv))
+    }
+
+    // A quiet move that refuted a line becomes a killer at this ply and earns
+    // history everywhere; the quiet moves tried before it lose some.
+    fn record_cutoff(&mut self, mv: Move, depth: u32, ply: usize, tried: &[Move]) {
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(mv) {
+            killers[1] = killers[0];
+            killers[0] = Some(mv);
+        }
+        self.history.reward(mv, depth);
+        for &failed in tried {
+            self.history.penalize(failed, depth);
+        }
+    }
+
+    fn quiescence(&mut self, mut alpha: i32, beta: i32, ply: usize) -> i32 {
+        self.nodes += 1;
+        if self.should_stop() {
+            return 0;
+        }
+        self.report_line(ply);
+
+        let stand_pat = eval::evaluate(&self.board);
+        if stand_pat >= beta || ply >= MAX_PLY {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut buffers = self.take_buffers(ply);
+        let moves = &mut buffers.moves;
+        movegen::generate_captures_into(&self.board, moves);
+        // Underpromotions are left to the main search.
+        moves.retain(|&mv| matches!(mv.promotion, None | Some(PieceKind::Queen)));
+        self.order_moves(moves, None);
+
+        for &mv in moves.iter() {
+            self.line.truncate(ply);
+            self.line.push(mv);
+            let undo = self.board.make_move(mv);
+            let score = -self.quiescence(-beta, -alpha, ply + 1);
+            self.board.unmake_move(mv, &undo);
+            if self.aborted {
+                break;
+            }
+            if score > alpha {
+                alpha = score;
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+        self.return_buffers(ply, buffers);
+        if self.aborted {
+            return 0;
+        }
+        alpha
+    }
+
+    /// Searches every root move but `excluded` to `depth` within
+    /// `(alpha, beta)`, trying `previous_best` first. A score outside the
+    /// window is only a bound. `on_move` hears about each root move as it is
+    /// started.
+    fn search_root(
+        &mut self,
+        depth: u32,
+        (alpha, beta): (i32, i32),
+        previous_best: Option<Move>,
+        excluded: &[Move],
+        on_move: &mut dyn FnMut(Move, usize),
+    ) -> Option<(Move, i32)> {
+        let mut moves = movegen::generate_legal_moves(&self.board);
+        moves.retain(|mv| !excluded.contains(mv));
+        self.order_moves(&mut moves, previous_best);
+
+        self.nodes += 1;
+        let original_alpha = alpha;
+        let mut alpha = alpha;
+        let mut best: Option<(Move, i32)> = None;
+        for (i, mv) in moves.into_iter().enumerate() {
+            on_move(mv, i + 1);
+            // Equal scores go to the move first in UCI order, whatever order
+            // the moves were searched in. Such a move gets a window one point
+            // wider so that a tie shows as an exact score.
+            let wins_ties = best.is_some_and(|(best_move, _)| mv.to_uci() < best_move.to_uci());
+            let floor = if wins_ties { (alpha - 1).max(-INFINITY) } else { alpha };
+            self.line.truncate(0);
+            self.line.push(mv);
+            let undo = self.board.make_move(mv);
+            let score = -self.negamax(depth - 1, -beta, -floor, 1);
+            self.board.unmake_move(mv, &undo);
+            if self.aborted {
+                return None;
+            }
+            if best.is_none_or(|(_, best_score)| {
+                score > best_score || (wins_ties && score == best_score && score > floor)
+            }) {
+                best = Some((mv, score));
+                self.update_pv(0, mv);
+                alpha = alpha.max(score);
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+
+        // The best of some moves is not the position's best move.
+        let exact = best.filter(|&(_, score)| {
+            excluded.is_empty() && score > original_alpha && score < beta
+        });
+        if let Some((mv, score)) = exact {
+            self.tt.store(
+                self.board.hash(),
+                TtEntry {
+                    best_move: Some(mv),
+                    score,
+                    depth,
+                    bound: Bound::Exact,
+                },
+            );
+        }
+        best
+    }
+
+    /// Searches the root to `depth` in a narrow window around the last
+    /// iteration's score, widening it whenever the score falls outside.
+    fn aspiration_search(
+        &mut self,
+        depth: u32,
+        previous: &SearchResult,
+        on_move: &mut dyn FnMut(Move, usize),
+    ) -> Option<(Move, i32)> {
+        let previous_best = previous.best_move;
+        let mut delta = self.config.aspiration_delta;
+        let center = previous.score.to_internal();
+        if delta <= 0 || depth < ASPIRATION_MIN_DEPTH || is_mate_score(center) {
+            return self.search_root(depth, (-INFINITY, INFINITY), previous_best, &[], on_move);
+        }
+        let (mut alpha, mut beta) = (center - delta, center + delta);
+        loop {
+            let (mv, score) =
+                self.search_root(depth, (alpha, beta), previous_best, &[], on_move)?;
+            if score <= alpha {
+                alpha = (score - delta).max(-INFINITY);
+            } else if score >= beta {
+                beta = (score + delta).min(INFINITY);
+            } else {
+                return Some((mv, score));
+            }
+            delta = delta.saturating_mul(2);
+        }
+    }
+
+    /// Lines 2 to `count` at `depth`, each the best full-window line without
+    /// `best` and the lines before it. `None` if the search was stopped.
+    fn extra_lines(
+        &mut self,
+        depth: u32,
+        best: Move,
+        count: usize,
+        previous: &[PvLine],
+        on_move: &mut dyn FnMut(Move, usize),
+    ) -> Option<Vec<PvLine>> {
+        let mut excluded = vec![best];
+        let mut lines = Vec::new();
+        while excluded.len() < count {
+            let previous_best = previous.get(lines.len()).and_then(|line| line.pv.first());
+            let window = (-INFINITY, INFINITY);
+            let (mv, score) =
+                self.search_root(depth, window, previous_best.copied(), &excluded, on_move)?;
+            excluded.push(mv);
+            lines.push(PvLine {
+                score: Score::from_internal(score),
+                pv: self.pv[0].clone(),
+            });
+        }
+        Some(lines)
+    }
+}
+
+/// Iterative-deepening search, reporting progress through `on_event`.
+/// Setting `stop` ends the search with the last completed iteration.
+pub fn search(
+    board: &Board,
+    limits: &SearchLimits,
+    config: &SearchConfig,
+    tt: &TranspositionTable,
+    stop: &AtomicBool,
+    on_event: impl FnMut(SearchEvent<'_>),
+) -> SearchResult {
+    run_search(board, limits, config, tt, stop, on_event, true)
+}
+
+/// As `search`, but allocating fresh move lists at every node the way it
+/// did before they were reused. Only for measuring what reuse saves.
+#[doc(hidden)]
+pub fn search_allocating(
+    board: &Board,
+    limits: &SearchLimits,
+    config: &SearchConfig,
+    tt: &TranspositionTable,
+    stop: &AtomicBool,
+    on_event: impl FnMut(SearchEvent<'_>),
+) -> SearchResult {
+    run_search(board, limits, config, tt, stop, on_event, false)
+}
+
+// `search`, with `reuse_buffers` off allocating fresh move lists at every node.
+fn run_search(
+    board: &Board,
+    limits: &SearchLimits,
+    config: &SearchConfig,
+    tt: &TranspositionTable,
+    stop: &AtomicBool,
+    on_event: impl FnMut(SearchEvent<'_>),
+    reuse_buffers: bool,
+) -> SearchResult {
+    // A broken board would panic deep in the search, perhaps across FFI.
+    if let Err(e) = board.check_consistency() {
+        error!("Not searching a corrupt board: {}", e);
+        return SearchResult::default();
+    }
+    let start = Instant::now();
+    let helper_nodes = AtomicU64::new(0);
+    let main_done = AtomicBool::new(false);
+
+    let (threads, deadline, node_limit) = if config.deterministic {
+        let budget = limits
+            .movetime
+            .map(|t| (t.as_millis() as u64).max(1) * DETERMINISTIC_NODES_PER_MS);
+        (1, None, limits.nodes.or(budget))
+    } else {
+        (
+            config.threads.max(1),
+            limits.movetime.map(|t| start + t),
+            limits.nodes,
+        )
+    };
+    tt.new_search();
+
+    let new_searcher = |is_main: bool| Searcher {
+        board: board.clone(),
+        tt,
+        config,
+        stop,
+        main_done: &main_done,
+        helper_nodes: &helper_nodes,
+        is_main,
+        deadline,
+        shared_deadline: limits.deadline.as_deref(),
+        node_limit,
+        nodes: 0,
+        flushed_nodes: 0,
+        aborted: false,
+        tt_probes: 0,
+        tt_hits: 0,
+        pv: vec![Vec::new(); MAX_PLY + 1],
+        killers: vec![[None; 2]; MAX_PLY + 1],
+        history: History::default(),
+        line: Vec::with_capacity(MAX_PLY + 1),
+        on_line: None,
+        next_line_report: start,
+        buffers: vec![PlyBuffers::default(); MAX_PLY + 1],
+        reuse_buffers,
+    };
+
+    let mut result = SearchResult::default();
+    let root_moves = movegen::generate_legal_moves(board);
+    if root_moves.is_empty() {
+        result.score = Score::from_internal(new_searcher(true).no_moves_score(0));
+        return result;
+    }
+    let max_depth = match (limits.depth, limits.mate) {
+        (Some(depth), _) if !limits.infinite => depth.clamp(1, MAX_DEPTH),
+        // A mate in `moves` needs at most twice as many plies to find.
+        (None, Some(moves)) if !limits.infinite => moves.saturating_mul(2).clamp(1, MAX_DEPTH),
+        _ if root_moves.len() == 1 && !limits.infinite => SINGLE_MOVE_DEPTH,
+        _ => MAX_DEPTH,
+    };
+
+    // Reports come from inside the main searcher as well as from here.
+    let on_event = RefCell::new(on_event);
+    let on_line = |line: &[Move]| (on_event.borrow_mut())(SearchEvent::CurrentLine { line });
+
+    let main_nodes = std::thread::scope(|scope| {
+        // Lazy SMP: helpers search the same tree, half of them one ply ahead,
+        // and share what they learn only through the hash table.
+        let new_searcher = &new_searcher;
+        for id in 1..threads {
+            scope.spawn(move || {
+                let mut helper = new_searcher(false);
+                let mut best = None;
+                for depth in (1 + id as u32 % 2)..=max_depth {
+                    let window = (-INFINITY, INFINITY);
+                    match helper.search_root(depth, window, best, &[], &mut |_, _| {}) {
+                        Some((mv, _)) => best = Some(mv),
+                        None => break,
+                    }
+                }
+                helper.flush_nodes();
+            });
+        }
+
+        let mut searcher = new_searcher(true);
+        if config.currline {
+            searcher.on_line = Some(&on_line);
+        }
+        for depth in 1..=max_depth {
+            let mut on_move = |mv, number| {
+                (on_event.borrow_mut())(SearchEvent::CurrentMove {
+                    depth,
+                    mv,
+                    number,
+                    elapsed: start.elapsed(),
+                })
+            };
+            let Some((best_move, score)) =
+                searcher.aspiration_search(depth, &result, &mut on_move)
+            else {
+                break;
+            };
+            let pv = searcher.pv[0].clone();
+            // A MultiPV iteration only counts once every line is done.
+            let count = config.multi_pv.clamp(1, root_moves.len());
+            let previous = &result.extra_lines;
+            let Some(extra_lines) =
+                searcher.extra_lines(depth, best_move, count, previous, &mut on_move)
+            else {
+                break;
+            };
+            result = SearchResult {
+                best_move: Some(best_move),
+                score: Score::from_internal(score),
+                depth,
+                nodes: searcher.total_nodes(),
+                hashfull: tt.hashfull(),
+                tt_probes: searcher.tt_probes,
+                tt_hits: searcher.tt_hits,
+                pv,
+                extra_lines,
+            };
+            (on_event.borrow_mut())(SearchEvent::Iteration {
+                result: &result,
+                elapsed: start.elapsed(),
+            });
+
+            if is_mate_score(score) && limits.depth.is_none() && !limits.infinite {
+                break;
+            }
+            if let (Some(wanted), Score::Mate(moves)) = (limits.mate, result.score) {
+                if moves > 0 && moves as u32 <= wanted {
+                    break;
+                }
+            }
+        }
+        main_done.store(true, Ordering::Relaxed);
+        searcher.nodes
+    });
+
+    if result.best_move.is_none() {
+        // Stopped before depth 1 finished; any legal move beats none.
+        result.best_move = Some(root_moves[0]);
+        result.pv = vec![root_moves[0]];
+    }
+    result.nodes = main_nodes + helper_nodes.load(Ordering::Relaxed);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search_fen(fen: &str, depth: u32, config: &SearchConfig) -> SearchResult {
+        let board = Board::from_fen(fen).unwrap();
+        let limits = SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        };
+        let tt = TranspositionTable::new(1);
+        search(&board, &limits, config, &tt, &AtomicBool::new(false), |_| {})
+    }
+
+    fn best_move(fen: &str, depth: u32) -> SearchResult {
+        search_fen(fen, depth, &SearchConfig::default())
+    }
+
+    #[test]
+    fn test_finds_mate_in_one() {
+        let result = best_move("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 2);
+        assert_eq!(result.best_move.unwrap().to_uci(), "a1a8");
+        assert_eq!(result.score, Score::Mate(1));
+    }
+
+    #[test]
+    fn test_mate_beats_the_fifty_move_rule() {
+        // Ra8 is mate and brings the halfmove clock to 100.
+        let result = best_move("6k1/5ppp/8/8/8/8/8/R5K1 w - - 99 80", 2);
+        assert_eq!(result.best_move.unwrap().to_uci(), "a1a8");
+        assert_eq!(result.score, Score::Mate(1));
+        // With luft for the king every move runs into the draw.
+        let result = best_move("6k1/5pp1/7p/8/8/8/8/R5K1 w - - 99 80", 2);
+        assert_eq!(result.score, Score::Cp(0));
+    }
+
+    #[test]
+    fn test_deep_mate_search_stays_in_range() {
+        let result = best_move("1k6/8/1K6/8/8/8/8/7R b - - 0 1", 24);
+        assert_eq!(result.score, Score::Mate(-2));
+        let result = best_move("k7/8/1K6/8/8/8/8/7R w - - 0 1", 24);
+        assert_eq!(result.score, Score::Mate(1));
+        assert_eq!(Score::Mate(i32::MAX).to_internal(), MATE - (MAX_PLY as i32 - 1));
+        assert_eq!(Score::Mate(i32::MIN).to_internal(), -MATE + MAX_PLY as i32);
+        assert!(!is_mate_score(Score::Cp(i32::MAX).to_internal()));
+        assert_eq!(Score::Cp(i32::MIN).to_internal().checked_neg(), Some(MATE_BOUND - 1));
+    }
+
+    #[test]
+    fn test_prefers_the_shortest_mate() {
+        // Ra8 and Qb8 mate at once; with queen and rook most other moves
+        // mate a few moves later.
+        let fen = "6k1/5ppp/8/8/8/8/1Q6/R5K1 w - - 0 1";
+        let board = Board::from_fen(fen).unwrap();
+        let limits = SearchLimits {
+            depth: Some(5),
+            ..Default::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let config = SearchConfig::default();
+        // The second search starts from mate scores already in the table.
+        for _ in 0..2 {
+            let result = search(&board, &limits, &config, &tt, &AtomicBool::new(false), |_| {});
+            assert_eq!(result.score, Score::Mate(1));
+            assert!(["a1a8", "b2b8"].contains(&result.best_move.unwrap().to_uci().as_str()));
+        }
+    }
+
+    #[test]
+    fn test_losing_side_delays_mate() {
+        // Ka8 allows Rh8 mate at once; Kc8 holds out one move longer.
+        let result = best_move("1k6/8/1K6/8/8/8/8/7R b - - 0 1", 5);
+        assert_eq!(result.best_move.unwrap().to_uci(), "b8c8");
+        assert_eq!(result.score, Score::Mate(-2));
+    }
+
+    #[test]
+    fn test_score_kinds() {
+        // Black's only move is Kb8, then Rh8 mates.
+        let result = best_move("k7/8/1K6/8/8/8/8/7R b - - 0 1", 3);
+        assert_eq!(result.score, Score::Mate(-1));
+        assert_eq!(best_move(crate::board::START_FEN, 2).score, Score::Cp(0));
+
+        assert_eq!(Score::from_internal(MATE - 3), Score::Mate(2));
+        assert_eq!(Score::from_internal(-MATE + 4), Score::Mate(-2));
+        for score in [MATE - 1, MATE - 5, -MATE + 2, -MATE + 6, 123] {
+            assert_eq!(Score::from_internal(score).to_internal(), score);
+        }
+        assert_eq!(Score::Mate(-3).to_string(), "mate -3");
+    }
+
+    #[test]
+    fn test_score_for_parent() {
+        assert_eq!(Score::Cp(35).for_parent(), Score::Cp(-35));
+        // Being mated right after our move means we delivered mate in one.
+        assert_eq!(Score::from_internal(-MATE).for_parent(), Score::Mate(1));
+        assert_eq!(Score::Mate(-2).for_parent(), Score::Mate(3));
+        assert_eq!(Score::Mate(1).for_parent(), Score::Mate(-1));
+    }
+
+    #[test]
+    fn test_captures_hanging_queen() {
+        let result = best_move("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", 2);
+        assert_eq!(result.best_move.unwrap().to_uci(), "d2d5");
+    }
+
+    #[test]
+    fn test_no_legal_moves_returns_no_best_move() {
+        let result = best_move("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 3);
+        assert_eq!(result.best_move, None);
+    }
+
+    #[test]
+    fn test_single_legal_move_returns_at_once() {
+        // Kxb2 is the only way out of check.
+        let board = Board::from_fen("k7/8/8/8/8/8/1q6/K7 w - - 0 1").unwrap();
+        let limits = SearchLimits {
+            movetime: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let start = Instant::now();
+        let config = SearchConfig::default();
+        let result = search(&board, &limits, &config, &tt, &AtomicBool::new(false), |_| {});
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(result.best_move.unwrap().to_uci(), "a1b2");
+        assert_eq!(result.depth, SINGLE_MOVE_DEPTH);
+        assert_eq!(result.score, Score::Cp(0));
+    }
+
+    #[test]
+    fn test_stalemate_at_the_horizon_scores_zero() {
+        // Nxd5 wins the rook but leaves Black stalemated; the queen is worth more.
+        let fen = "k7/p1K5/P7/3r4/5N2/8/7Q/8 w - - 0 1";
+        for depth in [1, 2] {
+            let result = best_move(fen, depth);
+            assert_ne!(result.best_move.unwrap().to_uci(), "f4d5");
+            assert!(result.score.to_internal() > 0);
+        }
+    }
+
+    #[test]
+    fn test_root_without_moves_tells_mate_from_stalemate() {
+        let stalemate = best_move("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 2);
+        assert_eq!(stalemate.score, Score::Cp(0));
+        let mated = best_move("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", 2);
+        assert_eq!(mated.best_move, None);
+        assert_eq!(mated.score, Score::Mate(0));
+        assert!(mated.score.to_internal() < 0);
+    }
+
+    #[test]
+    fn test_pv_starts_with_best_move_and_is_legal() {
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let result = best_move(fen, 4);
+        assert_eq!(result.pv[0], result.best_move.unwrap());
+        let mut board = Board::from_fen(fen).unwrap();
+        for mv in &result.pv {
+            assert!(movegen::generate_legal_moves(&board).contains(mv));
+            board.make_move(*mv);
+        }
+    }
+
+    #[test]
+    fn test_mate_limit_stops_before_the_depth_limit() {
+        let board = Board::from_fen("1k6/8/3K4/8/8/8/8/7R w - - 0 1").unwrap();
+        let limits = SearchLimits {
+            depth: Some(20),
+            mate: Some(5),
+            ..Default::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let config = SearchConfig::default();
+        let result = search(&board, &limits, &config, &tt, &AtomicBool::new(false), |_| {});
+        assert_eq!(result.score, Score::Mate(3));
+        assert!(result.depth < 20);
+    }
+
+    #[test]
+    fn test_nodes_per_second() {
+        assert_eq!(nodes_per_second(5_000, Duration::from_millis(500)), 10_000);
+        // Below a millisecond counts as one.
+        assert_eq!(nodes_per_second(1_000, Duration::ZERO), 1_000_000);
+        assert_eq!(nodes_per_second(1_000, Duration::from_micros(300)), 1_000_000);
+        assert_eq!(nodes_per_second(u64::MAX, Duration::ZERO), u64::MAX);
+        assert_eq!(nodes_per_second(0, Duration::ZERO), 0);
+    }
+
+    #[test]
+    fn test_node_counts_past_u32() {
+        let result = SearchResult {
+            nodes: u32::MAX as u64 * 3,
+            depth: 1,
+            ..Default::default()
+        };
+        let info = format_info(&result, Duration::ZERO, None);
+        assert!(info.contains(&format!(" nodes {} ", u32::MAX as u64 * 3)), "{}", info);
+        assert!(info.contains(&format!(" nps {} ", u32::MAX as u64 * 3000)), "{}", info);
+    }
+
+    #[test]
+    fn test_pv_reaches_the_search_depth() {
+        // Rxd5 wins the queen and the kings then manoeuvre: no checks to
+        // extend the line, no mate to cut it short.
+        let fen = "4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1";
+        let result = best_move(fen, 6);
+        assert_eq!(result.pv.len(), 6);
+        assert_eq!(result.pv[0].to_uci(), "d2d5");
+        let mut board = Board::from_fen(fen).unwrap();
+        for mv in &result.pv {
+            assert!(movegen::generate_legal_moves(&board).contains(mv));
+            board.make_move(*mv);
+        }
+    }
+
+    #[test]
+    fn test_reused_buffers_search_like_fresh_ones() {
+        for (fen, depth) in [
+            (crate::board::START_FEN, 6),
+            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 5),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 8),
+            ("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 3),
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            let limits = SearchLimits {
+                depth: Some(depth),
+                ..Default::default()
+            };
+            let [reused, fresh] = [true, false].map(|reuse_buffers| {
+                let tt = TranspositionTable::new(1);
+                let config = SearchConfig::default();
+                let stop = AtomicBool::new(false);
+                run_search(&board, &limits, &config, &tt, &stop, |_| {}, reuse_buffers)
+            });
+            assert_eq!(reused, fresh, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_current_move_is_reported_for_root_moves_only() {
+        let board = Board::startpos();
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..Default::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let root_moves = movegen::generate_legal_moves(&board);
+        let mut reported = Vec::new();
+        search(&board, &limits, &SearchConfig::default(), &tt, &AtomicBool::new(false), |event| {
+            if let SearchEvent::CurrentMove { depth, mv, number, .. } = event {
+                reported.push((depth, mv, number));
+            }
+        });
+        assert_eq!(reported.len(), 3 * root_moves.len());
+        for (depth, mv, number) in reported {
+            assert!((1..=3).contains(&depth));
+            assert!(root_moves.contains(&mv));
+            assert!((1..=root_moves.len()).contains(&number));
+        }
+    }
+
+    #[test]
+    fn test_bogus_tt_moves_are_ignored() {
+        let board = Board::startpos();
+        let tt = TranspositionTable::new(1);
+        let sq = |s| crate::types::Square::from_algebraic(s).unwrap();
+        let bogus = TtEntry {
+            best_move: Some(Move::new(sq("a1"), sq("h8"))),
+            score: 0,
+            depth: 0,
+            bound: Bound::Lower,
+        };
+        // Plant the illegal move at the root and at every reply to it.
+        tt.store(board.hash(), bogus);
+        for mv in movegen::generate_legal_moves(&board) {
+            let mut child = board.clone();
+            child.make_move(mv);
+            tt.store(child.hash(), bogus);
+        }
+
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..Default::default()
+        };
+        let config = SearchConfig::default();
+        let result = search(&board, &limits, &config, &tt, &AtomicBool::new(false), |_| {});
+        let mut replay = board.clone();
+        for mv in &result.pv {
+            assert!(movegen::generate_legal_moves(&replay).contains(mv));
+            replay.make_move(*mv);
+        }
+        assert!(movegen::generate_legal_moves(&board).contains(&result.best_move.unwrap()));
+    }
+
+    #[test]
+    fn test_deterministic_search_is_reproducible() {
+        let config = SearchConfig {
+            threads: 4,
+            deterministic: true,
+            ..Default::default()
+        };
+        let board = Board::from_fen(
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+        )
+        .unwrap();
+        let limits = SearchLimits {
+            depth: Some(4),
+            ..Default::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let stop = AtomicBool::new(false);
+        let first = search(&board, &limits, &config, &tt, &stop, |_| {});
+        tt.clear();
+        let second = search(&board, &limits, &config, &tt, &stop, |_| {});
+        assert_eq!(first, second);
+        assert!(first.nodes > 0);
+    }
+
+    #[test]
+    fn test_equal_scores_go_to_the_first_move_in_uci_order() {
+        // Every move draws; the capture is searched first but Kd1 sorts first.
+        let board = Board::from_fen("4k3/8/8/8/8/8/3n4/4K3 w - - 0 1").unwrap();
+        let tt = TranspositionTable::new(1);
+        let stop = AtomicBool::new(false);
+        for depth in [1, 2, 3, 4, 1, 4] {
+            let limits = SearchLimits {
+                depth: Some(depth),
+                ..Default::default()
+            };
+            let result = search(&board, &limits, &SearchConfig::default(), &tt, &stop, |_| {});
+            assert_eq!(result.score, Score::Cp(0));
+            assert_eq!(result.best_move.map(|mv| mv.to_uci()).as_deref(), Some("e1d1"));
+        }
+    }
+
+    #[test]
+    fn test_iid_saves_nodes_from_a_cold_table() {
+        // At depth 7 every PV node deep enough for IID still has a hash move
+        // from the iteration before, so both searches are the same.
+        let fen = crate::board::START_FEN;
+        let without = search_fen(fen, 8, &SearchConfig { iid: false, ..Default::default() });
+        let with = search_fen(fen, 8, &SearchConfig::default());
+        assert!(with.nodes < without.nodes, "{} >= {}", with.nodes, without.nodes);
+        assert_eq!(with.best_move, without.best_move);
+    }
+
+    #[test]
+    fn test_razoring_prunes_quiet_positions_but_keeps_tactics() {
+        let off = SearchConfig {
+            razor_margin: 0,
+            ..Default::default()
+        };
+        let quiet = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+        let razored = search_fen(quiet, 5, &SearchConfig::default());
+        assert!(razored.nodes < search_fen(quiet, 5, &off).nodes);
+        for fen in [
+            "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+            "r1b2k1r/ppp1bppp/8/1B1Q4/5q2/2P5/PPP2PPP/R3R1K1 w - - 0 1",
+        ] {
+            assert_eq!(
+                search_fen(fen, 5, &SearchConfig::default()).best_move,
+                search_fen(fen, 5, &off).best_move
+            );
+        }
+    }
+
+    #[test]
+    fn test_lmr_divisor_changes_the_tree_but_not_the_tactic() {
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+        let default = search_fen(fen, 5, &SearchConfig::default());
+        let aggressive = SearchConfig {
+            lmr_divisor: 1,
+            ..Default::default()
+        };
+        assert_ne!(search_fen(fen, 5, &aggressive).nodes, default.nodes);
+
+        let result = search_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", 5, &aggressive);
+        assert_eq!(result.best_move.unwrap().to_uci(), "d2d5");
+    }
+
+    #[test]
+    fn test_history_reductions_save_nodes_but_keep_tactics() {
+        let off = SearchConfig {
+            history_reductions: false,
+            ..Default::default()
+        };
+        let quiet = [
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+            "r1bq1rk1/ppp2ppp/2np1n2/2b1p3/2B1P3/2PP1N2/PP3PPP/RNBQ1RK1 w - - 0 7",
+        ];
+        let nodes = |config: &SearchConfig| -> u64 {
+            quiet.iter().map(|fen| search_fen(fen, 7, config).nodes).sum()
+        };
+        let (with, without) = (nodes(&SearchConfig::default()), nodes(&off));
+        assert!(with < without, "{} >= {}", with, without);
+        let tactics = crate::epd::parse_tactic_suite(include_str!("../data/tactics.epd")).unwrap();
+        let limits = SearchLimits {
+            depth: Some(5),
+            ..Default::default()
+        };
+        let tt = TranspositionTable::new(16);
+        for config in [SearchConfig::default(), off] {
+            for outcome in crate::epd::run_tactic_suite(&tactics, &limits, &config, &tt) {
+                assert!(outcome.solved, "{}", outcome);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gentler_reductions_search_more_and_agree() {
+        let gentle = SearchConfig {
+            lmr_percent: 50,
+            ..Default::default()
+        };
+        let both = |fen: &str, depth: u32| {
+            (search_fen(fen, depth, &SearchConfig::default()), search_fen(fen, depth, &gentle))
+        };
+        let (full, half) =
+            both("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4", 6);
+        assert!(half.nodes > full.nodes, "{} <= {}", half.nodes, full.nodes);
+        let (full, half) = both("r1b2k1r/ppp1bppp/8/1B1Q4/5q2/2P5/PPP2PPP/R3R1K1 w - - 0 1", 5);
+        assert_eq!((half.best_move, half.score), (full.best_move, full.score));
+    }
+
+    #[test]
+    fn test_queen_and_king_corner_the_bare_king() {
+        use crate::game::{Game, GameStatus};
+        use crate::types::Color;
+        let mut game = Game::new(Board::from_fen("8/8/8/3k4/8/8/8/Q3K3 w - - 0 1").unwrap());
+        let tt = TranspositionTable::new(1);
+        let limits = SearchLimits {
+            depth: Some(5),
+            ..Default::default()
+        };
+        let (config, stop) = (SearchConfig::default(), AtomicBool::new(false));
+        // The bare king's moves each time it is Black's turn.
+        let mut mobility = Vec::new();
+        while game.status() == GameStatus::Ongoing && game.moves().len() < 60 {
+            let result = search(game.board(), &limits, &config, &tt, &stop, |_| {});
+            game.play(result.best_move.unwrap());
+            if game.board().side_to_move() == Color::Black {
+                mobility.push(movegen::generate_legal_moves(game.board()).len());
+            }
+        }
+        assert_eq!(game.status(), GameStatus::Checkmate { winner: Color::White });
+        let half = mobility.len() / 2;
+        let average = |moves: &[usize]| moves.iter().sum::<usize>() as f64 / moves.len() as f64;
+        assert!(average(&mobility[half..]) < average(&mobility[..half]), "{:?}", mobility);
+    }
+
+    #[test]
+    fn test_deadline_set_mid_search_stops_it() {
+        let deadline = Arc::new(Deadline::new());
+        let limits = SearchLimits {
+            deadline: Some(Arc::clone(&deadline)),
+            ..Default::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let stop = AtomicBool::new(false);
+        let start = Instant::now();
+        let result = std::thread::scope(|scope| {
+            let search = scope.spawn(|| {
+                search(&Board::startpos(), &limits, &SearchConfig::default(), &tt, &stop, |_| {})
+            });
+            std::thread::sleep(Duration::from_millis(100));
+            assert!(!search.is_finished());
+            deadline.set(Duration::from_millis(50));
+            search.join().unwrap()
+        });
+        assert!(start.elapsed() >= Duration::from_millis(150));
+        assert!(start.elapsed() < Duration::from_secs(5), "{:?}", start.elapsed());
+        assert!(result.best_move.is_some());
+        assert_eq!(deadline, deadline.clone());
+        assert_ne!(*deadline, Deadline::new());
+    }
+
+    #[test]
+    fn test_helper_threads_still_find_the_tactic() {
+        let config = SearchConfig {
+            threads: 3,
+            ..Default::default()
+        };
+        let result = search_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", 3, &config);
+        assert_eq!(result.best_move.unwrap().to_uci(), "d2d5");
+    }
+
+    #[test]
+    fn test_multipv_reports_distinct_lines_best_first() {
+        let config = SearchConfig {
+            multi_pv: 3,
+            ..Default::default()
+        };
+        let result = search_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", 4, &config);
+        assert_eq!(result.best_move.unwrap().to_uci(), "d2d5");
+        assert_eq!(result.extra_lines.len(), 2);
+        let firsts: Vec<Move> = result.extra_lines.iter().map(|line| line.pv[0]).collect();
+        assert!(!firsts.contains(&result.best_move.unwrap()));
+        assert_ne!(firsts[0], firsts[1]);
+        let scores = [result.score, result.extra_lines[0].score, result.extra_lines[1].score];
+        assert!(scores.windows(2).all(|w| w[0].to_internal() >= w[1].to_internal()));
+
+        let lines = format_all_lines(&result, Duration::ZERO, None);
+        assert!(lines[0].starts_with("info depth 4 multipv 1 score "), "{}", lines[0]);
+        assert!(lines[2].starts_with("info depth 4 multipv 3 score "), "{}", lines[2]);
+        // A single line is not tagged at all.
+        let single = best_move("4k3/8/8/8/8/8/3R4/4K3 w - - 0 1", 2);
+        assert!(!format_info(&single, Duration::ZERO, None).contains("multipv"));
+    }
+
+    #[test]
+    fn test_corrupt_board_is_not_searched() {
+        let e1 = crate::types::Square::from_algebraic("e1").unwrap();
+        let board = Board::startpos().corrupted(e1);
+        let limits = SearchLimits {
+            depth: Some(3),
+            ..Default::default()
+        };
+        let tt = TranspositionTable::new(1);
+        let config = SearchConfig::default();
+        let result = search(&board, &limits, &config, &tt, &AtomicBool::new(false), |_| {});
+        assert_eq!(result.best_move, None);
+    }
+}
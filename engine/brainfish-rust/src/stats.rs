@@ -0,0 +1,91 @@
+use crate::search::{self, SearchResult};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// One JSON object per line for every finished search, for profiling the
+/// engine over many positions.
+pub struct StatsLog {
+    writer: BufWriter<File>,
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    fen: &'a str,
+    depth: u32,
+    nodes: u64,
+    nps: u64,
+    time_ms: u64,
+    bestmove: String,
+    score: String,
+    tt_hit_rate: f64,
+}
+
+impl StatsLog {
+    /// Opens `path` for appending, creating it if needed.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    /// Appends a record for `result` and flushes it to disk.
+    pub fn record(
+        &mut self,
+        fen: &str,
+        result: &SearchResult,
+        elapsed: Duration,
+    ) -> io::Result<()> {
+        let millis = elapsed.as_millis() as u64;
+        let record = Record {
+            fen,
+            depth: result.depth,
+            nodes: result.nodes,
+            nps: search::nodes_per_second(result.nodes, elapsed),
+            time_ms: millis,
+            bestmove: result.best_move.map_or(String::from("0000"), |mv| mv.to_uci()),
+            score: result.score.to_string(),
+            tt_hit_rate: result.tt_hits as f64 / result.tt_probes.max(1) as f64,
+        };
+        serde_json::to_writer(&mut self.writer, &record)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_appends_json_lines() {
+        let name = format!("brainfish-stats-{}.log", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_file(&path);
+        let result = SearchResult {
+            depth: 3,
+            nodes: 500,
+            tt_probes: 10,
+            tt_hits: 4,
+            ..Default::default()
+        };
+        let mut log = StatsLog::open(&path).unwrap();
+        log.record("8/8/8/8/8/8/8/K6k w - - 0 1", &result, Duration::from_millis(100))
+            .unwrap();
+        log.record("8/8/8/8/8/8/8/K6k w - - 0 1", &result, Duration::ZERO)
+            .unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let record: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(record["depth"], 3);
+        assert_eq!(record["nps"], 5000);
+        assert_eq!(record["bestmove"], "0000");
+        assert_eq!(record["tt_hit_rate"], 0.4);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::rng::Rng;
+use crate::search::SearchResult;
+
+/// Range of the UCI_Elo option.
+pub const MIN_ELO: u32 = 800;
+pub const MAX_ELO: u32 = 2800;
+pub const DEFAULT_ELO: u32 = 1500;
+/// Range of the Skill Level option; the top level is full strength.
+pub const MAX_SKILL_LEVEL: u32 = 20;
+/// Root lines searched so there is something other than the best to play.
+pub const CANDIDATES: usize = 4;
+
+// Each step of this many Elo is worth a ply of depth.
+const ELO_PER_PLY: u32 = 200;
+// At the bottom of the range a move may be this many centipawns worse than
+// the best; the allowance shrinks to nothing at the top.
+const MAX_MARGIN: i32 = 250;
+
+/// How a UCI_LimitStrength engine plays at a given Elo: shallow searches,
+/// then a random pick among the moves not much worse than the best. Lower
+/// ratings search less deep and accept bigger losses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Strength {
+    pub depth: u32,
+    /// Centipawns a chosen move may trail the best one by.
+    pub margin: i32,
+}
+
+impl Strength {
+    /// `elo` is clamped to `MIN_ELO..=MAX_ELO`.
+    pub fn for_elo(elo: u32) -> Self {
+        let elo = elo.clamp(MIN_ELO, MAX_ELO);
+        let range = (MAX_ELO - MIN_ELO) as i32;
+        Self {
+            depth: 1 + (elo - MIN_ELO) / ELO_PER_PLY,
+            margin: MAX_MARGIN * (MAX_ELO - elo) as i32 / range,
+        }
+    }
+
+    /// Skill Level `level`, clamped to `0..=MAX_SKILL_LEVEL`: as in Stockfish,
+    /// a search to depth `1 + level`. The margin shrinks evenly from the
+    /// widest at level 0 to nothing at the top level.
+    pub fn for_skill(level: u32) -> Self {
+        let level = level.min(MAX_SKILL_LEVEL);
+        Self {
+            depth: 1 + level,
+            margin: MAX_MARGIN * (MAX_SKILL_LEVEL - level) as i32 / MAX_SKILL_LEVEL as i32,
+        }
+    }
+
+    /// The line to play from a search with several lines. Moves within the
+    /// margin are picked with weights falling as they get worse; anything
+    /// further behind is never played.
+    pub fn choose(&self, result: &SearchResult, rng: &mut Rng) -> SearchResult {
+        let best = result.score.to_internal();
+        let weights: Vec<u32> = std::iter::once(result.score)
+            .chain(result.extra_lines.iter().map(|line| line.score))
+            .map(|score| {
+                let loss = best.saturating_sub(score.to_internal());
+                if loss > self.margin {
+                    0
+                } else {
+                    (self.margin - loss + 1) as u32
+                }
+            })
+            .collect();
+        let index = rng.choose_weighted(&weights).unwrap_or(0);
+        let Some(line) = index.checked_sub(1).map(|i| &result.extra_lines[i]) else {
+            return result.clone();
+        };
+        let Some(&mv) = line.pv.first() else {
+            return result.clone();
+        };
+        SearchResult {
+            best_move: Some(mv),
+            score: line.score,
+            pv: line.pv.clone(),
+            ..result.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moves::Move;
+    use crate::search::{PvLine, Score};
+    use crate::types::Square;
+
+    #[test]
+    fn test_lower_elo_is_never_stronger() {
+        let mut previous = Strength::for_elo(MIN_ELO);
+        assert_eq!(previous, Strength { depth: 1, margin: MAX_MARGIN });
+        for elo in (MIN_ELO..=MAX_ELO).step_by(50) {
+            let strength = Strength::for_elo(elo);
+            assert!(strength.depth >= previous.depth, "{}", elo);
+            assert!(strength.margin <= previous.margin, "{}", elo);
+            previous = strength;
+        }
+        assert_eq!(previous.margin, 0);
+        assert_eq!(Strength::for_elo(0), Strength::for_elo(MIN_ELO));
+    }
+
+    #[test]
+    fn test_skill_levels_span_random_to_full_strength() {
+        assert_eq!(Strength::for_skill(0), Strength { depth: 1, margin: MAX_MARGIN });
+        assert_eq!(Strength::for_skill(MAX_SKILL_LEVEL).margin, 0);
+        assert_eq!(Strength::for_skill(99), Strength::for_skill(MAX_SKILL_LEVEL));
+        let margins: Vec<i32> =
+            (0..=MAX_SKILL_LEVEL).map(|level| Strength::for_skill(level).margin).collect();
+        assert!(margins.windows(2).all(|pair| pair[1] < pair[0]), "{:?}", margins);
+    }
+
+    #[test]
+    fn test_choose_stays_within_the_margin() {
+        let mv = |from: &str, to: &str| {
+            Move::new(Square::from_algebraic(from).unwrap(), Square::from_algebraic(to).unwrap())
+        };
+        let line = |cp, m| PvLine {
+            score: Score::Cp(cp),
+            pv: vec![m],
+        };
+        let result = SearchResult {
+            best_move: Some(mv("e2", "e4")),
+            score: Score::Cp(50),
+            pv: vec![mv("e2", "e4")],
+            extra_lines: vec![line(20, mv("d2", "d4")), line(-300, mv("g2", "g4"))],
+            ..Default::default()
+        };
+        let strength = Strength::for_elo(MIN_ELO);
+        let mut rng = Rng::new(3);
+        let picks: Vec<Move> = (0..100)
+            .map(|_| strength.choose(&result, &mut rng).best_move.unwrap())
+            .collect();
+        assert!(picks.contains(&mv("d2", "d4")));
+        assert!(picks.contains(&mv("e2", "e4")));
+        assert!(!picks.contains(&mv("g2", "g4")));
+        // The same seed makes the same picks.
+        let mut rng = Rng::new(3);
+        assert!(picks.iter().all(|&m| strength.choose(&result, &mut rng).best_move == Some(m)));
+        // At full strength only the best move is left.
+        let full = Strength::for_elo(MAX_ELO);
+        assert_eq!(full.choose(&result, &mut rng), result);
+    }
+}
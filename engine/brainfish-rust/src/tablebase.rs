@@ -0,0 +1,257 @@
+use crate::board::Board;
+use crate::types::{Color, PieceKind};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// First four bytes of every Syzygy WDL and DTZ file.
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+/// Order pieces appear in in Syzygy file names, e.g. `KQRvKB`.
+const NAME_ORDER: [PieceKind; 6] = [
+    PieceKind::King,
+    PieceKind::Queen,
+    PieceKind::Rook,
+    PieceKind::Bishop,
+    PieceKind::Knight,
+    PieceKind::Pawn,
+];
+
+/// A tablebase outcome for the side to move, in Syzygy's five classes. A
+/// cursed win or blessed loss is a draw only because of the fifty-move rule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
+
+impl fmt::Display for Wdl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Wdl::Loss => "loss",
+            Wdl::BlessedLoss => "blessed-loss",
+            Wdl::Draw => "draw",
+            Wdl::CursedWin => "cursed-win",
+            Wdl::Win => "win",
+        })
+    }
+}
+
+/// What the tables say about a position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TableResult {
+    /// As if the halfmove clock were 0.
+    pub wdl: Wdl,
+    /// Plies to the next capture or pawn move with best play, positive when
+    /// the side to move wins; `None` without a DTZ table.
+    pub dtz: Option<i32>,
+}
+
+impl TableResult {
+    /// The outcome with `halfmove_clock` plies already used up: a win or loss
+    /// whose next zeroing move comes after the hundredth ply is drawn.
+    pub fn wdl_with_clock(&self, halfmove_clock: u32) -> Wdl {
+        let too_late = self
+            .dtz
+            .is_some_and(|dtz| dtz.unsigned_abs() + halfmove_clock > 100);
+        match self.wdl {
+            Wdl::Win if too_late => Wdl::CursedWin,
+            Wdl::Loss if too_late => Wdl::BlessedLoss,
+            wdl => wdl,
+        }
+    }
+}
+
+/// Looks up a position the loaded tables cover. Only file headers are read
+/// here, not the compressed contents, so the lookup itself comes from the
+/// host; see `Engine::set_tablebase_probe`.
+pub type Probe = Arc<dyn Fn(&Board) -> Option<TableResult> + Send + Sync>;
+
+/// The `wdl` command's answer for `board`: the outcome for the side to move
+/// with its clock taken into account, and the DTZ when known.
+pub fn describe(board: &Board, result: Option<TableResult>) -> String {
+    let Some(result) = result else {
+        return String::from("wdl not in tablebase");
+    };
+    let mut text = format!("wdl {}", result.wdl_with_clock(board.halfmove_clock()));
+    if let Some(dtz) = result.dtz {
+        text.push_str(&format!(" dtz {}", dtz));
+    }
+    text
+}
+
+#[derive(Clone, Debug, Default)]
+struct TableFiles {
+    wdl: Option<PathBuf>,
+    dtz: Option<PathBuf>,
+}
+
+/// The Syzygy files found under `SyzygyPath`. Files that can't be read or
+/// don't carry the right magic number are skipped, so a partial or damaged
+/// set still covers what it can.
+#[derive(Clone, Debug, Default)]
+pub struct Tablebases {
+    tables: HashMap<String, TableFiles>,
+    max_pieces: usize,
+}
+
+impl Tablebases {
+    /// Scans every directory in `paths` (separated like `PATH`). Returns the
+    /// usable tables and a warning for each problem found.
+    pub fn load(paths: &str) -> (Self, Vec<String>) {
+        let mut tablebases = Self::default();
+        let mut warnings = Vec::new();
+        for dir in std::env::split_paths(paths).filter(|p| !p.as_os_str().is_empty()) {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    warnings.push(format!("cannot read {}: {}", dir.display(), e));
+                    continue;
+                }
+            };
+            for path in entries.flatten().map(|entry| entry.path()) {
+                if let Err(warning) = tablebases.add_file(&path) {
+                    warnings.push(warning);
+                }
+            }
+        }
+        (tablebases, warnings)
+    }
+
+    fn add_file(&mut self, path: &Path) -> Result<(), String> {
+        let magic = match path.extension().and_then(|e| e.to_str()) {
+            Some("rtbw") => WDL_MAGIC,
+            Some("rtbz") => DTZ_MAGIC,
+            _ => return Ok(()),
+        };
+        let name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .filter(|s| is_table_name(s))
+            .ok_or_else(|| format!("ignoring {}: not a tablebase name", path.display()))?;
+
+        let mut header = [0u8; 4];
+        File::open(path)
+            .and_then(|mut file| file.read_exact(&mut header))
+            .map_err(|e| format!("ignoring {}: {}", path.display(), e))?;
+        if header != magic {
+            return Err(format!("ignoring {}: bad magic number", path.display()));
+        }
+
+        self.max_pieces = self.max_pieces.max(name.len() - 1);
+        let files = self.tables.entry(name.to_string()).or_default();
+        if magic == WDL_MAGIC {
+            files.wdl = Some(path.to_path_buf());
+        } else {
+            files.dtz = Some(path.to_path_buf());
+        }
+        Ok(())
+    }
+
+    /// Number of material combinations with a usable WDL table.
+    pub fn len(&self) -> usize {
+        self.tables.values().filter(|t| t.wdl.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Most pieces, kings included, in any usable table.
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    /// Whether a WDL table exists for the material on `board`.
+    pub fn covers(&self, board: &Board) -> bool {
+        let white = material_side(board, Color::White);
+        let black = material_side(board, Color::Black);
+        [format!("{}v{}", white, black), format!("{}v{}", black, white)]
+            .iter()
+            .any(|name| self.tables.get(name).is_some_and(|t| t.wdl.is_some()))
+    }
+}
+
+fn is_table_name(name: &str) -> bool {
+    let mut sides = name.split('v');
+    let valid_side = |side: Option<&str>| {
+        side.is_some_and(|s| s.starts_with('K') && s.chars().all(|c| "KQRBNP".contains(c)))
+    };
+    valid_side(sides.next()) && valid_side(sides.next()) && sides.next().is_none()
+}
+
+fn material_side(board: &Board, color: Color) -> String {
+    let material = board.material_count();
+    NAME_ORDER
+        .iter()
+        .flat_map(|&kind| {
+            let letter = kind.to_san_char();
+            std::iter::repeat_n(letter, material.count(color, kind) as usize)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_and_corrupt_sets() {
+        let dir = std::env::temp_dir().join(format!("brainfish-syzygy-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("KQvK.rtbw"), [&WDL_MAGIC[..], &[0; 12]].concat()).unwrap();
+        fs::write(dir.join("KQvK.rtbz"), DTZ_MAGIC).unwrap();
+        fs::write(dir.join("KRvK.rtbw"), b"garbage").unwrap();
+        fs::write(dir.join("KBvK.rtbw"), [0x71]).unwrap();
+        fs::write(dir.join("README.txt"), b"not a table").unwrap();
+
+        let missing = dir.join("missing");
+        let paths = std::env::join_paths([dir.clone(), missing]).unwrap();
+        let (tablebases, warnings) = Tablebases::load(paths.to_str().unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(tablebases.len(), 1);
+        assert_eq!(tablebases.max_pieces(), 3);
+        assert_eq!(warnings.len(), 3, "{:?}", warnings);
+        assert!(tablebases.covers(&Board::from_fen("8/8/8/8/2k5/8/8/Q3K3 w - - 0 1").unwrap()));
+        assert!(tablebases.covers(&Board::from_fen("8/8/8/8/2K5/8/8/q3k3 w - - 0 1").unwrap()));
+        assert!(!tablebases.covers(&Board::from_fen("8/8/8/8/2k5/8/8/R3K3 w - - 0 1").unwrap()));
+    }
+
+    #[test]
+    fn test_describe_applies_the_fifty_move_rule() {
+        let kqvk = |clock: u32| {
+            let fen = format!("8/8/8/8/2k5/8/8/Q3K3 w - - {} 80", clock);
+            Board::from_fen(&fen).unwrap()
+        };
+        let win = TableResult {
+            wdl: Wdl::Win,
+            dtz: Some(17),
+        };
+        assert_eq!(describe(&kqvk(0), Some(win)), "wdl win dtz 17");
+        assert_eq!(describe(&kqvk(83), Some(win)), "wdl win dtz 17");
+        assert_eq!(describe(&kqvk(84), Some(win)), "wdl cursed-win dtz 17");
+        let loss = TableResult {
+            wdl: Wdl::Loss,
+            dtz: Some(-40),
+        };
+        assert_eq!(loss.wdl_with_clock(61), Wdl::BlessedLoss);
+        let unknown = TableResult { dtz: None, ..win };
+        assert_eq!(describe(&kqvk(99), Some(unknown)), "wdl win");
+        assert_eq!(describe(&kqvk(0), None), "wdl not in tablebase");
+    }
+
+    #[test]
+    fn test_table_names() {
+        assert!(is_table_name("KRPvKR"));
+        assert!(!is_table_name("KRvKvK"));
+        assert!(!is_table_name("QKvK"));
+    }
+}
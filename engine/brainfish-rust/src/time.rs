@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+/// Percent of the usual allocation spent by default; see `allocate`.
+pub const DEFAULT_SLOW_MOVER: u32 = 100;
+
+// Without `movestogo` the game may last any length, so the moves still to
+// play are guessed from how far it has got, never fewer than the floor.
+const SUDDEN_DEATH_MOVES: u32 = 50;
+const SUDDEN_DEATH_MIN_MOVES: u32 = 20;
+// However large the overhead, always leave the search some time to return a move.
+const MIN_ALLOCATION: Duration = Duration::from_millis(1);
+// Share of each increment spent on the move it arrives with, in quarters.
+const INCREMENT_QUARTERS: u32 = 3;
+// The increment is only credited after the move, so a single move never uses
+// more than this share (in fifths) of the time actually on the clock.
+const MAX_CLOCK_FIFTHS: u32 = 4;
+
+/// Clock state for the side to move, as given by `go wtime ... btime ...`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Clock {
+    pub remaining: Duration,
+    pub increment: Duration,
+    pub moves_to_go: Option<u32>,
+    /// Full move number of the position; 0 when unknown.
+    pub move_number: u32,
+}
+
+/// Moves the remaining time should last for in sudden death.
+fn sudden_death_moves(move_number: u32) -> u32 {
+    SUDDEN_DEATH_MOVES
+        .saturating_sub(move_number / 2)
+        .max(SUDDEN_DEATH_MIN_MOVES)
+}
+
+/// How long to think about the current move: a share of the remaining time
+/// plus most of the increment. The share is one of `movestogo` moves when
+/// given, and an estimate of the moves left otherwise. `move_overhead` is
+/// held back for communication lag between the engine and the clock.
+/// `slow_mover` scales that, in percent, before the cap on one move.
+pub fn allocate(clock: &Clock, move_overhead: Duration, slow_mover: u32) -> Duration {
+    let moves = match clock.moves_to_go {
+        Some(moves) => moves.max(1),
+        None => sudden_death_moves(clock.move_number),
+    };
+    let ideal = clock.remaining / moves + clock.increment * INCREMENT_QUARTERS / 4;
+    let ideal = ideal * slow_mover / 100;
+    let ceiling = clock.remaining.saturating_sub(move_overhead) * MAX_CLOCK_FIFTHS / 5;
+    ideal
+        .saturating_sub(move_overhead)
+        .min(ceiling)
+        .max(MIN_ALLOCATION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocation_splits_remaining_time() {
+        let clock = Clock {
+            remaining: Duration::from_secs(60),
+            moves_to_go: Some(20),
+            ..Default::default()
+        };
+        assert_eq!(allocate(&clock, Duration::ZERO, DEFAULT_SLOW_MOVER), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_slow_mover_scales_allocation() {
+        let clock = Clock {
+            remaining: Duration::from_secs(60),
+            increment: Duration::from_secs(1),
+            ..Default::default()
+        };
+        let overhead = Duration::from_millis(10);
+        let usual = allocate(&clock, overhead, 100);
+        assert_eq!(allocate(&clock, overhead, 200), (usual + overhead) * 2 - overhead);
+        assert_eq!(allocate(&clock, overhead, 50), (usual + overhead) / 2 - overhead);
+        // Even a huge multiplier leaves time on the clock.
+        let few_moves = Clock {
+            moves_to_go: Some(5),
+            ..clock
+        };
+        let ceiling = (clock.remaining - overhead) * MAX_CLOCK_FIFTHS / 5;
+        assert_eq!(allocate(&few_moves, overhead, 1000), ceiling);
+    }
+
+    #[test]
+    fn test_move_overhead_reduces_allocation() {
+        let clock = Clock {
+            remaining: Duration::from_secs(60),
+            moves_to_go: Some(20),
+            ..Default::default()
+        };
+        let base = allocate(&clock, Duration::from_millis(10), DEFAULT_SLOW_MOVER);
+        let more = allocate(&clock, Duration::from_millis(110), DEFAULT_SLOW_MOVER);
+        assert_eq!(base - more, Duration::from_millis(100));
+        assert_eq!(allocate(&clock, Duration::from_secs(10), DEFAULT_SLOW_MOVER), MIN_ALLOCATION);
+    }
+
+    #[test]
+    fn test_increment_is_mostly_spent() {
+        let clock = Clock {
+            remaining: Duration::from_secs(60),
+            increment: Duration::from_secs(2),
+            moves_to_go: Some(30),
+            ..Default::default()
+        };
+        assert_eq!(
+            allocate(&clock, Duration::ZERO, DEFAULT_SLOW_MOVER),
+            Duration::from_millis(3500)
+        );
+    }
+
+    #[test]
+    fn test_low_base_high_increment_stays_on_the_clock() {
+        let clock = Clock {
+            remaining: Duration::from_millis(500),
+            increment: Duration::from_secs(10),
+            ..Default::default()
+        };
+        let overhead = Duration::from_millis(10);
+        let allocation = allocate(&clock, overhead, DEFAULT_SLOW_MOVER);
+        // Most of what is left, but never all of it: the increment comes later.
+        assert_eq!(allocation, Duration::from_millis(392));
+        assert!(allocation + overhead < clock.remaining);
+
+        let flagging = Clock {
+            remaining: Duration::from_millis(5),
+            ..clock
+        };
+        assert_eq!(allocate(&flagging, overhead, DEFAULT_SLOW_MOVER), MIN_ALLOCATION);
+    }
+
+    #[test]
+    fn test_sudden_death_spends_a_small_fraction() {
+        let opening = Clock {
+            remaining: Duration::from_secs(60),
+            move_number: 1,
+            ..Default::default()
+        };
+        let allocation = allocate(&opening, Duration::ZERO, DEFAULT_SLOW_MOVER);
+        assert_eq!(allocation, Duration::from_millis(1200));
+        assert!(allocation < opening.remaining / 20);
+
+        // Later moves expect fewer moves left, but never fewer than the floor.
+        let middlegame = Clock {
+            move_number: 40,
+            ..opening
+        };
+        assert_eq!(
+            allocate(&middlegame, Duration::ZERO, DEFAULT_SLOW_MOVER),
+            Duration::from_secs(2)
+        );
+        let endgame = Clock {
+            move_number: 120,
+            ..opening
+        };
+        assert_eq!(allocate(&endgame, Duration::ZERO, DEFAULT_SLOW_MOVER), Duration::from_secs(3));
+    }
+}
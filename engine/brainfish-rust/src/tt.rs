@@ -0,0 +1,245 @@
+use crate::moves::Move;
+use crate::search::MATE_BOUND;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+pub const DEFAULT_HASH_MB: usize = 16;
+const ENTRY_BYTES: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Bound::Exact => "exact",
+            Bound::Lower => "lower",
+            Bound::Upper => "upper",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TtEntry {
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u32,
+    pub bound: Bound,
+}
+
+// Each slot stores `key ^ data` next to `data`. A torn write from another
+// thread makes the pair fail the key check instead of yielding a mixed entry.
+struct Slot {
+    check: AtomicU64,
+    data: AtomicU64,
+}
+
+/// Transposition table shared between search threads.
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    generation: AtomicU8,
+}
+
+fn pack(entry: &TtEntry, generation: u8) -> u64 {
+    let best_move = entry.best_move.map_or(0, |mv| mv.to_u16()) as u64;
+    debug_assert!(i16::try_from(entry.score).is_ok(), "score {} out of range", entry.score);
+    let score = entry.score as i16 as u16 as u64;
+    let depth = entry.depth.min(255) as u64;
+    let bound = match entry.bound {
+        Bound::Exact => 0,
+        Bound::Lower => 1,
+        Bound::Upper => 2,
+    };
+    best_move | score << 16 | depth << 32 | bound << 40 | (generation as u64 & 63) << 42
+}
+
+fn unpack(data: u64) -> (TtEntry, u8) {
+    let bound = match data >> 40 & 3 {
+        0 => Bound::Exact,
+        1 => Bound::Lower,
+        _ => Bound::Upper,
+    };
+    let entry = TtEntry {
+        best_move: Move::from_u16(data as u16),
+        score: (data >> 16) as u16 as i16 as i32,
+        depth: (data >> 32 & 255) as u32,
+        bound,
+    };
+    (entry, (data >> 42 & 63) as u8)
+}
+
+/// Mate scores are stored relative to the node rather than the root, so an
+/// entry stays correct when reached at a different ply.
+pub fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+pub fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+/// Number of slots that fit in `megabytes`, rounded down to a power of two
+/// so an index is a mask. Never zero, and saturates instead of overflowing.
+fn entry_count(megabytes: usize) -> usize {
+    let entries = (megabytes.saturating_mul(1024 * 1024) / ENTRY_BYTES).max(1);
+    1 << (usize::BITS - 1 - entries.leading_zeros())
+}
+
+impl TranspositionTable {
+    pub fn new(megabytes: usize) -> Self {
+        let slots = (0..entry_count(megabytes))
+            .map(|_| Slot {
+                check: AtomicU64::new(0),
+                data: AtomicU64::new(0),
+            })
+            .collect();
+        Self {
+            slots,
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    fn slot(&self, key: u64) -> &Slot {
+        &self.slots[key as usize & (self.slots.len() - 1)]
+    }
+
+    /// Marks the start of a new search so older entries are replaced first.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.check.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Permille of sampled slots holding an entry from the current search.
+    pub fn hashfull(&self) -> u32 {
+        let sample = self.slots.len().min(1000);
+        let generation = self.generation.load(Ordering::Relaxed) & 63;
+        let used = self.slots[..sample]
+            .iter()
+            .filter(|slot| {
+                let data = slot.data.load(Ordering::Relaxed);
+                data != 0 && unpack(data).1 == generation
+            })
+            .count();
+        (used * 1000 / sample) as u32
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        let slot = self.slot(key);
+        let data = slot.data.load(Ordering::Relaxed);
+        let check = slot.check.load(Ordering::Relaxed);
+        if data == 0 || check ^ data != key {
+            return None;
+        }
+        Some(unpack(data).0)
+    }
+
+    pub fn store(&self, key: u64, entry: TtEntry) {
+        let slot = self.slot(key);
+        let generation = self.generation.load(Ordering::Relaxed);
+        let old_data = slot.data.load(Ordering::Relaxed);
+        let old_check = slot.check.load(Ordering::Relaxed);
+        if old_data != 0 {
+            let (old, old_generation) = unpack(old_data);
+            let same_position = old_check ^ old_data == key;
+            let current = old_generation == generation & 63;
+            // Keep a deeper result for this search unless the new one is exact.
+            if current && old.depth > entry.depth && entry.bound != Bound::Exact {
+                return;
+            }
+            // Don't lose a known best move to a store that has none.
+            if same_position && entry.best_move.is_none() && old.best_move.is_some() {
+                let entry = TtEntry {
+                    best_move: old.best_move,
+                    ..entry
+                };
+                let data = pack(&entry, generation);
+                slot.check.store(key ^ data, Ordering::Relaxed);
+                slot.data.store(data, Ordering::Relaxed);
+                return;
+            }
+        }
+        let data = pack(&entry, generation);
+        slot.check.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::MATE;
+    use crate::types::{PieceKind, Square};
+
+    #[test]
+    fn test_store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        let mv = Move::with_promotion(
+            Square::from_algebraic("e7").unwrap(),
+            Square::from_algebraic("e8").unwrap(),
+            PieceKind::Knight,
+        );
+        let entry = TtEntry {
+            best_move: Some(mv),
+            score: -1234,
+            depth: 7,
+            bound: Bound::Lower,
+        };
+        tt.store(0xDEAD_BEEF, entry);
+        assert_eq!(tt.probe(0xDEAD_BEEF), Some(entry));
+        assert_eq!(tt.probe(0xDEAD_BEEF + tt.len() as u64), None);
+
+        assert!(tt.hashfull() <= 1);
+        tt.clear();
+        assert_eq!(tt.probe(0xDEAD_BEEF), None);
+        assert_eq!(tt.hashfull(), 0);
+    }
+
+    #[test]
+    fn test_entry_count_for_large_and_small_sizes() {
+        assert_eq!(entry_count(1), 1 << 16);
+        assert_eq!(entry_count(64), 1 << 22);
+        assert_eq!(entry_count(4096), 1 << 28);
+        assert_eq!(entry_count(100), 1 << 22);
+        assert_eq!(entry_count(0), 1);
+        assert!(entry_count(usize::MAX).is_power_of_two());
+        assert!(!TranspositionTable::new(1).is_empty());
+    }
+
+    #[test]
+    fn test_mate_scores_are_ply_relative() {
+        let stored = score_to_tt(MATE - 5, 3);
+        assert_eq!(score_from_tt(stored, 3), MATE - 5);
+        assert_eq!(score_from_tt(stored, 1), MATE - 3);
+        assert_eq!(score_to_tt(42, 9), 42);
+    }
+}
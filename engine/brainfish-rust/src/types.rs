@@ -0,0 +1,278 @@
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    pub const ALL: [Color; 2] = [Color::White, Color::Black];
+
+    pub fn opposite(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PieceKind {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
+impl PieceKind {
+    pub const ALL: [PieceKind; 6] = [
+        PieceKind::Pawn,
+        PieceKind::Knight,
+        PieceKind::Bishop,
+        PieceKind::Rook,
+        PieceKind::Queen,
+        PieceKind::King,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Lowercase letter used for this kind in FEN and UCI promotion suffixes.
+    pub fn to_char(self) -> char {
+        match self {
+            PieceKind::Pawn => 'p',
+            PieceKind::Knight => 'n',
+            PieceKind::Bishop => 'b',
+            PieceKind::Rook => 'r',
+            PieceKind::Queen => 'q',
+            PieceKind::King => 'k',
+        }
+    }
+
+    /// Uppercase letter used in SAN and tablebase file names.
+    pub fn to_san_char(self) -> char {
+        self.to_char().to_ascii_uppercase()
+    }
+
+    pub fn from_char(c: char) -> Option<PieceKind> {
+        match c.to_ascii_lowercase() {
+            'p' => Some(PieceKind::Pawn),
+            'n' => Some(PieceKind::Knight),
+            'b' => Some(PieceKind::Bishop),
+            'r' => Some(PieceKind::Rook),
+            'q' => Some(PieceKind::Queen),
+            'k' => Some(PieceKind::King),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Piece {
+    color: Color,
+    kind: PieceKind,
+}
+
+impl Piece {
+    pub fn new(color: Color, kind: PieceKind) -> Self {
+        Self { color, kind }
+    }
+
+    pub fn color(self) -> Color {
+        self.color
+    }
+
+    pub fn kind(self) -> PieceKind {
+        self.kind
+    }
+
+    /// Index 0..12: White's pieces in `PieceKind` order, then Black's.
+    pub fn index(self) -> usize {
+        self.color.index() * 6 + self.kind.index()
+    }
+
+    /// FEN letter for the piece: uppercase for White, lowercase for Black.
+    pub fn to_fen_char(self) -> char {
+        match self.color {
+            Color::White => self.kind.to_san_char(),
+            Color::Black => self.kind.to_char(),
+        }
+    }
+
+    pub fn from_fen_char(c: char) -> Option<Piece> {
+        let kind = PieceKind::from_char(c)?;
+        let color = if c.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        Some(Piece::new(color, kind))
+    }
+}
+
+/// A board square indexed 0..64 with a1 = 0, b1 = 1, ..., h8 = 63.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Square(u8);
+
+impl Square {
+    pub fn new(index: u8) -> Option<Square> {
+        (index < 64).then_some(Square(index))
+    }
+
+    pub fn from_coords(file: u8, rank: u8) -> Option<Square> {
+        (file < 8 && rank < 8).then(|| Square(rank * 8 + file))
+    }
+
+    pub(crate) fn from_index_unchecked(index: usize) -> Square {
+        debug_assert!(index < 64);
+        Square(index as u8)
+    }
+
+    pub fn from_algebraic(s: &str) -> Option<Square> {
+        let bytes = s.as_bytes();
+        if bytes.len() != 2 {
+            return None;
+        }
+        let file = bytes[0].wrapping_sub(b'a');
+        let rank = bytes[1].wrapping_sub(b'1');
+        Square::from_coords(file, rank)
+    }
+
+    pub fn to_algebraic(self) -> String {
+        self.to_string()
+    }
+
+    /// File letter, `a` to `h`.
+    pub fn file_char(self) -> char {
+        (b'a' + self.file()) as char
+    }
+
+    /// Rank digit, `1` to `8`.
+    pub fn rank_char(self) -> char {
+        (b'1' + self.rank()) as char
+    }
+
+    /// The same square seen from the other side of the board (e2 <-> e7).
+    pub fn flip(self) -> Square {
+        Square(self.0 ^ 56)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn file(self) -> u8 {
+        self.0 % 8
+    }
+
+    pub fn rank(self) -> u8 {
+        self.0 / 8
+    }
+
+    pub fn bitboard(self) -> u64 {
+        1u64 << self.0
+    }
+
+    /// King moves from `self` to `other`.
+    pub fn distance(self, other: Square) -> u8 {
+        self.file().abs_diff(other.file()).max(self.rank().abs_diff(other.rank()))
+    }
+
+    /// The square one rank further from `color`'s back rank, if any.
+    pub fn forward(self, color: Color) -> Option<Square> {
+        match color {
+            Color::White => Square::new(self.0 + 8),
+            Color::Black => self.0.checked_sub(8).map(Square),
+        }
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.file_char(), self.rank_char())
+    }
+}
+
+impl TryFrom<u8> for Square {
+    type Error = u8;
+
+    /// Fails with the index itself when it is 64 or more.
+    fn try_from(index: u8) -> Result<Square, u8> {
+        Square::new(index).ok_or(index)
+    }
+}
+
+impl From<Square> for usize {
+    fn from(sq: Square) -> usize {
+        sq.index()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_piece_fen_chars_round_trip() {
+        for c in "PNBRQKpnbrqk".chars() {
+            let piece = Piece::from_fen_char(c).unwrap();
+            assert_eq!(piece.to_fen_char(), c);
+            let color = if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+            assert_eq!(piece.color(), color);
+        }
+        let white_king = Piece::new(Color::White, PieceKind::King);
+        let black_king = Piece::new(Color::Black, PieceKind::King);
+        assert_eq!(white_king.to_fen_char(), 'K');
+        assert_eq!(black_king.to_fen_char(), 'k');
+        assert_eq!(Piece::from_fen_char('n').unwrap().kind(), PieceKind::Knight);
+        for c in ['x', 'X', '1', ' '] {
+            assert_eq!(Piece::from_fen_char(c), None);
+        }
+    }
+
+    #[test]
+    fn test_piece_indices_are_distinct() {
+        let mut seen = [false; 12];
+        for color in Color::ALL {
+            for kind in PieceKind::ALL {
+                let index = Piece::new(color, kind).index();
+                assert!(!seen[index]);
+                seen[index] = true;
+            }
+        }
+    }
+
+    #[test]
+    fn test_all_squares_round_trip() {
+        for index in 0..64u8 {
+            let sq = Square::try_from(index).unwrap();
+            let name = sq.to_algebraic();
+            assert_eq!(Square::from_algebraic(&name), Some(sq));
+            assert_eq!(Square::from_coords(sq.file(), sq.rank()), Some(sq));
+            assert_eq!(usize::from(sq), index as usize);
+            assert_eq!(sq.flip().flip(), sq);
+        }
+        assert_eq!(Square::from_algebraic("a1").unwrap().index(), 0);
+        assert_eq!(Square::from_algebraic("h8").unwrap().index(), 63);
+        assert_eq!(Square::from_algebraic("e2").unwrap().flip().to_algebraic(), "e7");
+    }
+
+    #[test]
+    fn test_invalid_squares() {
+        for name in ["i9", "i1", "a9", "a0", "", "e", "e44", "E4", "4e"] {
+            assert_eq!(Square::from_algebraic(name), None, "{}", name);
+        }
+        assert_eq!(Square::try_from(64), Err(64));
+        assert_eq!(Square::from_coords(8, 0), None);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::search::is_mate_score;
+
+// Default model parameters, fitted to this engine's handcrafted evaluation.
+pub const WDL_A: f64 = 220.0;
+pub const WDL_B: f64 = 70.0;
+
+/// Logistic model: P(win) = 1 / (1 + exp((a - cp) / b)). `a` is the score
+/// at which a win becomes as likely as not, `b` how quickly that probability
+/// changes with the score. Evaluations on another scale need other values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WdlModel {
+    pub a: f64,
+    pub b: f64,
+}
+
+impl Default for WdlModel {
+    fn default() -> Self {
+        Self { a: WDL_A, b: WDL_B }
+    }
+}
+
+impl WdlModel {
+    fn win_permille(&self, cp: i32) -> u32 {
+        let probability = 1.0 / (1.0 + ((self.a - cp as f64) / self.b).exp());
+        (probability * 1000.0).round() as u32
+    }
+
+    /// Win/draw/loss expectation in per-mille for the side the score is
+    /// relative to. The three values always sum to 1000.
+    pub fn wdl(&self, score: i32) -> (u32, u32, u32) {
+        if is_mate_score(score) {
+            return if score > 0 { (1000, 0, 0) } else { (0, 0, 1000) };
+        }
+        let win = self.win_permille(score);
+        let loss = self.win_permille(-score);
+        // With `a` below zero both can pass 500; the draw share can't go negative.
+        let loss = loss.min(1000 - win);
+        (win, 1000 - win - loss, loss)
+    }
+}
+
+/// `WdlModel::wdl` with the default parameters.
+pub fn wdl_from_score(score: i32) -> (u32, u32, u32) {
+    WdlModel::default().wdl(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::MATE;
+
+    #[test]
+    fn test_wdl_sums_to_1000() {
+        for score in [-3000, -450, -100, -1, 0, 1, 37, 100, 450, 3000] {
+            let (w, d, l) = wdl_from_score(score);
+            assert_eq!(w + d + l, 1000, "score {}", score);
+        }
+    }
+
+    #[test]
+    fn test_wdl_tracks_score() {
+        let (w, _, l) = wdl_from_score(0);
+        assert_eq!(w, l);
+        let (w, _, l) = wdl_from_score(800);
+        assert!(w > 950);
+        assert!(l < 5);
+        assert_eq!(wdl_from_score(MATE - 3), (1000, 0, 0));
+        assert_eq!(wdl_from_score(-MATE + 4), (0, 0, 1000));
+    }
+
+    #[test]
+    fn test_model_parameters_shift_the_split() {
+        let default = WdlModel::default().wdl(150);
+        // A higher `a` asks for more of an edge before a win is likely.
+        let cautious = WdlModel { a: 400.0, ..Default::default() }.wdl(150);
+        assert!(cautious.0 < default.0 && cautious.1 > default.1, "{:?}", cautious);
+        // A wider `b` spreads the curve, so the same score wins less surely.
+        let flat = WdlModel { b: 200.0, ..Default::default() }.wdl(600);
+        assert!(flat.0 < WdlModel::default().wdl(600).0);
+        let odd = WdlModel { a: -50.0, b: 70.0 }.wdl(0);
+        assert_eq!(odd.0 + odd.1 + odd.2, 1000);
+    }
+}
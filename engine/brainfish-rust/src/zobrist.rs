@@ -0,0 +1,61 @@
+use crate::rng::{self, Rng};
+use crate::types::{Color, Piece, Square};
+use std::sync::OnceLock;
+
+pub struct ZobristKeys {
+    pieces: [[u64; 64]; 12],
+    castling: [u64; 16],
+    en_passant_file: [u64; 8],
+    black_to_move: u64,
+}
+
+impl ZobristKeys {
+    // Every board in the process shares these keys, so they always come from
+    // the default seed rather than the `Seed` option.
+    fn generate() -> Self {
+        let mut rng = Rng::new(rng::DEFAULT_SEED);
+        let mut keys = ZobristKeys {
+            pieces: [[0; 64]; 12],
+            castling: [0; 16],
+            en_passant_file: [0; 8],
+            black_to_move: 0,
+        };
+        for table in keys.pieces.iter_mut() {
+            for key in table.iter_mut() {
+                *key = rng.next_u64();
+            }
+        }
+        for key in keys.castling.iter_mut() {
+            *key = rng.next_u64();
+        }
+        for key in keys.en_passant_file.iter_mut() {
+            *key = rng.next_u64();
+        }
+        keys.black_to_move = rng.next_u64();
+        keys
+    }
+
+    pub fn piece(&self, piece: Piece, sq: Square) -> u64 {
+        self.pieces[piece.index()][sq.index()]
+    }
+
+    pub fn castling(&self, bits: u8) -> u64 {
+        self.castling[bits as usize & 15]
+    }
+
+    pub fn en_passant(&self, sq: Square) -> u64 {
+        self.en_passant_file[sq.file() as usize]
+    }
+
+    pub fn side(&self, color: Color) -> u64 {
+        match color {
+            Color::White => 0,
+            Color::Black => self.black_to_move,
+        }
+    }
+}
+
+pub fn keys() -> &'static ZobristKeys {
+    static KEYS: OnceLock<ZobristKeys> = OnceLock::new();
+    KEYS.get_or_init(ZobristKeys::generate)
+}